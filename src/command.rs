@@ -1,16 +1,45 @@
+use std::{
+	borrow::Cow,
+	collections::BTreeMap,
+	net::IpAddr,
+	path::PathBuf,
+	str::FromStr,
+	time::{Duration, SystemTime},
+};
+
 use half::f16;
+use internment::Intern;
+use tokio::task::JoinHandle;
 
 use crate::{
+	error::GeneralError,
+	level::{
+		block::{BLOCK_INFO, BLOCK_STRING_ID_MAP},
+		classic_world::CW_EXTENSION,
+		generation::LevelGeneration,
+		EnvColorType, Level, DAY_LENGTH_TICKS,
+	},
+	locale::{self, tr, Translatable},
 	packet::{
 		server::{ServerPacket, TeleportBehavior},
 		ExtBitmask, STRING_LENGTH,
 	},
-	player::PlayerType,
+	player::{PlayerSpawn, PlayerType, SavablePlayerData},
+	selection::{Cuboid, PendingSelection, SelectionAction, SELECTION_PREVIEW_ID},
 	server::{
-		config::{ConfigCoordinatesWithOrientation, ServerProtectionMode},
-		network::set_player_inventory,
-		ServerData,
+		config::{
+			BanEntry, ConfigCoordinatesWithOrientation, ConfigSpawn, ServerConfig,
+			ServerProtectionMode,
+		},
+		network::{
+			build_level_packets, level_env_packets, player_model_packet, player_spawn_packet,
+			server_identification_packet, set_player_block_permissions, set_player_inventory,
+			set_player_rank_defaults,
+		},
+		password::hash_password,
+		AuditEvent, ServerData, LEVELS_PATH,
 	},
+	util::{format_duration, parse_duration, truncate_to_string_length},
 };
 
 const CMD_ME: &str = "me";
@@ -26,26 +55,375 @@ const CMD_SETLEVELSPAWN: &str = "setlevelspawn";
 const CMD_WEATHER: &str = "weather";
 const CMD_SAVE: &str = "save";
 const CMD_TELEPORT: &str = "tp";
+const CMD_LANG: &str = "lang";
+const CMD_HOLLOW: &str = "hollow";
+const CMD_COUNT_SELECTION: &str = "count-selection";
+const CMD_LEVELRULE: &str = "levelrule";
+const CMD_GOTO: &str = "goto";
+const CMD_TELL: &str = "tell";
+const CMD_PLAYERS: &str = "players";
+const CMD_LIST: &str = "list";
+const CMD_BANIP: &str = "banip";
+const CMD_EXPORT: &str = "export";
+const CMD_UNDO: &str = "undo";
+const CMD_CUBOID: &str = "cuboid";
+const CMD_FILL: &str = "fill";
+const CMD_ENVCOLOR: &str = "envcolor";
+const CMD_MODEL: &str = "model";
+const CMD_CLEAR: &str = "clear";
+const CMD_TIME: &str = "time";
+const CMD_ADDSPAWN: &str = "addspawn";
+const CMD_CLEARSPAWNS: &str = "clearspawns";
+const CMD_SETSPAWN: &str = "setspawn";
+const CMD_SPAWN: &str = "spawn";
+const CMD_RESETSPAWN: &str = "resetspawn";
+const CMD_BACK: &str = "back";
+const CMD_LEVELINFO: &str = "levelinfo";
+const CMD_MEASURE: &str = "measure";
+const CMD_WHOIS: &str = "whois";
+const CMD_PHYSICS: &str = "physics";
+const CMD_INVENTORY: &str = "inventory";
+const CMD_PROTECT: &str = "protect";
+const CMD_WORLDS: &str = "worlds";
+const CMD_NEWWORLD: &str = "newworld";
+const CMD_RULES: &str = "rules";
+const CMD_SPAWNPOINT: &str = "spawnpoint";
+const CMD_PROMOTE: &str = "promote";
+const CMD_DEMOTE: &str = "demote";
+const CMD_TOP: &str = "top";
+const CMD_GENERATE: &str = "generate";
+
+/// the argument to /clear which clears the chat instead of a player's data
+const CLEAR_CHAT: &str = "chat";
+/// how many blank lines are sent to push old messages out of the chat
+const CLEAR_CHAT_LINES: usize = 30;
+/// the most blocks along each side /generate previews, so previewing a huge level can't take too long
+const MAX_PREVIEW_SIDE: usize = 128;
 
 const USERNAME_SELF: &str = "@s";
 
-/// list of commands available on the server
-pub const COMMANDS_LIST: &[&str] = &[
-	CMD_ME,
-	CMD_SAY,
-	CMD_SETPERM,
-	CMD_KICK,
-	CMD_STOP,
-	CMD_HELP,
-	CMD_BAN,
-	CMD_ALLOWENTRY,
-	CMD_SETPASS,
-	CMD_SETLEVELSPAWN,
-	CMD_WEATHER,
-	CMD_SAVE,
-	CMD_TELEPORT,
+/// information about every command available on the server, in the order they're listed
+pub const COMMANDS: &[CommandInfo] = &[
+	CommandInfo::new(
+		CMD_ME,
+		PlayerType::Normal,
+		&[ArgumentInfo::required("action")],
+	),
+	CommandInfo::new(
+		CMD_SAY,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("message")],
+	),
+	CommandInfo::new(
+		CMD_SETPERM,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::required("permission level"),
+		],
+	),
+	CommandInfo::new(
+		CMD_KICK,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::optional("reason"),
+		],
+	),
+	CommandInfo::new(CMD_STOP, PlayerType::Operator, &[]),
+	CommandInfo::new(
+		CMD_HELP,
+		PlayerType::Normal,
+		&[ArgumentInfo::optional("command")],
+	),
+	CommandInfo::new(
+		CMD_BAN,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::optional("duration"),
+			ArgumentInfo::optional("reason"),
+		],
+	),
+	CommandInfo::new(
+		CMD_ALLOWENTRY,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("username")],
+	),
+	CommandInfo::new(
+		CMD_SETPASS,
+		PlayerType::Normal,
+		&[ArgumentInfo::required("new password")],
+	),
+	CommandInfo::new(CMD_SETLEVELSPAWN, PlayerType::Moderator, &[]),
+	CommandInfo::new(
+		CMD_WEATHER,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("weather type")],
+	),
+	CommandInfo::new(
+		CMD_SAVE,
+		PlayerType::Moderator,
+		&[ArgumentInfo::optional("all")],
+	),
+	CommandInfo::new(
+		CMD_TELEPORT,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::required("username or x y z"),
+		],
+	),
+	CommandInfo::new(
+		CMD_LANG,
+		PlayerType::Normal,
+		&[ArgumentInfo::required("language code")],
+	),
+	CommandInfo::new(
+		CMD_HOLLOW,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("block")],
+	),
+	CommandInfo::new(CMD_COUNT_SELECTION, PlayerType::Moderator, &[]),
+	CommandInfo::new(
+		CMD_LEVELRULE,
+		PlayerType::Operator,
+		&[
+			ArgumentInfo::required("rule or all"),
+			ArgumentInfo::optional("value"),
+		],
+	),
+	CommandInfo::new(
+		CMD_GOTO,
+		PlayerType::Normal,
+		&[ArgumentInfo::required("level")],
+	),
+	CommandInfo::new(
+		CMD_TELL,
+		PlayerType::Normal,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::required("message"),
+		],
+	),
+	CommandInfo::new(CMD_PLAYERS, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_BANIP,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("username or address")],
+	),
+	CommandInfo::new(
+		CMD_EXPORT,
+		PlayerType::Operator,
+		&[ArgumentInfo::required("filename")],
+	),
+	CommandInfo::new(
+		CMD_UNDO,
+		PlayerType::Normal,
+		&[
+			ArgumentInfo::optional("username"),
+			ArgumentInfo::optional("count"),
+		],
+	),
+	CommandInfo::new(
+		CMD_CUBOID,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("block")],
+	),
+	CommandInfo::new(
+		CMD_FILL,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("x1"),
+			ArgumentInfo::required("y1"),
+			ArgumentInfo::required("z1"),
+			ArgumentInfo::required("x2"),
+			ArgumentInfo::required("y2"),
+			ArgumentInfo::required("z2"),
+			ArgumentInfo::required("block"),
+		],
+	),
+	CommandInfo::new(
+		CMD_ENVCOLOR,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("sky, cloud, fog, sunlight or shadow"),
+			ArgumentInfo::required("hex color or reset"),
+		],
+	),
+	CommandInfo::new(
+		CMD_MODEL,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::required("model"),
+		],
+	),
+	CommandInfo::new(
+		CMD_CLEAR,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("chat or username")],
+	),
+	CommandInfo::new(
+		CMD_TIME,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::optional("set or add"),
+			ArgumentInfo::optional("ticks, day or night"),
+		],
+	),
+	CommandInfo::new(CMD_ADDSPAWN, PlayerType::Moderator, &[]),
+	CommandInfo::new(CMD_CLEARSPAWNS, PlayerType::Moderator, &[]),
+	CommandInfo::new(CMD_SETSPAWN, PlayerType::Normal, &[]),
+	CommandInfo::new(CMD_SPAWN, PlayerType::Normal, &[]),
+	CommandInfo::new(CMD_RESETSPAWN, PlayerType::Normal, &[]),
+	CommandInfo::new(CMD_BACK, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_LEVELINFO,
+		PlayerType::Operator,
+		&[
+			ArgumentInfo::required("name or motd"),
+			ArgumentInfo::optional("value"),
+		],
+	),
+	CommandInfo::new(CMD_MEASURE, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_WHOIS,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("username")],
+	),
+	CommandInfo::new(
+		CMD_PHYSICS,
+		PlayerType::Operator,
+		&[ArgumentInfo::optional("on or off")],
+	),
+	CommandInfo::new(
+		CMD_INVENTORY,
+		PlayerType::Operator,
+		&[ArgumentInfo::optional("reset or blocks...")],
+	),
+	CommandInfo::new(
+		CMD_PROTECT,
+		PlayerType::Operator,
+		&[ArgumentInfo::required("permission level")],
+	),
+	CommandInfo::new(CMD_WORLDS, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_NEWWORLD,
+		PlayerType::Operator,
+		&[
+			ArgumentInfo::required("name"),
+			ArgumentInfo::required("x"),
+			ArgumentInfo::required("y"),
+			ArgumentInfo::required("z"),
+			ArgumentInfo::required("empty, flat or random"),
+			ArgumentInfo::optional("seed"),
+		],
+	),
+	CommandInfo::new(CMD_RULES, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_SPAWNPOINT,
+		PlayerType::Moderator,
+		&[
+			ArgumentInfo::required("username"),
+			ArgumentInfo::required("x"),
+			ArgumentInfo::required("y"),
+			ArgumentInfo::required("z"),
+		],
+	),
+	CommandInfo::new(
+		CMD_PROMOTE,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("username")],
+	),
+	CommandInfo::new(
+		CMD_DEMOTE,
+		PlayerType::Moderator,
+		&[ArgumentInfo::required("username")],
+	),
+	CommandInfo::new(CMD_TOP, PlayerType::Normal, &[]),
+	CommandInfo::new(
+		CMD_GENERATE,
+		PlayerType::Operator,
+		&[
+			ArgumentInfo::required("seed or preview"),
+			ArgumentInfo::optional("seed"),
+			ArgumentInfo::optional("generator"),
+		],
+	),
 ];
 
+/// information about a command, for listing commands and showing help about them
+#[derive(Debug, Clone, Copy)]
+pub struct CommandInfo {
+	/// the command's name
+	pub name: &'static str,
+	/// the permissions required to run the command, unless configured otherwise
+	pub permissions: PlayerType,
+	/// the arguments the command takes
+	pub arguments: &'static [ArgumentInfo],
+}
+
+impl CommandInfo {
+	/// creates new information about a command
+	const fn new(
+		name: &'static str,
+		permissions: PlayerType,
+		arguments: &'static [ArgumentInfo],
+	) -> Self {
+		Self {
+			name,
+			permissions,
+			arguments,
+		}
+	}
+
+	/// gets the command's usage, such as `/kick <username> [reason]`
+	pub fn usage(&self) -> String {
+		let mut usage = format!("{}{}", Command::PREFIX, self.name);
+		for argument in self.arguments {
+			if argument.optional {
+				usage.push_str(&format!(" [{}]", argument.name));
+			} else {
+				usage.push_str(&format!(" <{}>", argument.name));
+			}
+		}
+		usage
+	}
+
+	/// gets the command's description in the given locale
+	pub fn description(&self, locale: &str) -> String {
+		locale::translate(locale, &format!("help.{}", self.name), &[])
+	}
+}
+
+/// information about an argument to a command
+#[derive(Debug, Clone, Copy)]
+pub struct ArgumentInfo {
+	/// the argument's name, describing what it takes
+	pub name: &'static str,
+	/// whether the argument can be left out
+	pub optional: bool,
+}
+
+impl ArgumentInfo {
+	/// creates information about an argument which must be given
+	const fn required(name: &'static str) -> Self {
+		Self {
+			name,
+			optional: false,
+		}
+	}
+
+	/// creates information about an argument which can be left out
+	const fn optional(name: &'static str) -> Self {
+		Self {
+			name,
+			optional: true,
+		}
+	}
+}
+
 /// enum for possible commands
 #[derive(Debug, Clone)]
 pub enum Command<'m> {
@@ -57,26 +435,27 @@ pub enum Command<'m> {
 	Say { message: &'m str },
 	/// sets permissions for a player
 	SetPermissions {
-		player_username: &'m str,
+		player_username: Cow<'m, str>,
 		permissions: PlayerType,
 	},
 	/// kicks a player from the server
 	Kick {
-		username: &'m str,
+		username: Cow<'m, str>,
 		message: Option<&'m str>,
 	},
 	/// command to stop the server
 	Stop,
 	/// gets help about the given command, or about all commands if no command is given
 	Help { command: Option<&'m str> },
-	/// bans a player from the server
+	/// bans a player from the server, optionally only for a while
 	Ban {
-		player_username: &'m str,
+		player_username: Cow<'m, str>,
+		duration: Option<Duration>,
 		message: Option<&'m str>,
 	},
 	/// allows a player entry into the server
 	AllowEntry {
-		player_username: &'m str,
+		player_username: Cow<'m, str>,
 		password: Option<&'m str>,
 	},
 	/// sets the current player's password
@@ -85,28 +464,401 @@ pub enum Command<'m> {
 	SetLevelSpawn,
 	/// changes the levels weather
 	Weather { weather_type: &'m str },
-	/// saves the current level
-	Save,
+	/// saves the current level, or every loaded level
+	Save { all: bool },
 	/// teleports a player to the given coordinates or player
 	Teleport {
-		username: &'m str,
+		username: Cow<'m, str>,
 		mode: TeleportMode<'m>,
 	},
+	/// sets the language server messages are sent to the player in
+	Lang { locale: Cow<'m, str> },
+	/// fills the outside of a selected cuboid with a block, leaving air inside
+	Hollow { block: u16 },
+	/// reports how many blocks a selected cuboid would affect
+	CountSelection,
+	/// gets or sets a level rule
+	LevelRule {
+		rule: Cow<'m, str>,
+		value: Option<&'m str>,
+	},
+	/// moves the player to another loaded level
+	Goto { level_name: Cow<'m, str> },
+	/// sends a private message to another player
+	Tell {
+		username: Cow<'m, str>,
+		message: &'m str,
+	},
+	/// lists the players connected to the server
+	Players,
+	/// bans an address, either given directly or taken from a connected player
+	BanIp { target: Cow<'m, str> },
+	/// exports the current level to a ClassicWorld file
+	Export { filename: Cow<'m, str> },
+	/// reverts a player's most recent block edits
+	Undo {
+		username: Option<Cow<'m, str>>,
+		count: usize,
+	},
+	/// fills a selected cuboid with a block
+	Cuboid { block: u16 },
+	/// fills the cuboid between two given corners with a block
+	Fill {
+		x1: i32,
+		y1: i32,
+		z1: i32,
+		x2: i32,
+		y2: i32,
+		z2: i32,
+		block: u16,
+	},
+	/// sets or resets one of the level's environment colors
+	EnvColor {
+		target: Cow<'m, str>,
+		color: Cow<'m, str>,
+	},
+	/// sets the model a player is shown with
+	Model {
+		username: Cow<'m, str>,
+		model: Cow<'m, str>,
+	},
+	/// clears the chat or a player's saved positions in each level
+	Clear { mode: ClearMode<'m> },
+	/// gets or changes the level's time of day
+	Time { mode: TimeMode },
+	/// adds the player's location to the main level's spawn points
+	AddSpawn,
+	/// removes all of the main level's spawn points
+	ClearSpawns,
+	/// sets the player's personal spawn to their location
+	SetSpawn,
+	/// teleports the player to their personal spawn or the level's spawn
+	Spawn,
+	/// removes the player's personal spawn
+	ResetSpawn,
+	/// teleports the player back to where they were before their last teleport
+	Back,
+	/// sets or clears the current level's own name or motd
+	LevelInfo {
+		field: Cow<'m, str>,
+		value: Option<&'m str>,
+	},
+	/// reports the dimensions of a selected cuboid
+	Measure,
+	/// reports details about a player
+	Whois { username: Cow<'m, str> },
+	/// pauses or resumes the level's physics, or reports whether they're running
+	Physics { enabled: Option<bool> },
+	/// sets, resets or reports the level's custom inventory order
+	Inventory { mode: InventoryMode },
+	/// protects a selected cuboid so only players of at least the given rank may edit it
+	Protect { permissions: PlayerType },
+	/// lists the loaded levels
+	Worlds,
+	/// generates a new level and loads it
+	NewWorld {
+		name: Cow<'m, str>,
+		x_size: usize,
+		y_size: usize,
+		z_size: usize,
+		generation: LevelGeneration,
+		seed: Option<u64>,
+	},
+	/// shows the server's rules
+	Rules,
+	/// sets where a player respawns without teleporting them
+	SpawnPoint {
+		username: Cow<'m, str>,
+		x: f32,
+		y: f32,
+		z: f32,
+	},
+	/// raises a player's permissions by one rank
+	Promote { username: Cow<'m, str> },
+	/// lowers a player's permissions by one rank
+	Demote { username: Cow<'m, str> },
+	/// teleports the player up to the highest block above them
+	Top,
+	/// shows the current level's seed or previews generating a level without keeping it
+	Generate { mode: GenerateMode<'m> },
+}
+
+#[derive(Debug, Clone)]
+pub enum ClearMode<'m> {
+	Chat,
+	Player(Cow<'m, str>),
+}
+
+#[derive(Debug, Clone)]
+pub enum GenerateMode<'m> {
+	Seed,
+	Preview {
+		seed: u64,
+		generator: Option<Cow<'m, str>>,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub enum TimeMode {
+	Query,
+	Set(u32),
+	Add(u32),
+}
+
+#[derive(Debug, Clone)]
+pub enum InventoryMode {
+	Query,
+	Reset,
+	Set(Vec<u16>),
 }
 
 #[derive(Debug, Clone)]
 pub enum TeleportMode<'m> {
-	Coordinates { x: f32, y: f32, z: f32 },
+	Coordinates {
+		x: Coordinate,
+		y: Coordinate,
+		z: Coordinate,
+	},
 	Player(&'m str),
 }
 
+/// a coordinate given to a command, either exact or written with `~` as an offset from a player's position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coordinate {
+	Absolute(f32),
+	Relative(f32),
+}
+
+impl Coordinate {
+	/// gets the coordinate's value, offsetting relative coordinates from the given one
+	fn resolve(self, current: f32) -> f32 {
+		match self {
+			Self::Absolute(value) => value,
+			Self::Relative(offset) => current + offset,
+		}
+	}
+}
+
+/// work started by a command which is done on a blocking thread, finished by the tick loop once it's done
+#[derive(Debug)]
+pub enum PendingCommand {
+	/// a player moving to another level, once the level's packets are built from a snapshot of it
+	Goto {
+		username: String,
+		level_name: String,
+		task: JoinHandle<(Level, Result<Vec<ServerPacket>, GeneralError>)>,
+	},
+	/// a new level being generated, which is added once it's done
+	NewWorld {
+		username: String,
+		level_name: String,
+		seed: u64,
+		task: JoinHandle<Level>,
+	},
+	/// a seed being previewed, whose block counts are sent once it's generated
+	Preview {
+		username: String,
+		seed: u64,
+		size: (usize, usize, usize),
+		task: JoinHandle<BTreeMap<u16, usize>>,
+	},
+	/// a player being allowed into the server, whose new password is added once it's hashed
+	AllowEntry {
+		username: String,
+		player_username: String,
+		password: String,
+		was_banned: bool,
+		task: JoinHandle<String>,
+	},
+	/// a player's new password being hashed, which replaces their old one once it's done
+	SetPass {
+		username: String,
+		task: JoinHandle<String>,
+	},
+}
+
+impl PendingCommand {
+	/// whether the command's work is done, so it can be finished without waiting
+	pub fn is_finished(&self) -> bool {
+		match self {
+			Self::Goto { task, .. } => task.is_finished(),
+			Self::NewWorld { task, .. } => task.is_finished(),
+			Self::Preview { task, .. } => task.is_finished(),
+			Self::AllowEntry { task, .. } => task.is_finished(),
+			Self::SetPass { task, .. } => task.is_finished(),
+		}
+	}
+
+	/// finishes the command with the result of its work
+	pub async fn finish(self, data: &mut ServerData) {
+		match self {
+			Self::Goto {
+				username,
+				level_name,
+				task,
+			} => {
+				let result = task
+					.await
+					.map_err(GeneralError::from)
+					.and_then(|(snapshot, packets)| Ok((snapshot, packets?)));
+				finish_goto(data, &username, &level_name, result);
+			}
+			Self::NewWorld {
+				username,
+				level_name,
+				seed,
+				task,
+			} => {
+				match task.await {
+					// another level may have been loaded with the same name in the meantime
+					Ok(_) if data.levels.contains_key(&level_name) => {
+						send_messages(data, &username, |locale| {
+							vec![tr!(locale, "command.newworld.exists", level_name)]
+						});
+					}
+					Ok(mut level) => {
+						// new levels are written to the levels directory by the next save check
+						level.save_now = true;
+						data.levels.insert(level_name.clone(), level);
+						send_messages(data, &username, |locale| {
+							vec![
+								tr!(locale, "command.newworld.success", level_name),
+								tr!(locale, "command.generate.seed", seed),
+							]
+						});
+					}
+					Err(e) => {
+						eprintln!("failed to generate level {level_name}: {e}");
+						send_messages(data, &username, |locale| {
+							vec![tr!(locale, "command.newworld.failed", level_name)]
+						});
+					}
+				}
+			}
+			Self::Preview {
+				username,
+				seed,
+				size: (x_size, y_size, z_size),
+				task,
+			} => match task.await {
+				Ok(counts) => {
+					let mut counts: Vec<_> = counts.into_iter().collect();
+					counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+					let counts: Vec<_> = counts
+						.into_iter()
+						.map(|(id, count)| {
+							let name = BLOCK_INFO
+								.get(&id)
+								.map(|info| info.str_id.to_string())
+								.unwrap_or_else(|| id.to_string());
+							format!("{name}: {count}")
+						})
+						.collect();
+					send_messages(data, &username, |locale| {
+						let mut messages = vec![tr!(
+							locale,
+							"command.generate.preview",
+							seed,
+							x_size,
+							y_size,
+							z_size
+						)];
+						messages.extend(wrap_list("&7", counts.iter().map(String::as_str)));
+						messages
+					});
+				}
+				Err(e) => {
+					eprintln!("failed to preview seed {seed}: {e}");
+					send_messages(data, &username, |locale| {
+						vec![tr!(locale, "command.generate.preview_failed")]
+					});
+				}
+			},
+			Self::AllowEntry {
+				username,
+				player_username,
+				password,
+				was_banned,
+				task,
+			} => {
+				let hash = match task.await {
+					Ok(hash) => hash,
+					Err(e) => {
+						eprintln!("failed to hash password for {player_username}: {e}");
+						send_messages(data, &username, |locale| {
+							vec![tr!(locale, "command.allowentry.failed")]
+						});
+						return;
+					}
+				};
+				// the player may have been given a password in the meantime, which is kept
+				let added = match &mut data.config.protection_mode {
+					ServerProtectionMode::PasswordsByUser(passwords)
+						if !passwords.contains_key(&player_username) =>
+					{
+						passwords.insert(player_username.clone(), hash);
+						true
+					}
+					_ => false,
+				};
+				if added {
+					data.config_needs_saving = true;
+				}
+				send_messages(data, &username, |locale| {
+					if added {
+						vec![
+							tr!(locale, "command.allowentry.success", player_username),
+							tr!(locale, "command.allowentry.password", password),
+						]
+					} else if was_banned {
+						vec![tr!(locale, "command.allowentry.success", player_username)]
+					} else {
+						vec![tr!(locale, "command.allowentry.already")]
+					}
+				});
+			}
+			Self::SetPass { username, task } => {
+				let hash = match task.await {
+					Ok(hash) => hash,
+					Err(e) => {
+						eprintln!("failed to hash password for {username}: {e}");
+						send_messages(data, &username, |locale| {
+							vec![tr!(locale, "command.setpass.failed")]
+						});
+						return;
+					}
+				};
+				let message = if let ServerProtectionMode::PasswordsByUser(passwords) =
+					&mut data.config.protection_mode
+				{
+					passwords.insert(username.clone(), hash);
+					data.config_needs_saving = true;
+					"command.setpass.success"
+				} else {
+					"command.requires_per_user_passwords"
+				};
+				send_messages(data, &username, |locale| vec![tr!(locale, message)]);
+			}
+		}
+	}
+}
+
 impl<'m> Command<'m> {
 	/// the prefix for commands
 	pub const PREFIX: char = '/';
 
 	/// parses a command, returning the parsed command or an error to be displayed to the player who sent the command
-	pub fn parse(input: &'m str) -> Result<Command, String> {
+	///
+	/// command names found in `aliases` are replaced with the command they're an alias for
+	pub fn parse(
+		input: &'m str,
+		aliases: &BTreeMap<String, String>,
+	) -> Result<Command<'m>, Translatable> {
 		let (command_name, mut arguments) = input.split_once(' ').unwrap_or((input, ""));
+		let command_name = aliases
+			.get(command_name)
+			.map(String::as_str)
+			.unwrap_or(command_name);
 		Ok(match command_name {
 			CMD_ME => Self::Me { action: arguments },
 			CMD_SAY => Self::Say { message: arguments },
@@ -115,7 +867,7 @@ impl<'m> Command<'m> {
 				permissions: arguments
 					.trim()
 					.try_into()
-					.map_err(|_| format!("&cUnknown permissions type: {arguments}"))?,
+					.map_err(|_| Translatable::new("command.unknown_permissions").arg(arguments))?,
 			},
 			CMD_KICK => {
 				let username = Self::next_string(&mut arguments)?;
@@ -129,10 +881,12 @@ impl<'m> Command<'m> {
 			},
 			CMD_BAN => {
 				let player_username = Self::next_string(&mut arguments)?;
+				let duration = Self::next_duration(&mut arguments);
 				let message = arguments.trim();
 				let message = (!message.is_empty()).then_some(message);
 				Self::Ban {
 					player_username,
+					duration,
 					message,
 				}
 			}
@@ -152,14 +906,20 @@ impl<'m> Command<'m> {
 			CMD_WEATHER => Self::Weather {
 				weather_type: arguments,
 			},
-			CMD_SAVE => Self::Save,
+			CMD_SAVE => Self::Save {
+				all: match arguments.trim() {
+					"" => false,
+					"all" => true,
+					other => return Err(Translatable::new("command.save.unknown_scope").arg(other)),
+				},
+			},
 			CMD_TELEPORT => {
 				let username = Self::next_string(&mut arguments)?;
-				let mode = if let Ok(x) = Self::next_f32(&mut arguments) {
+				let mode = if let Ok(x) = Self::next_coordinate(&mut arguments) {
 					TeleportMode::Coordinates {
 						x,
-						y: Self::next_f32(&mut arguments)?,
-						z: Self::next_f32(&mut arguments)?,
+						y: Self::next_coordinate(&mut arguments)?,
+						z: Self::next_coordinate(&mut arguments)?,
 					}
 				} else {
 					TeleportMode::Player(arguments)
@@ -167,7 +927,203 @@ impl<'m> Command<'m> {
 
 				Self::Teleport { username, mode }
 			}
-			_ => return Err(format!("Unknown command: {command_name}")),
+			CMD_LANG => Self::Lang {
+				locale: Self::next_string(&mut arguments)?,
+			},
+			CMD_HOLLOW => Self::Hollow {
+				block: Self::next_block(&mut arguments)?,
+			},
+			CMD_COUNT_SELECTION => Self::CountSelection,
+			CMD_LEVELRULE => {
+				let rule = Self::next_string(&mut arguments)?;
+				let value = arguments.trim();
+				let value = (!value.is_empty()).then_some(value);
+				Self::LevelRule { rule, value }
+			}
+			CMD_GOTO => Self::Goto {
+				level_name: Self::next_string(&mut arguments)?,
+			},
+			CMD_TELL => {
+				let username = Self::next_string(&mut arguments)?;
+				let message = arguments.trim();
+				if message.is_empty() {
+					return Err(Translatable::new("command.missing_argument"));
+				}
+				Self::Tell { username, message }
+			}
+			CMD_PLAYERS | CMD_LIST => Self::Players,
+			CMD_BANIP => Self::BanIp {
+				target: Self::next_string(&mut arguments)?,
+			},
+			CMD_EXPORT => Self::Export {
+				filename: Self::next_string(&mut arguments)?,
+			},
+			CMD_UNDO => {
+				let username = if arguments.is_empty() || Self::peek_usize(arguments) {
+					None
+				} else {
+					Some(Self::next_string(&mut arguments)?)
+				};
+				let count = if arguments.is_empty() {
+					1
+				} else {
+					Self::next_number(&mut arguments)?
+				};
+				Self::Undo { username, count }
+			}
+			CMD_CUBOID => Self::Cuboid {
+				block: Self::next_block(&mut arguments)?,
+			},
+			CMD_FILL => Self::Fill {
+				x1: Self::next_number(&mut arguments)?,
+				y1: Self::next_number(&mut arguments)?,
+				z1: Self::next_number(&mut arguments)?,
+				x2: Self::next_number(&mut arguments)?,
+				y2: Self::next_number(&mut arguments)?,
+				z2: Self::next_number(&mut arguments)?,
+				block: Self::next_block(&mut arguments)?,
+			},
+			CMD_ENVCOLOR => Self::EnvColor {
+				target: Self::next_string(&mut arguments)?,
+				color: Self::next_string(&mut arguments)?,
+			},
+			CMD_MODEL => Self::Model {
+				username: Self::next_string(&mut arguments)?,
+				model: Self::next_string(&mut arguments)?,
+			},
+			CMD_CLEAR => {
+				let target = Self::next_string(&mut arguments)?;
+				let mode = if target == CLEAR_CHAT {
+					ClearMode::Chat
+				} else {
+					ClearMode::Player(target)
+				};
+
+				Self::Clear { mode }
+			}
+			CMD_TIME => {
+				let mode = if arguments.is_empty() {
+					TimeMode::Query
+				} else {
+					let action = Self::next_string(&mut arguments)?;
+					match &*action {
+						"set" => TimeMode::Set(match arguments {
+							"day" => 0,
+							"night" => DAY_LENGTH_TICKS / 2,
+							_ => Self::next_number(&mut arguments)?,
+						}),
+						"add" => TimeMode::Add(Self::next_number(&mut arguments)?),
+						_ => {
+							return Err(Translatable::new("command.time.unknown_action").arg(action))
+						}
+					}
+				};
+
+				Self::Time { mode }
+			}
+			CMD_ADDSPAWN => Self::AddSpawn,
+			CMD_CLEARSPAWNS => Self::ClearSpawns,
+			CMD_SETSPAWN => Self::SetSpawn,
+			CMD_SPAWN => Self::Spawn,
+			CMD_RESETSPAWN => Self::ResetSpawn,
+			CMD_BACK => Self::Back,
+			CMD_LEVELINFO => {
+				let field = Self::next_string(&mut arguments)?;
+				let value = arguments.trim();
+				let value = (!value.is_empty()).then_some(value);
+				Self::LevelInfo { field, value }
+			}
+			CMD_MEASURE => Self::Measure,
+			CMD_WHOIS => Self::Whois {
+				username: Self::next_string(&mut arguments)?,
+			},
+			CMD_PHYSICS => Self::Physics {
+				enabled: match arguments.trim() {
+					"" => None,
+					"on" => Some(true),
+					"off" => Some(false),
+					other => {
+						return Err(Translatable::new("command.physics.unknown_state").arg(other))
+					}
+				},
+			},
+			CMD_INVENTORY => Self::Inventory {
+				mode: match arguments.trim() {
+					"" => InventoryMode::Query,
+					"reset" => InventoryMode::Reset,
+					_ => {
+						let mut blocks = Vec::new();
+						while !arguments.trim().is_empty() {
+							blocks.push(Self::next_block(&mut arguments)?);
+						}
+						InventoryMode::Set(blocks)
+					}
+				},
+			},
+			CMD_PROTECT => Self::Protect {
+				permissions: arguments
+					.trim()
+					.try_into()
+					.map_err(|_| Translatable::new("command.unknown_permissions").arg(arguments))?,
+			},
+			CMD_WORLDS => Self::Worlds,
+			CMD_NEWWORLD => {
+				let name = Self::next_string(&mut arguments)?;
+				let x_size = Self::next_number(&mut arguments)?;
+				let y_size = Self::next_number(&mut arguments)?;
+				let z_size = Self::next_number(&mut arguments)?;
+				let generator = Self::next_string(&mut arguments)?;
+				let generation =
+					LevelGeneration::from_name(&generator, y_size).ok_or_else(|| {
+						Translatable::new("command.newworld.unknown_generator").arg(&generator)
+					})?;
+				let seed = if arguments.is_empty() {
+					None
+				} else {
+					Some(Self::next_number(&mut arguments)?)
+				};
+				Self::NewWorld {
+					name,
+					x_size,
+					y_size,
+					z_size,
+					generation,
+					seed,
+				}
+			}
+			CMD_RULES => Self::Rules,
+			CMD_SPAWNPOINT => Self::SpawnPoint {
+				username: Self::next_string(&mut arguments)?,
+				x: Self::next_f32(&mut arguments)?,
+				y: Self::next_f32(&mut arguments)?,
+				z: Self::next_f32(&mut arguments)?,
+			},
+			CMD_PROMOTE => Self::Promote {
+				username: Self::next_string(&mut arguments)?,
+			},
+			CMD_DEMOTE => Self::Demote {
+				username: Self::next_string(&mut arguments)?,
+			},
+			CMD_TOP => Self::Top,
+			CMD_GENERATE => {
+				let action = Self::next_string(&mut arguments)?;
+				let mode = match &*action {
+					"seed" => GenerateMode::Seed,
+					"preview" => GenerateMode::Preview {
+						seed: Self::next_number(&mut arguments)?,
+						generator: if arguments.is_empty() {
+							None
+						} else {
+							Some(Self::next_string(&mut arguments)?)
+						},
+					},
+					_ => {
+						return Err(Translatable::new("command.generate.unknown_action").arg(action))
+					}
+				};
+				Self::Generate { mode }
+			}
+			_ => return Err(Translatable::new("command.unknown").arg(command_name)),
 		})
 	}
 
@@ -185,157 +1141,212 @@ impl<'m> Command<'m> {
 			Self::SetPass { .. } => CMD_SETPASS,
 			Self::SetLevelSpawn => CMD_SETLEVELSPAWN,
 			Self::Weather { .. } => CMD_WEATHER,
-			Self::Save => CMD_SAVE,
+			Self::Save { .. } => CMD_SAVE,
 			Self::Teleport { .. } => CMD_TELEPORT,
+			Self::Lang { .. } => CMD_LANG,
+			Self::Hollow { .. } => CMD_HOLLOW,
+			Self::CountSelection => CMD_COUNT_SELECTION,
+			Self::LevelRule { .. } => CMD_LEVELRULE,
+			Self::Goto { .. } => CMD_GOTO,
+			Self::Tell { .. } => CMD_TELL,
+			Self::Players => CMD_PLAYERS,
+			Self::BanIp { .. } => CMD_BANIP,
+			Self::Export { .. } => CMD_EXPORT,
+			Self::Undo { .. } => CMD_UNDO,
+			Self::Cuboid { .. } => CMD_CUBOID,
+			Self::Fill { .. } => CMD_FILL,
+			Self::EnvColor { .. } => CMD_ENVCOLOR,
+			Self::Model { .. } => CMD_MODEL,
+			Self::Clear { .. } => CMD_CLEAR,
+			Self::Time { .. } => CMD_TIME,
+			Self::AddSpawn => CMD_ADDSPAWN,
+			Self::ClearSpawns => CMD_CLEARSPAWNS,
+			Self::SetSpawn => CMD_SETSPAWN,
+			Self::Spawn => CMD_SPAWN,
+			Self::ResetSpawn => CMD_RESETSPAWN,
+			Self::Back => CMD_BACK,
+			Self::LevelInfo { .. } => CMD_LEVELINFO,
+			Self::Measure => CMD_MEASURE,
+			Self::Whois { .. } => CMD_WHOIS,
+			Self::Physics { .. } => CMD_PHYSICS,
+			Self::Inventory { .. } => CMD_INVENTORY,
+			Self::Protect { .. } => CMD_PROTECT,
+			Self::Worlds => CMD_WORLDS,
+			Self::NewWorld { .. } => CMD_NEWWORLD,
+			Self::Rules => CMD_RULES,
+			Self::SpawnPoint { .. } => CMD_SPAWNPOINT,
+			Self::Promote { .. } => CMD_PROMOTE,
+			Self::Demote { .. } => CMD_DEMOTE,
+			Self::Top => CMD_TOP,
+			Self::Generate { .. } => CMD_GENERATE,
 		}
 	}
 
 	/// checks which permissions are required to run this command
-	pub fn perms_required(&self) -> PlayerType {
-		Self::perms_required_by_name(self.command_name())
-	}
-
-	/// checks which permissions are required to run a command by name
-	pub fn perms_required_by_name(cmd: &str) -> PlayerType {
-		match cmd {
-			CMD_ME => PlayerType::Normal,
-			CMD_STOP => PlayerType::Operator,
-			CMD_HELP => PlayerType::Normal,
-			CMD_SETPASS => PlayerType::Normal,
-			_ => PlayerType::Moderator,
+	pub fn perms_required(&self, config: &ServerConfig) -> PlayerType {
+		Self::perms_required_by_name(self.command_name(), config)
+	}
+
+	/// checks which permissions are required to run a command by name, using the configured permissions if there are any
+	pub fn perms_required_by_name(cmd: &str, config: &ServerConfig) -> PlayerType {
+		if let Some(perms) = config.command_permissions.get(cmd) {
+			return *perms;
 		}
+		Self::info(cmd).map_or(PlayerType::Moderator, |info| info.permissions)
+	}
+
+	/// gets information about every command
+	pub fn metadata() -> &'static [CommandInfo] {
+		COMMANDS
+	}
+
+	/// gets information about a command by name
+	pub fn info(cmd: &str) -> Option<&'static CommandInfo> {
+		COMMANDS.iter().find(|info| info.name == cmd)
 	}
 
 	/// gets help about the given command
-	pub fn help(cmd: &str) -> Vec<String> {
-		let c = |t: &str| format!("&f{}{cmd} {t}", Self::PREFIX);
-
-		match cmd {
-			CMD_ME => vec![
-				c("<action>"),
-				"&fDisplays an action as if you're doing it.".to_string(),
-			],
-			CMD_SAY => vec![
-				c("<message>"),
-				"&fSends a message as being from the server.".to_string(),
-			],
-			CMD_SETPERM => vec![
-				c("<username> <permission level>"),
-				"&fSets a player's permission level.".to_string(),
-			],
-			CMD_KICK => vec![
-				c("<username> [reason]"),
-				"&fKicks a player from the server.".to_string(),
-			],
-			CMD_STOP => vec![
-				c(""),
-				"&fStops the server while saving the level.".to_string(),
-			],
-			CMD_HELP => vec![
-				c("[command]"),
-				"&fGets a list of commands or help about a command.".to_string(),
-			],
-			CMD_BAN => vec![
-				c("<username> [reason]"),
-				"&fBans a player from the server.".to_string(),
-			],
-			CMD_ALLOWENTRY => vec![
-				c("<username>"),
-				"&fAllows a player into the server.".to_string(),
-			],
-			CMD_SETPASS => vec![c("<new password>"), "&fUpdates your password.".to_string()],
-			CMD_SETLEVELSPAWN => vec![
-				c(""),
-				"&fSets the level's spawn to your location.".to_string(),
-			],
-			CMD_WEATHER => vec![
-				c("<weather type>"),
-				"&fSets the level's weather.".to_string(),
-			],
-			CMD_SAVE => vec![c(""), "&fSaves the current level.".to_string()],
-			CMD_TELEPORT => vec![
-				c("(<username> or <x> <y> <z>"),
-				"&fTeleports to the given username or coordinates.".to_string(),
-			],
-			_ => vec!["&eUnknown command!".to_string()],
+	pub fn help(cmd: &str, locale: &str) -> Vec<String> {
+		match Self::info(cmd) {
+			Some(info) => vec![format!("&f{}", info.usage()), info.description(locale)],
+			None => vec![tr!(locale, "help.unknown")],
 		}
 	}
 
 	/// gets the next string argument from the command
-	fn next_string(args: &mut &'m str) -> Result<&'m str, String> {
+	///
+	/// arguments can be quoted to include spaces, with `\"` and `\\` inside quotes standing for `"` and `\`
+	fn next_string(args: &mut &'m str) -> Result<Cow<'m, str>, Translatable> {
+		*args = args.trim_start();
 		if args.is_empty() {
-			return Err("Missing argument".to_string());
+			return Err(Translatable::new("command.missing_argument"));
 		}
 
-		let (start_index, end_index, extra) = if args.starts_with('"') {
-			let mut end_index = 1;
-			let mut extra = 1;
-			while end_index < args.len() {
-				if let Some(index) = args[end_index..].find('"') {
-					end_index += index;
-					if &args[end_index - 1..=end_index - 1] == "\\" {
+		let Some(quoted) = args.strip_prefix('"') else {
+			let (result, rest) = args.split_once(' ').unwrap_or((args, ""));
+			*args = rest.trim();
+			return Ok(Cow::Borrowed(result));
+		};
+
+		let mut unescaped = String::new();
+		let mut chars = quoted.char_indices();
+		while let Some((index, c)) = chars.next() {
+			match c {
+				'"' => {
+					// only copy the argument if escapes had to be removed from it
+					let result = if unescaped.len() == index {
+						Cow::Borrowed(&quoted[..index])
 					} else {
-						break;
-					}
-				} else {
-					end_index = args.len();
-					extra = 0;
-					break;
+						Cow::Owned(unescaped)
+					};
+					*args = quoted[index + 1..].trim();
+					return Ok(result);
 				}
+				'\\' => match chars.clone().next() {
+					Some((_, escaped @ ('"' | '\\'))) => {
+						chars.next();
+						unescaped.push(escaped);
+					}
+					_ => unescaped.push(c),
+				},
+				_ => unescaped.push(c),
 			}
-			(1, end_index, extra)
-		} else {
-			(0, args.find(' ').unwrap_or(args.len()), 0)
-		};
+		}
+		Err(Translatable::new("command.unterminated_quote"))
+	}
 
-		let result = &args[start_index..end_index];
-		*args = args[end_index + extra..].trim();
+	/// gets the next f32 argument from the command
+	fn next_f32(args: &mut &'m str) -> Result<f32, Translatable> {
+		let (s, r) = args.split_once(' ').unwrap_or((args, ""));
+		let n = s
+			.parse()
+			.map_err(|_| Translatable::new("command.expected_number"))?;
+		*args = r.trim();
+		Ok(n)
+	}
 
-		Ok(result)
+	/// gets the next coordinate argument from the command, where `~` alone keeps the current value and `~` before a
+	/// number offsets it
+	fn next_coordinate(args: &mut &'m str) -> Result<Coordinate, Translatable> {
+		let Some(offset) = args.strip_prefix('~') else {
+			return Self::next_f32(args).map(Coordinate::Absolute);
+		};
+		if offset.is_empty() || offset.starts_with(' ') {
+			*args = offset.trim();
+			return Ok(Coordinate::Relative(0.0));
+		}
+		let mut offset = offset;
+		let value = Self::next_f32(&mut offset)?;
+		*args = offset;
+		Ok(Coordinate::Relative(value))
 	}
 
-	/// gets the next f32 argument from the command
-	fn next_f32(args: &mut &'m str) -> Result<f32, String> {
+	/// gets the next whole number argument from the command
+	fn next_number<T>(args: &mut &'m str) -> Result<T, Translatable>
+	where
+		T: FromStr,
+	{
 		let (s, r) = args.split_once(' ').unwrap_or((args, ""));
-		let n = s.parse().map_err(|_| "Expected number!".to_string())?;
+		let n = s
+			.parse()
+			.map_err(|_| Translatable::new("command.expected_number"))?;
 		*args = r.trim();
 		Ok(n)
 	}
 
+	/// checks whether the next argument from the command is a whole number, without consuming it
+	fn peek_usize(args: &str) -> bool {
+		let s = args.split_once(' ').map(|(s, _)| s).unwrap_or(args);
+		s.parse::<usize>().is_ok()
+	}
+
+	/// gets the next argument from the command if it's a duration such as `2d`, leaving the arguments untouched otherwise
+	fn next_duration(args: &mut &'m str) -> Option<Duration> {
+		let (s, r) = args.split_once(' ').unwrap_or((args, ""));
+		let duration = parse_duration(s)?;
+		*args = r.trim();
+		Some(duration)
+	}
+
+	/// gets the next block argument from the command, either by string id or numeric id
+	fn next_block(args: &mut &'m str) -> Result<u16, Translatable> {
+		let name = Self::next_string(args)?;
+		BLOCK_STRING_ID_MAP
+			.get(&Intern::new(name.to_string()))
+			.copied()
+			.or_else(|| name.parse().ok().filter(|id| BLOCK_INFO.contains_key(id)))
+			.ok_or_else(|| Translatable::new("command.unknown_block").arg(name))
+	}
+
 	/// processes the command >:3
 	pub fn process(self, data: &mut ServerData, own_id: i8) -> Vec<String> {
 		let mut messages = Vec::new();
 
-		let player = data
-			.players
-			.iter()
-			.find(|p| p.id == own_id)
-			.expect("missing player");
+		let player = data.player(own_id).expect("missing player");
+		let locale = player.locale.clone();
+		let locale = locale.as_str();
+		let level_name = player.current_level.clone();
 
-		if self.perms_required() > player.permissions {
-			messages.push("&cPermissions do not allow you to use this command".to_string());
+		if self.perms_required(&data.config) > player.permissions {
+			messages.push(tr!(locale, "command.no_permission"));
 			return messages;
 		}
 
 		match self {
 			Command::Me { action } => {
-				let message = format!(
-					"&f*{} {action}",
-					data.players
-						.iter()
-						.find(|p| p.id == own_id)
-						.expect("missing player")
-						.username
+				let message = format!("&f*{} {action}", player.username);
+				data.spread_packet(
+					&level_name,
+					ServerPacket::Message {
+						player_id: own_id,
+						message,
+					},
 				);
-				data.spread_packet(ServerPacket::Message {
-					player_id: own_id,
-					message,
-				});
 			}
 
 			Command::Say { message } => {
 				let message = format!("&d[SERVER] &f{message}");
-				data.spread_packet(ServerPacket::Message {
+				data.spread_packet_global(ServerPacket::Message {
 					player_id: own_id,
 					message,
 				});
@@ -346,21 +1357,21 @@ impl<'m> Command<'m> {
 				permissions,
 			} => {
 				let player_perms = player.permissions;
-				if player_username == player.username {
-					messages.push("&cCannot change your own permissions".to_string());
+				let own_username = player.username.clone();
+				let player_username = data.resolve_username(&player_username);
+				if player_username == own_username {
+					messages.push(tr!(locale, "command.setperm.self"));
 					return messages;
 				} else if permissions >= player_perms {
-					messages
-						.push("&cCannot set permissions higher or equal to your own".to_string());
+					messages.push(tr!(locale, "command.setperm.too_high"));
 					return messages;
 				}
 
 				let perm_string: &'static str = permissions.into();
 
-				if let Some(current) = data.config.player_perms.get(player_username) {
+				if let Some(current) = data.config.player_perms.get(&player_username) {
 					if *current >= player_perms {
-						messages
-							.push("&cThis player outranks or is the same rank as you".to_string());
+						messages.push(tr!(locale, "command.outranked"));
 						return messages;
 					}
 				}
@@ -368,24 +1379,29 @@ impl<'m> Command<'m> {
 				data.config_needs_saving = true;
 
 				if matches!(permissions, PlayerType::Normal) {
-					data.config.player_perms.remove(player_username);
+					data.config.player_perms.remove(&player_username);
+					data.config.operators.retain(|u| *u != player_username);
 				} else {
 					data.config
 						.player_perms
-						.insert(player_username.to_string(), permissions);
+						.insert(player_username.clone(), permissions);
 				}
 				if let Some(p) = data
 					.players
 					.iter_mut()
 					.find(|p| p.username == player_username)
 				{
+					let level = data
+						.levels
+						.get(&p.current_level)
+						.expect("player should always be in a loaded level");
 					p.permissions = permissions;
 					p.packets_to_send.push(ServerPacket::UpdateUserType {
-						user_type: p.permissions,
+						user_type: data.config.user_type_for(p.permissions),
 					});
 					p.packets_to_send.push(ServerPacket::Message {
 						player_id: p.id,
-						message: format!("Your permissions have been set to {perm_string}"),
+						message: tr!(&p.locale, "command.setperm.notify", perm_string),
 					});
 
 					if p.extensions.contains(ExtBitmask::InventoryOrder) {
@@ -393,31 +1409,55 @@ impl<'m> Command<'m> {
 							p.permissions,
 							p.extensions,
 							p.custom_blocks_support_level,
+							level.inventory_order.as_deref(),
 							&mut p.packets_to_send,
 						);
 					}
+					set_player_block_permissions(
+						p.permissions,
+						p.extensions,
+						p.custom_blocks_support_level,
+						&mut p.packets_to_send,
+					);
+					set_player_rank_defaults(
+						&data.config,
+						&level.rules,
+						p.permissions,
+						p.extensions,
+						&mut p.packets_to_send,
+					);
 				}
-				messages.push(format!(
-					"Set permissions for {player_username} to {perm_string}"
+				messages.push(tr!(
+					locale,
+					"command.setperm.success",
+					player_username,
+					perm_string
 				));
 			}
 
 			Command::Kick { username, message } => {
 				let player_perms = player.permissions;
+				let staff = data
+					.config
+					.show_staff_in_kick_reasons
+					.then(|| player.username.clone());
 
-				if let Some(other_player) = data.players.iter_mut().find(|p| p.username == username)
-				{
-					if player_perms <= other_player.permissions {
-						messages
-							.push("&cThis player outranks or is the same rank as you".to_string());
-						return messages;
-					}
+				match data.find_player_mut(&username) {
+					Ok(other_player) => {
+						if player_perms <= other_player.permissions {
+							messages.push(tr!(locale, "command.outranked"));
+							return messages;
+						}
 
-					other_player.should_be_kicked =
-						Some(format!("Kicked: {}", message.unwrap_or("<no message>")));
-					messages.push(format!("{} has been kicked", other_player.username));
-				} else {
-					messages.push("&cPlayer not connected to server!".to_string());
+						other_player.should_be_kicked = Some(staff_disconnect_reason(
+							&other_player.locale,
+							"kick",
+							staff.as_deref(),
+							message,
+						));
+						messages.push(tr!(locale, "command.kick.success", other_player.username));
+					}
+					Err(err) => messages.push(err.translate(locale)),
 				}
 			}
 
@@ -427,27 +1467,25 @@ impl<'m> Command<'m> {
 
 			Command::Help { command } => {
 				let msgs = if let Some(command) = command {
-					Command::help(command)
+					let command = data
+						.config
+						.aliases
+						.get(command)
+						.map(String::as_str)
+						.unwrap_or(command);
+					Command::help(command, locale)
 				} else {
-					let mut msgs = vec!["Commands available to you:".to_string()];
-					let mut current_message = "&f".to_string();
-					for command in COMMANDS_LIST.iter() {
-						if Command::perms_required_by_name(command) > player.permissions {
-							continue;
-						}
-						if current_message.len() + 3 + command.len() > STRING_LENGTH {
-							msgs.push(format!("{current_message},"));
-							current_message = "&f".to_string();
-						}
-						if current_message.len() == 2 {
-							current_message = format!("{current_message}{command}");
-						} else {
-							current_message = format!("{current_message}, {command}");
-						}
-					}
-					if !current_message.is_empty() {
-						msgs.push(current_message);
-					}
+					let mut msgs = vec![tr!(locale, "command.help.header")];
+					msgs.extend(wrap_list(
+						"&f",
+						Command::metadata()
+							.iter()
+							.map(|info| info.name)
+							.filter(|command| {
+								Command::perms_required_by_name(command, &data.config)
+									<= player.permissions
+							}),
+					));
 					msgs
 				};
 				for msg in msgs {
@@ -457,37 +1495,68 @@ impl<'m> Command<'m> {
 
 			Command::Ban {
 				player_username,
+				duration,
 				message,
 			} => {
 				let player_perms = player.permissions;
-				if let ServerProtectionMode::PasswordsByUser(passwords) =
-					&mut data.config.protection_mode
+				let username = player.username.clone();
+				let staff = data
+					.config
+					.show_staff_in_kick_reasons
+					.then(|| player.username.clone());
+				let player_username = data.resolve_username(&player_username);
+				if data
+					.config
+					.banned_users
+					.get(&player_username)
+					.is_some_and(|ban| !ban.is_expired())
 				{
-					if !passwords.contains_key(player_username) {
-						messages.push("&cPlayer is already banned!".to_string());
-					} else {
-						passwords.remove(player_username);
-						data.config.player_perms.remove(player_username);
-						data.config_needs_saving = true;
-						if let Some(other_player) = data
-							.players
-							.iter_mut()
-							.find(|p| p.username == player_username)
-						{
-							if player_perms <= other_player.permissions {
-								messages.push(
-									"&cThis player outranks or is the same rank as you".to_string(),
-								);
-								return messages;
-							}
+					messages.push(tr!(locale, "command.ban.already"));
+					return messages;
+				}
+				let target_perms = data
+					.find_player(&player_username)
+					.map(|p| p.permissions)
+					.ok()
+					.or_else(|| data.config.player_perms.get(&player_username).copied())
+					.unwrap_or_default();
+				if player_perms <= target_perms {
+					messages.push(tr!(locale, "command.outranked"));
+					return messages;
+				}
 
-							other_player.should_be_kicked =
-								Some(format!("Banned: {}", message.unwrap_or("<no_message>")));
-						}
-						messages.push(format!("{} has been banned", player_username));
-					}
+				data.config.banned_users.insert(
+					player_username.clone(),
+					BanEntry {
+						reason: message.unwrap_or_default().to_string(),
+						expires: duration.map(|duration| SystemTime::now() + duration),
+					},
+				);
+				data.config.player_perms.remove(&player_username);
+				data.config.operators.retain(|u| *u != player_username);
+				data.config_needs_saving = true;
+				data.audit.log(AuditEvent::Ban {
+					staff: &username,
+					target: &player_username,
+					reason: message.unwrap_or_default(),
+				});
+				if let Ok(other_player) = data.find_player_mut(&player_username) {
+					other_player.should_be_kicked = Some(staff_disconnect_reason(
+						&other_player.locale,
+						"ban",
+						staff.as_deref(),
+						message,
+					));
+				}
+				if let Some(duration) = duration {
+					messages.push(tr!(
+						locale,
+						"command.ban.success_temporary",
+						player_username,
+						format_duration(duration)
+					));
 				} else {
-					messages.push("&cServer must be set to per-user passwords!".to_string());
+					messages.push(tr!(locale, "command.ban.success", player_username));
 				}
 			}
 
@@ -495,66 +1564,281 @@ impl<'m> Command<'m> {
 				player_username,
 				password,
 			} => {
-				if let ServerProtectionMode::PasswordsByUser(passwords) =
-					&mut data.config.protection_mode
-				{
-					if passwords.contains_key(player_username) {
-						messages.push("&cPlayer is already allowed in the server!".to_string());
-					} else {
-						let password = password
-							.map(|p| p.to_string())
-							.unwrap_or_else(|| nanoid::nanoid!());
-						messages.push(format!("{player_username} is now allowed in the server."));
-						messages.push(format!("Password: {password}"));
-						passwords.insert(player_username.to_string(), password);
-						data.config_needs_saving = true;
-					}
+				let username = player.username.clone();
+				let was_banned = data.config.banned_users.remove(&*player_username).is_some();
+				if was_banned {
+					data.config_needs_saving = true;
+				}
+				let needs_password = matches!(
+					&data.config.protection_mode,
+					ServerProtectionMode::PasswordsByUser(passwords)
+						if !passwords.contains_key(&*player_username)
+				);
+
+				if needs_password {
+					let password = password
+						.map(|p| p.to_string())
+						.unwrap_or_else(|| nanoid::nanoid!());
+					let task = tokio::task::spawn_blocking({
+						let password = password.clone();
+						move || hash_password(&password)
+					});
+					data.pending_commands.push(PendingCommand::AllowEntry {
+						username,
+						player_username: player_username.to_string(),
+						password,
+						was_banned,
+						task,
+					});
+				} else if was_banned {
+					messages.push(tr!(locale, "command.allowentry.success", player_username));
 				} else {
-					messages.push("&cServer must be set to per-user passwords!".to_string());
+					messages.push(tr!(locale, "command.allowentry.already"));
 				}
 			}
 
 			Command::SetPass { password } => {
-				let username = player.username.clone();
-				if let ServerProtectionMode::PasswordsByUser(passwords) =
-					&mut data.config.protection_mode
-				{
-					passwords.insert(username, password.to_string());
-					data.config_needs_saving = true;
-					messages.push("Updated password!".to_string());
+				if matches!(
+					data.config.protection_mode,
+					ServerProtectionMode::PasswordsByUser(_)
+				) {
+					let password = password.to_string();
+					let task = tokio::task::spawn_blocking(move || hash_password(&password));
+					data.pending_commands.push(PendingCommand::SetPass {
+						username: player.username.clone(),
+						task,
+					});
 				} else {
-					messages.push("&cServer must be set to per-user passwords!".to_string());
+					messages.push(tr!(locale, "command.requires_per_user_passwords"));
 				}
 			}
 
 			Command::SetLevelSpawn => {
-				data.config.spawn = Some(ConfigCoordinatesWithOrientation {
+				if level_name != data.config.level_name {
+					messages.push(tr!(locale, "command.setlevelspawn.main_only"));
+					return messages;
+				}
+				data.config.spawn = Some(ConfigSpawn::Single(ConfigCoordinatesWithOrientation {
 					x: player.x.to_f32(),
 					y: player.y.to_f32(),
 					z: player.z.to_f32(),
 					yaw: player.yaw,
 					pitch: player.pitch,
-				});
+				}));
 				data.config_needs_saving = true;
-				messages.push("Level spawn updated!".to_string());
+				messages.push(tr!(locale, "command.setlevelspawn.success"));
 			}
 
-			Command::Weather { weather_type } => {
-				if let Ok(weather_type) = weather_type.try_into() {
-					data.level.weather = weather_type;
-					let packet = ServerPacket::EnvWeatherType { weather_type };
-					for player in &mut data.players {
-						player.packets_to_send.push(packet.clone());
-					}
-					messages.push("Weather updated!".to_string());
+			Command::AddSpawn => {
+				if level_name != data.config.level_name {
+					messages.push(tr!(locale, "command.setlevelspawn.main_only"));
+					return messages;
+				}
+				let point = ConfigCoordinatesWithOrientation {
+					x: player.x.to_f32(),
+					y: player.y.to_f32(),
+					z: player.z.to_f32(),
+					yaw: player.yaw,
+					pitch: player.pitch,
+				};
+				let count = match &mut data.config.spawn {
+					Some(spawn) => {
+						spawn.push(point);
+						spawn.points().len()
+					}
+					None => {
+						data.config.spawn = Some(ConfigSpawn::Multiple(vec![point]));
+						1
+					}
+				};
+				data.config_needs_saving = true;
+				messages.push(tr!(locale, "command.addspawn.success", count));
+			}
+
+			Command::ClearSpawns => {
+				data.config.spawn = None;
+				data.config_needs_saving = true;
+				messages.push(tr!(locale, "command.clearspawns.success"));
+			}
+
+			Command::Weather { weather_type } => {
+				if let Ok(weather_type) = weather_type.try_into() {
+					let level = data
+						.player_level_mut(own_id)
+						.expect("player should always be in a loaded level");
+					level.weather = weather_type;
+					// the weather is only kept in the level's info, so save it right away rather than waiting for the next auto save
+					level.save_now = true;
+					let packet = ServerPacket::EnvWeatherType { weather_type };
+					for player in data
+						.players
+						.iter_mut()
+						.filter(|p| p.current_level == level_name)
+						.filter(|p| p.extensions.contains(ExtBitmask::EnvWeatherType))
+					{
+						player.packets_to_send.push(packet.clone());
+					}
+					messages.push(tr!(locale, "command.weather.success"));
+				} else {
+					messages.push(tr!(locale, "command.weather.unknown", weather_type));
+				}
+			}
+
+			Command::EnvColor { target, color } => {
+				let Ok(variable) = EnvColorType::from_str(&target) else {
+					messages.push(tr!(locale, "command.envcolor.unknown_target", target));
+					return messages;
+				};
+				let color = if color.eq_ignore_ascii_case("reset") {
+					None
+				} else if let Some(color) = parse_hex_color(&color) {
+					Some(color)
 				} else {
-					messages.push(format!("&cUnknown weather type {weather_type}!"));
+					messages.push(tr!(locale, "command.envcolor.invalid_color", color));
+					return messages;
+				};
+
+				let level = data
+					.player_level_mut(own_id)
+					.expect("player should always be in a loaded level");
+				if let Some(color) = color {
+					level.env_colors.insert(variable, color);
+				} else {
+					level.env_colors.remove(&variable);
 				}
+				let packet = ServerPacket::EnvSetColor {
+					variable,
+					color: level.env_color(variable),
+				};
+				for player in data
+					.players
+					.iter_mut()
+					.filter(|p| p.current_level == level_name)
+					.filter(|p| p.extensions.contains(ExtBitmask::EnvColors))
+				{
+					player.packets_to_send.push(packet.clone());
+				}
+				messages.push(tr!(locale, "command.envcolor.success"));
 			}
 
-			Command::Save => {
-				data.level.save_now = true;
-				messages.push("Saving level...".to_string());
+			Command::Model { username, model } => {
+				let username = if username == USERNAME_SELF {
+					player.username.clone()
+				} else {
+					username.to_string()
+				};
+				let model = model.to_lowercase();
+				let target = match data.find_player_mut(&username) {
+					Ok(target) => target,
+					Err(err) => {
+						messages.push(err.translate(locale));
+						return messages;
+					}
+				};
+				target.model = model.clone();
+				let target_id = target.id;
+				let target_level = target.current_level.clone();
+				let packet = ServerPacket::ChangeModel {
+					entity_id: target_id,
+					model: model.clone(),
+				};
+				for player in data
+					.players
+					.iter_mut()
+					.filter(|p| p.current_level == target_level)
+					.filter(|p| p.extensions.contains(ExtBitmask::ChangeModel))
+				{
+					player.packets_to_send.push(packet.clone());
+				}
+				messages.push(tr!(locale, "command.model.success", username, model));
+			}
+
+			Command::Time { mode } => {
+				let level = data
+					.levels
+					.get_mut(&level_name)
+					.expect("player should always be in a loaded level");
+				match mode {
+					TimeMode::Query => {
+						messages.push(tr!(locale, "command.time.current", level.time));
+						return messages;
+					}
+					TimeMode::Set(time) => level.set_time(time),
+					TimeMode::Add(time) => level.set_time(level.time + time % DAY_LENGTH_TICKS),
+				}
+				let packets =
+					level.update_time_colors(&data.config.day_colors, &data.config.night_colors);
+				for player in data
+					.players
+					.iter_mut()
+					.filter(|p| p.current_level == level_name)
+					.filter(|p| p.extensions.contains(ExtBitmask::EnvColors))
+				{
+					player.packets_to_send.extend(packets.iter().cloned());
+				}
+				messages.push(tr!(locale, "command.time.set", level.time));
+			}
+
+			Command::Clear { mode } => match mode {
+				ClearMode::Chat => {
+					for player in &mut data.players {
+						for _ in 0..CLEAR_CHAT_LINES {
+							player.packets_to_send.push(ServerPacket::Message {
+								player_id: -1,
+								message: String::new(),
+							});
+						}
+					}
+					messages.push(tr!(locale, "command.clear.chat"));
+				}
+				ClearMode::Player(username) => {
+					let player_perms = player.permissions;
+					let username = if username == USERNAME_SELF {
+						player.username.clone()
+					} else {
+						data.resolve_username(&username)
+					};
+					if username != player.username {
+						let target_perms = data
+							.find_player(&username)
+							.ok()
+							.filter(|p| p.username == username)
+							.map(|p| p.permissions)
+							.or_else(|| data.config.player_perms.get(&username).copied())
+							.unwrap_or_default();
+						if player_perms <= target_perms {
+							messages.push(tr!(locale, "command.outranked"));
+							return messages;
+						}
+					}
+
+					for level in data.levels.values_mut() {
+						level.player_data.remove(&username);
+					}
+					// a connected player's data would otherwise be saved again when they leave their level
+					if let Some(target) = data
+						.find_player_mut(&username)
+						.ok()
+						.filter(|p| p.username == username)
+					{
+						target.savable_data = Default::default();
+					}
+					messages.push(tr!(locale, "command.clear.player", username));
+				}
+			},
+
+			Command::Save { all } => {
+				if all {
+					for level in data.levels.values_mut() {
+						level.save_now = true;
+					}
+					messages.push(tr!(locale, "command.save.started_all", data.levels.len()));
+				} else {
+					data.player_level_mut(own_id)
+						.expect("player should always be in a loaded level")
+						.save_now = true;
+					messages.push(tr!(locale, "command.save.started"));
+				}
 			}
 
 			Command::Teleport { username, mode } => {
@@ -563,89 +1847,834 @@ impl<'m> Command<'m> {
 				} else {
 					username.to_string()
 				};
+				let (username, teleported_level, position) = match data.find_player(&username) {
+					Ok(p) => (
+						p.username.clone(),
+						p.current_level.clone(),
+						(p.x.to_f32(), p.y.to_f32(), p.z.to_f32()),
+					),
+					Err(err) => {
+						messages.push(err.translate(locale));
+						return messages;
+					}
+				};
 
-				let (x, y, z, yaw, pitch, msg) = match mode {
+				let (x, y, z, yaw, pitch, target_username) = match mode {
 					TeleportMode::Player(username) => {
 						let username = if username == USERNAME_SELF {
 							player.username.clone()
 						} else {
 							username.to_string()
 						};
-						if let Some(player) =
-							data.players.iter_mut().find(|p| p.username == username)
-						{
-							(
-								player.x,
-								player.y,
-								player.z,
-								Some(player.yaw),
-								Some(player.pitch),
-								Some(format!("You have been teleported to {username}.")),
-							)
-						} else {
-							messages.push(format!("Unknown username: {username}"));
+						let player = match data.find_player(&username) {
+							Ok(player) => player,
+							Err(err) => {
+								messages.push(err.translate(locale));
+								return messages;
+							}
+						};
+						if player.current_level != teleported_level {
+							messages.push(tr!(
+								locale,
+								"command.teleport.other_level",
+								player.username
+							));
 							return messages;
 						}
+						(
+							player.x,
+							player.y,
+							player.z,
+							Some(player.yaw),
+							Some(player.pitch),
+							Some(player.username.clone()),
+						)
 					}
-					TeleportMode::Coordinates { x, y, z } => (
-						f16::from_f32(x + 0.5),
-						f16::from_f32(y + 1.0),
-						f16::from_f32(z + 0.5),
-						None,
-						None,
-						None,
+					TeleportMode::Coordinates { x, y, z } => {
+						// relative coordinates are offset from the block the teleported player is in, so the offsets
+						// added to every coordinate below leave them moved by exactly the given amount
+						let x = x.resolve(position.0 - 0.5);
+						let y = y.resolve(position.1 - 1.0);
+						let z = z.resolve(position.2 - 0.5);
+						let y = match data.levels.get(&teleported_level) {
+							Some(level)
+								if data.config.safe_teleport
+									&& x >= 0.0 && y >= 0.0 && z >= 0.0 =>
+							{
+								level
+									.clear_height_above(x as usize, y as usize, z as usize)
+									.filter(|clear| *clear > y as usize)
+									.map_or(y, |clear| clear as f32)
+							}
+							_ => y,
+						};
+						(
+							f16::from_f32(x + 0.5),
+							f16::from_f32(y + 1.0),
+							f16::from_f32(z + 0.5),
+							None,
+							None,
+							None,
+						)
+					}
+				};
+
+				let teleported =
+					teleport_player(data, &username, (x, y, z, yaw, pitch), |locale| {
+						if let Some(target_username) = &target_username {
+							tr!(locale, "command.teleport.to_player", target_username)
+						} else {
+							tr!(locale, "command.teleport.to_coordinates", x, y, z)
+						}
+					});
+				if !teleported {
+					messages.push(tr!(locale, "command.unknown_username", username));
+				}
+			}
+
+			Command::SetSpawn => {
+				let player = data.player_mut(own_id).expect("missing player");
+				player.spawn = Some(PlayerSpawn {
+					x: player.x,
+					y: player.y,
+					z: player.z,
+					yaw: player.yaw,
+					pitch: player.pitch,
+				});
+				messages.push(tr!(locale, "command.setspawn.success"));
+			}
+
+			Command::Spawn => {
+				let username = player.username.clone();
+				let (x, y, z, yaw, pitch) = match player.spawn {
+					Some(spawn) => (spawn.x, spawn.y, spawn.z, spawn.yaw, spawn.pitch),
+					None => data.level_spawn(&level_name),
+				};
+				teleport_player(
+					data,
+					&username,
+					(x, y, z, Some(yaw), Some(pitch)),
+					|locale| tr!(locale, "command.spawn.success"),
+				);
+			}
+
+			Command::LevelInfo { field, value } => {
+				let level = data
+					.player_level_mut(own_id)
+					.expect("player should always be in a loaded level");
+				let target = match &*field {
+					"name" => &mut level.name,
+					"motd" => &mut level.motd,
+					_ => {
+						messages.push(tr!(locale, "command.levelinfo.unknown", field));
+						return messages;
+					}
+				};
+				*target = value.map(str::to_string);
+				if let Some(value) = value {
+					messages.push(tr!(locale, "command.levelinfo.set", field, value));
+				} else {
+					messages.push(tr!(locale, "command.levelinfo.cleared", field));
+				}
+			}
+
+			Command::Back => {
+				let Some((x, y, z, yaw, pitch)) = player.previous_position else {
+					messages.push(tr!(locale, "command.back.none"));
+					return messages;
+				};
+				let username = player.username.clone();
+				teleport_player(
+					data,
+					&username,
+					(x, y, z, Some(yaw), Some(pitch)),
+					|locale| tr!(locale, "command.back.success"),
+				);
+			}
+
+			Command::ResetSpawn => {
+				let player = data.player_mut(own_id).expect("missing player");
+				if player.spawn.take().is_some() {
+					messages.push(tr!(locale, "command.resetspawn.success"));
+				} else {
+					messages.push(tr!(locale, "command.resetspawn.none"));
+				}
+			}
+
+			Command::Lang { locale: new_locale } => {
+				let new_locale = new_locale.to_lowercase();
+				if locale::is_available(&new_locale) {
+					messages.push(tr!(&new_locale, "command.lang.success", new_locale));
+					if let Some(player) = data.player_mut(own_id) {
+						player.locale = new_locale;
+					}
+				} else {
+					messages.push(tr!(
+						locale,
+						"command.lang.unknown",
+						new_locale,
+						locale::available_locales().join(", ")
+					));
+				}
+			}
+
+			Command::Hollow { block } => {
+				let info = BLOCK_INFO.get(&block).expect("missing block");
+				if info.place_permissions > player.permissions {
+					messages.push(tr!(locale, "block.cannot_place"));
+					return messages;
+				}
+				start_selection(data, own_id, SelectionAction::Hollow { block });
+				messages.push(tr!(locale, "selection.started"));
+			}
+
+			Command::Cuboid { block } => {
+				let info = BLOCK_INFO.get(&block).expect("missing block");
+				if info.place_permissions > player.permissions {
+					messages.push(tr!(locale, "block.cannot_place"));
+					return messages;
+				}
+				start_selection(data, own_id, SelectionAction::Fill { block });
+				messages.push(tr!(locale, "selection.started"));
+			}
+
+			Command::Fill {
+				x1,
+				y1,
+				z1,
+				x2,
+				y2,
+				z2,
+				block,
+			} => {
+				let info = BLOCK_INFO.get(&block).expect("missing block");
+				if info.place_permissions > player.permissions {
+					messages.push(tr!(locale, "block.cannot_place"));
+					return messages;
+				}
+				let level = data
+					.player_level(own_id)
+					.expect("player should always be in a loaded level");
+				let clamp = |n: i32, size: usize| n.clamp(0, size as i32 - 1) as usize;
+				let cuboid = Cuboid::from_corners(
+					(
+						clamp(x1, level.x_size),
+						clamp(y1, level.y_size),
+						clamp(z1, level.z_size),
+					),
+					(
+						clamp(x2, level.x_size),
+						clamp(y2, level.y_size),
+						clamp(z2, level.z_size),
 					),
+				);
+				messages.extend(SelectionAction::Fill { block }.apply(data, own_id, cuboid));
+			}
+
+			Command::CountSelection => {
+				start_selection(data, own_id, SelectionAction::Count);
+				messages.push(tr!(locale, "selection.started"));
+			}
+
+			Command::Measure => {
+				start_selection(data, own_id, SelectionAction::Measure);
+				messages.push(tr!(locale, "selection.started"));
+			}
+
+			Command::Protect { permissions } => {
+				start_selection(data, own_id, SelectionAction::Protect { permissions });
+				messages.push(tr!(locale, "selection.started"));
+			}
+
+			Command::Worlds => {
+				messages.push(tr!(locale, "command.worlds.header", data.levels.len()));
+				messages.extend(wrap_list("&f", data.levels.keys().map(String::as_str)));
+			}
+
+			Command::NewWorld {
+				name,
+				x_size,
+				y_size,
+				z_size,
+				generation,
+				seed,
+			} => {
+				if name.is_empty()
+					|| !name
+						.chars()
+						.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+				{
+					messages.push(tr!(locale, "command.newworld.invalid_name", name));
+					return messages;
+				}
+				if data.levels.contains_key(&*name) {
+					messages.push(tr!(locale, "command.newworld.exists", name));
+					return messages;
+				}
+				if !data.config.level_size_allowed(x_size, y_size, z_size) {
+					messages.push(tr!(
+						locale,
+						"command.newworld.invalid_size",
+						data.config.max_level_volume,
+						i16::MAX
+					));
+					return messages;
+				}
+
+				let generating = data.pending_commands.iter().any(|command| {
+					matches!(command, PendingCommand::NewWorld { level_name, .. } if *level_name == name)
+				});
+				if generating {
+					messages.push(tr!(locale, "command.newworld.exists", name));
+					return messages;
+				}
+
+				let seed = seed.unwrap_or_else(rand::random);
+				let features = data.config.generation_features.clone();
+				let task = tokio::task::spawn_blocking(move || {
+					let mut level = Level::new(x_size, y_size, z_size);
+					generation.generate_seeded(&mut level, &features, seed);
+					level
+				});
+				data.pending_commands.push(PendingCommand::NewWorld {
+					username: player.username.clone(),
+					level_name: name.to_string(),
+					seed,
+					task,
+				});
+				messages.push(tr!(locale, "command.newworld.generating", name));
+			}
+
+			Command::Rules => {
+				if data.config.rules.is_empty() {
+					messages.push(tr!(locale, "command.rules.none"));
+				} else {
+					messages.push(tr!(locale, "command.rules.header"));
+					messages.extend(data.config.rule_lines());
+				}
+			}
+
+			Command::SpawnPoint { username, x, y, z } => {
+				let target = match data.find_player_mut(&username) {
+					Ok(target) => target,
+					Err(err) => {
+						messages.push(err.translate(locale));
+						return messages;
+					}
 				};
+				if !target.extensions.contains(ExtBitmask::SetSpawnpoint) {
+					messages.push(tr!(
+						locale,
+						"command.spawnpoint.unsupported",
+						target.username
+					));
+					return messages;
+				}
+				target.packets_to_send.push(ServerPacket::SetSpawnpoint {
+					x: f16::from_f32(x + 0.5),
+					y: f16::from_f32(y + 1.0),
+					z: f16::from_f32(z + 0.5),
+					yaw: target.yaw,
+					pitch: target.pitch,
+				});
+				messages.push(tr!(
+					locale,
+					"command.spawnpoint.success",
+					target.username,
+					x,
+					y,
+					z
+				));
+			}
 
-				if let Some(player) = data.players.iter_mut().find(|p| p.username == username) {
-					let yaw = yaw.unwrap_or(player.yaw);
-					let pitch = pitch.unwrap_or(player.pitch);
-					player.x = x;
-					player.y = y;
-					player.z = z;
-					player.yaw = yaw;
-					player.pitch = pitch;
-					let packet = ServerPacket::SetPositionOrientation {
-						player_id: player.id,
-						x,
-						y,
-						z,
-						yaw,
-						pitch,
-					};
-					let ext_packet = ServerPacket::ExtEntityTeleport {
-						entity_id: player.id,
-						teleport_behavior: TeleportBehavior::UsePosition
-							| TeleportBehavior::UseOrientation
-							| TeleportBehavior::ModeInterpolated,
-						x,
-						y,
-						z,
-						yaw,
-						pitch,
-					};
-					let id = player.id;
+			Command::Promote { username } => {
+				return step_permissions(data, own_id, &username, true);
+			}
 
-					for player in &mut data.players {
-						let mut packet =
-							if player.extensions.contains(ExtBitmask::ExtEntityTeleport) {
-								ext_packet.clone()
+			Command::Demote { username } => {
+				return step_permissions(data, own_id, &username, false);
+			}
+
+			Command::Top => {
+				let username = player.username.clone();
+				let (x, z) = (player.x, player.z);
+				let top = data
+					.player_level(own_id)
+					.expect("player should always be in a loaded level")
+					.surface_height(x.to_f32() as usize, z.to_f32() as usize);
+				let Some(top) = top else {
+					messages.push(tr!(locale, "command.top.no_surface"));
+					return messages;
+				};
+				teleport_player(
+					data,
+					&username,
+					(x, f16::from_f32(top as f32 + 2.0), z, None, None),
+					|locale| tr!(locale, "command.top.success"),
+				);
+			}
+
+			Command::Generate { mode } => {
+				let level = data
+					.player_level(own_id)
+					.expect("player should always be in a loaded level");
+				match mode {
+					GenerateMode::Seed => {
+						if let Some(seed) = level.seed {
+							messages.push(tr!(locale, "command.generate.seed", seed));
+						} else {
+							messages.push(tr!(locale, "command.generate.no_seed"));
+						}
+					}
+					GenerateMode::Preview { seed, generator } => {
+						let (x_size, y_size, z_size) = (
+							level.x_size.min(MAX_PREVIEW_SIDE),
+							level.y_size.min(MAX_PREVIEW_SIDE),
+							level.z_size.min(MAX_PREVIEW_SIDE),
+						);
+						let generation = match generator {
+							Some(generator) => {
+								let Some(generation) =
+									LevelGeneration::from_name(&generator, y_size)
+								else {
+									messages.push(format!(
+										"&c{}",
+										Translatable::new("command.newworld.unknown_generator")
+											.arg(generator)
+											.translate(locale)
+									));
+									return messages;
+								};
+								generation
+							}
+							None => data.config.generation.clone(),
+						};
+						let username = player.username.clone();
+						let previewing = data.pending_commands.iter().any(|command| {
+							matches!(command, PendingCommand::Preview { username: previewer, .. } if *previewer == username)
+						});
+						if previewing {
+							messages.push(tr!(locale, "command.generate.busy"));
+							return messages;
+						}
+
+						let features = data.config.generation_features.clone();
+						let task = tokio::task::spawn_blocking(move || {
+							let mut preview = Level::new(x_size, y_size, z_size);
+							generation.generate_seeded(&mut preview, &features, seed);
+							preview.block_counts()
+						});
+						data.pending_commands.push(PendingCommand::Preview {
+							username,
+							seed,
+							size: (x_size, y_size, z_size),
+							task,
+						});
+					}
+				}
+			}
+
+			Command::LevelRule { rule, value } => {
+				let rules = &mut data
+					.player_level_mut(own_id)
+					.expect("player should always be in a loaded level")
+					.rules;
+				if rule == "all" {
+					messages.push(tr!(locale, "levelrule.all_header"));
+					messages.extend(rules.get_all_rules_info());
+				} else if rules.get_rule(&rule).is_none() {
+					messages.push(format!(
+						"&c{}",
+						Translatable::new("levelrule.unknown")
+							.arg(&rule)
+							.translate(locale)
+					));
+					if let Some(closest) = rules.closest_rule(&rule) {
+						messages.push(tr!(locale, "levelrule.suggestion", closest));
+					}
+					messages.push(tr!(locale, "levelrule.available"));
+					let names = rules.rule_names();
+					messages.extend(wrap_list("&f", names.iter().map(String::as_str)));
+				} else if let Some(value) = value {
+					match rules.set_rule(&rule, value) {
+						Ok(()) => {
+							messages.push(tr!(locale, "levelrule.set", rule, value));
+							// hack rules may have changed, so everyone in the level gets them again
+							let rules = &data.levels[&level_name].rules;
+							for p in data
+								.players
+								.iter_mut()
+								.filter(|p| p.current_level == level_name)
+							{
+								set_player_rank_defaults(
+									&data.config,
+									rules,
+									p.permissions,
+									p.extensions,
+									&mut p.packets_to_send,
+								);
+							}
+						}
+						Err(e) => messages.push(format!("&c{}", e.translate(locale))),
+					}
+				} else if let Some(value) = rules.get_rule(&rule) {
+					messages.push(format!("{rule} = {value}"));
+				}
+			}
+
+			Command::Goto {
+				level_name: target_level,
+			} => {
+				let Some(level) = data.levels.get(&*target_level) else {
+					messages.push(tr!(
+						locale,
+						"command.goto.unknown",
+						target_level,
+						data.levels
+							.keys()
+							.map(String::as_str)
+							.collect::<Vec<_>>()
+							.join(", ")
+					));
+					return messages;
+				};
+				if target_level == level_name {
+					messages.push(tr!(locale, "command.goto.already", target_level));
+					return messages;
+				}
+
+				// the level is sent from a snapshot so the server isn't locked while it's compressed
+				let snapshot = level.clone();
+				let extensions = player.extensions;
+				let custom_blocks_support_level = player.custom_blocks_support_level;
+				let compression = data.config.compression();
+				let task = tokio::task::spawn_blocking(move || {
+					let packets = build_level_packets(
+						&snapshot,
+						extensions,
+						custom_blocks_support_level,
+						compression,
+					);
+					(snapshot, packets)
+				});
+				data.pending_commands.push(PendingCommand::Goto {
+					username: player.username.clone(),
+					level_name: target_level.to_string(),
+					task,
+				});
+			}
+
+			Command::Tell { username, message } => {
+				if username == USERNAME_SELF {
+					messages.push(tr!(locale, "command.tell.self"));
+					return messages;
+				}
+				let sender = player.username.clone();
+				match data.find_player_mut(&username) {
+					Ok(target) => {
+						target.packets_to_send.push(ServerPacket::Message {
+							player_id: own_id,
+							message: truncate_to_string_length(&tr!(
+								&target.locale,
+								"command.tell.received",
+								sender,
+								message
+							)),
+						});
+						messages.push(truncate_to_string_length(&tr!(
+							locale,
+							"command.tell.sent",
+							target.username,
+							message
+						)));
+					}
+					Err(err) => messages.push(err.translate(locale)),
+				}
+			}
+
+			Command::Players => {
+				messages.push(tr!(locale, "command.players.header", data.player_count()));
+				for rank in [
+					PlayerType::Operator,
+					PlayerType::Moderator,
+					PlayerType::Normal,
+				] {
+					let mut players: Vec<_> = data
+						.players
+						.iter()
+						.filter(|p| p.permissions == rank)
+						.collect();
+					if players.is_empty() {
+						continue;
+					}
+					players.sort_unstable_by(|a, b| a.username.cmp(&b.username));
+					let entries: Vec<String> = players
+						.into_iter()
+						.map(|p| {
+							let activity = format!("{}b", p.block_rate.changed);
+							let activity = if p.block_rate.rejected == 0 {
+								activity
 							} else {
-								packet.clone()
+								format!("{activity} &c{}r&7", p.block_rate.rejected)
 							};
-						if player.id == id {
-							packet.set_player_id(-1);
-							player.packets_to_send.push(ServerPacket::Message {
-								player_id: -1,
-								message: msg.clone().unwrap_or_else(|| {
-									format!("You have been teleported to {x}, {y}, {z}.")
-								}),
-							});
+							match p.ping.rtt_ms {
+								Some(rtt) => format!("{} &7({rtt}ms, {activity})&f", p.username),
+								None => format!("{} &7({activity})&f", p.username),
+							}
+						})
+						.collect();
+					let rank: &'static str = rank.into();
+					messages.extend(wrap_list(
+						&format!("&e{rank}: &f"),
+						entries.iter().map(String::as_str),
+					));
+				}
+			}
+
+			Command::Physics { enabled } => {
+				let level = data
+					.player_level_mut(own_id)
+					.expect("player should always be in a loaded level");
+				if let Some(enabled) = enabled {
+					level.physics_enabled = enabled;
+				}
+				messages.push(if level.physics_enabled {
+					tr!(locale, "command.physics.on")
+				} else {
+					tr!(locale, "command.physics.off")
+				});
+			}
+
+			Command::Inventory { mode } => {
+				let level = data
+					.player_level_mut(own_id)
+					.expect("player should always be in a loaded level");
+				match mode {
+					InventoryMode::Query => {
+						if let Some(inventory_order) = &level.inventory_order {
+							messages.push(tr!(
+								locale,
+								"command.inventory.custom",
+								inventory_order.len()
+							));
+							messages.extend(wrap_list(
+								"&7",
+								inventory_order.iter().map(String::as_str),
+							));
+						} else {
+							messages.push(tr!(locale, "command.inventory.default"));
+						}
+						return messages;
+					}
+					InventoryMode::Reset => {
+						level.inventory_order = None;
+						messages.push(tr!(locale, "command.inventory.reset"));
+					}
+					InventoryMode::Set(blocks) => {
+						level.inventory_order = Some(
+							blocks
+								.iter()
+								.map(|id| {
+									BLOCK_INFO
+										.get(id)
+										.expect("missing block")
+										.str_id
+										.to_string()
+								})
+								.collect(),
+						);
+						messages.push(tr!(locale, "command.inventory.set", blocks.len()));
+					}
+				}
+
+				let inventory_order = level.inventory_order.clone();
+				for player in data.players.iter_mut().filter(|p| {
+					p.current_level == level_name
+						&& p.extensions.contains(ExtBitmask::InventoryOrder)
+				}) {
+					set_player_inventory(
+						player.permissions,
+						player.extensions,
+						player.custom_blocks_support_level,
+						inventory_order.as_deref(),
+						&mut player.packets_to_send,
+					);
+				}
+			}
+
+			Command::Whois { username } => {
+				let username = data.resolve_username(&username);
+				if let Some(other) = data.players.iter().find(|p| p.username == username) {
+					let rank: &'static str = other.permissions.into();
+					messages.push(tr!(
+						locale,
+						"command.whois.online",
+						other.username,
+						rank,
+						other.current_level
+					));
+					messages.push(tr!(locale, "command.whois.address", other.addr));
+					messages.push(tr!(
+						locale,
+						"command.whois.position",
+						other.x,
+						other.y,
+						other.z
+					));
+					messages.push(tr!(
+						locale,
+						"command.whois.custom_blocks",
+						other.custom_blocks_support_level
+					));
+					let extensions: Vec<String> = other
+						.extensions
+						.all_contained_info()
+						.into_iter()
+						.map(|info| info.ext_name)
+						.collect();
+					messages.push(tr!(locale, "command.whois.extensions", extensions.len()));
+					messages.extend(wrap_list("&7", extensions.iter().map(String::as_str)));
+					return messages;
+				}
+
+				let perms = data.config.player_perms.get(&username);
+				let saved: Vec<_> = data
+					.levels
+					.iter()
+					.filter_map(|(name, level)| level.player_data.get(&username).map(|d| (name, d)))
+					.collect();
+				if perms.is_none() && saved.is_empty() {
+					messages.push(tr!(locale, "command.unknown_username", username));
+					return messages;
+				}
+				let rank: &'static str = perms.copied().unwrap_or_default().into();
+				messages.push(tr!(locale, "command.whois.offline", username, rank));
+				for (level_name, saved) in saved {
+					messages.push(tr!(
+						locale,
+						"command.whois.saved_position",
+						level_name,
+						saved.x,
+						saved.y,
+						saved.z
+					));
+				}
+			}
+
+			Command::BanIp { target } => {
+				let player_perms = player.permissions;
+				let username = player.username.clone();
+				let staff = data
+					.config
+					.show_staff_in_kick_reasons
+					.then(|| player.username.clone());
+				let ip = if let Ok(ip) = target.parse::<IpAddr>() {
+					ip
+				} else {
+					match data.find_player(&target) {
+						Ok(other_player) => other_player.addr.ip(),
+						Err(err) => {
+							messages.push(err.translate(locale));
+							return messages;
 						}
-						player.packets_to_send.push(packet);
 					}
+				};
+
+				if data
+					.players
+					.iter()
+					.any(|p| p.addr.ip() == ip && p.permissions >= player_perms)
+				{
+					messages.push(tr!(locale, "command.outranked"));
+					return messages;
+				}
+				if data.config.banned_ips.contains(&ip) {
+					messages.push(tr!(locale, "command.ban.already"));
+					return messages;
+				}
+
+				data.config.banned_ips.push(ip);
+				data.config_needs_saving = true;
+				data.audit.log(AuditEvent::Ban {
+					staff: &username,
+					target: &ip.to_string(),
+					reason: "",
+				});
+				for other_player in data.players.iter_mut().filter(|p| p.addr.ip() == ip) {
+					other_player.should_be_kicked = Some(staff_disconnect_reason(
+						&other_player.locale,
+						"ban",
+						staff.as_deref(),
+						None,
+					));
+				}
+				messages.push(tr!(locale, "command.banip.success", ip));
+			}
+
+			Command::Export { filename } => {
+				if !filename
+					.chars()
+					.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+				{
+					messages.push(tr!(locale, "command.export.invalid_name", filename));
+					return messages;
+				}
+				let path = PathBuf::from(LEVELS_PATH).join(format!("{filename}.{CW_EXTENSION}"));
+				messages.push(tr!(locale, "command.export.started", path.display()));
+				data.player_level_mut(own_id)
+					.expect("player should always be in a loaded level")
+					.export_to = Some(path);
+			}
+
+			Command::Undo { username, count } => {
+				let player_perms = player.permissions;
+				let target = match username.filter(|u| *u != USERNAME_SELF) {
+					Some(username) => {
+						let other_player = match data.find_player(&username) {
+							Ok(other_player) => other_player,
+							Err(err) => {
+								messages.push(err.translate(locale));
+								return messages;
+							}
+						};
+						if other_player.id == own_id {
+							own_id
+						} else if player_perms < PlayerType::Moderator {
+							messages.push(tr!(locale, "command.no_permission"));
+							return messages;
+						} else if player_perms <= other_player.permissions {
+							messages.push(tr!(locale, "command.outranked"));
+							return messages;
+						} else {
+							other_player.id
+						}
+					}
+					None => own_id,
+				};
+
+				let target = data.player_mut(target).expect("missing player");
+				let count = count.min(target.edit_history.len());
+				let edits: Vec<_> = target
+					.edit_history
+					.drain(target.edit_history.len() - count..)
+					.rev()
+					.collect();
+				let username = target.username.clone();
+				for edit in &edits {
+					// edits in levels which have since been unloaded are skipped
+					let _ = data.set_block_broadcast(
+						&edit.level,
+						edit.x,
+						edit.y,
+						edit.z,
+						edit.previous_block,
+					);
+				}
+
+				if edits.is_empty() {
+					messages.push(tr!(locale, "command.undo.nothing", username));
 				} else {
-					messages.push(format!("&fUnknown username: {username}!"));
+					messages.push(tr!(locale, "command.undo.success", edits.len(), username));
 				}
 			}
 		}
@@ -653,3 +2682,589 @@ impl<'m> Command<'m> {
 		messages
 	}
 }
+
+/// moves a player to another level once its packets have been built
+fn finish_goto(
+	data: &mut ServerData,
+	username: &str,
+	target_level: &str,
+	result: Result<(Level, Vec<ServerPacket>), GeneralError>,
+) {
+	// the player may have left or moved elsewhere while the level was being prepared
+	let Some(player) = data.players.iter().find(|p| p.username == username) else {
+		return;
+	};
+	let own_id = player.id;
+	let level_name = player.current_level.clone();
+	let Some(level) = data.levels.get(target_level) else {
+		return;
+	};
+	if level_name == target_level {
+		return;
+	}
+
+	let extensions = player.extensions;
+	let (snapshot, mut own_packets) = match result {
+		Ok(result) => result,
+		Err(e) => {
+			eprintln!("failed to build level packets for {target_level}: {e}");
+			send_messages(data, username, |locale| {
+				vec![tr!(locale, "command.goto.failed")]
+			});
+			return;
+		}
+	};
+	own_packets.insert(
+		0,
+		server_identification_packet(&data.config, level, player.permissions),
+	);
+	// blocks changed while the packets were being built are sent after them
+	for (index, (old, new)) in snapshot.blocks.iter().zip(&level.blocks).enumerate() {
+		if old != new {
+			let (x, y, z) = level.coordinates(index);
+			own_packets.push(ServerPacket::SetBlock {
+				x: x as i16,
+				y: y as i16,
+				z: z as i16,
+				block_type: *new,
+			});
+		}
+	}
+	own_packets.extend(level_env_packets(level, extensions));
+	if extensions.contains(ExtBitmask::InventoryOrder) {
+		set_player_inventory(
+			player.permissions,
+			extensions,
+			player.custom_blocks_support_level,
+			level.inventory_order.as_deref(),
+			&mut own_packets,
+		);
+	}
+	set_player_rank_defaults(
+		&data.config,
+		&level.rules,
+		player.permissions,
+		extensions,
+		&mut own_packets,
+	);
+	let saved_position = level.player_data.get(username).cloned();
+	let spawn = data.level_spawn(target_level);
+
+	// remember where the player was and remove them from the old level
+	let savable_data = player.savable_data.clone();
+	if let Some(old_level) = data.levels.get_mut(&level_name) {
+		old_level
+			.player_data
+			.insert(username.to_string(), savable_data);
+	}
+	let despawn_packet = ServerPacket::DespawnPlayer { player_id: own_id };
+	for other in &mut data.players {
+		if other.id == own_id {
+			continue;
+		}
+		if other.current_level == level_name {
+			other.packets_to_send.push(despawn_packet.clone());
+			own_packets.push(ServerPacket::DespawnPlayer {
+				player_id: other.id,
+			});
+		} else if other.current_level == target_level {
+			own_packets.push(player_spawn_packet(other));
+			own_packets.extend(player_model_packet(other, extensions));
+		}
+	}
+
+	let player = data.player_mut(own_id).expect("missing player");
+	player.current_level = target_level.to_string();
+	player.previous_position = None;
+	if player.pending_selection.take().is_some()
+		&& player.extensions.contains(ExtBitmask::SelectionCuboid)
+	{
+		own_packets.push(ServerPacket::RemoveSelection {
+			selection_id: SELECTION_PREVIEW_ID,
+		});
+	}
+	if let Some(saved_position) = saved_position {
+		player.savable_data = saved_position;
+	} else {
+		let (x, y, z, yaw, pitch) = spawn;
+		player.savable_data = SavablePlayerData {
+			x,
+			y,
+			z,
+			yaw,
+			pitch,
+			spawn: None,
+		};
+	}
+	let spawn_packet = player_spawn_packet(player);
+	let model_packet = player_model_packet(player, ExtBitmask::ChangeModel);
+	own_packets.push(spawn_packet.clone());
+	if extensions.contains(ExtBitmask::ChangeModel) {
+		own_packets.extend(model_packet.clone());
+	}
+	player.packets_to_send.extend(own_packets);
+	for other in &mut data.players {
+		if other.id != own_id && other.current_level == target_level {
+			other.packets_to_send.push(spawn_packet.clone());
+			if other.extensions.contains(ExtBitmask::ChangeModel) {
+				other.packets_to_send.extend(model_packet.clone());
+			}
+		}
+	}
+	data.send_level_status(own_id);
+	data.send_online_status();
+	send_messages(data, username, |locale| {
+		vec![tr!(locale, "command.goto.success", target_level)]
+	});
+}
+
+/// sends messages to the player with the given username if they're still connected, built for their locale
+fn send_messages(
+	data: &mut ServerData,
+	username: &str,
+	messages: impl FnOnce(&str) -> Vec<String>,
+) {
+	let Some(player) = data.players.iter_mut().find(|p| p.username == username) else {
+		return;
+	};
+	for message in messages(&player.locale) {
+		player.packets_to_send.push(ServerPacket::Message {
+			player_id: -1,
+			message,
+		});
+	}
+}
+
+/// teleports a player within their level, telling them why with the given message
+///
+/// returns false if the player couldn't be found
+fn teleport_player(
+	data: &mut ServerData,
+	username: &str,
+	(x, y, z, yaw, pitch): (f16, f16, f16, Option<u8>, Option<u8>),
+	message: impl Fn(&str) -> String,
+) -> bool {
+	let Ok(player) = data.find_player_mut(username) else {
+		return false;
+	};
+	player.previous_position = Some((player.x, player.y, player.z, player.yaw, player.pitch));
+	let yaw = yaw.unwrap_or(player.yaw);
+	let pitch = pitch.unwrap_or(player.pitch);
+	player.x = x;
+	player.y = y;
+	player.z = z;
+	player.yaw = yaw;
+	player.pitch = pitch;
+	let packet = ServerPacket::SetPositionOrientation {
+		player_id: player.id,
+		x,
+		y,
+		z,
+		yaw,
+		pitch,
+	};
+	let ext_packet = ServerPacket::ExtEntityTeleport {
+		entity_id: player.id,
+		teleport_behavior: TeleportBehavior::UsePosition
+			| TeleportBehavior::UseOrientation
+			| TeleportBehavior::ModeInterpolated,
+		x,
+		y,
+		z,
+		yaw,
+		pitch,
+	};
+	let id = player.id;
+	let teleported_level = player.current_level.clone();
+
+	for player in data
+		.players
+		.iter_mut()
+		.filter(|p| p.current_level == teleported_level)
+	{
+		let mut packet = if player.extensions.contains(ExtBitmask::ExtEntityTeleport) {
+			ext_packet.clone()
+		} else {
+			packet.clone()
+		};
+		if player.id == id {
+			packet.set_player_id(-1);
+			player.packets_to_send.push(ServerPacket::Message {
+				player_id: -1,
+				message: message(&player.locale),
+			});
+		}
+		player.packets_to_send.push(packet);
+	}
+	true
+}
+
+/// moves a player's permissions up or down one rank, going through /setperm so the same restrictions apply
+fn step_permissions(
+	data: &mut ServerData,
+	own_id: i8,
+	username: &str,
+	promote: bool,
+) -> Vec<String> {
+	let player = data.player(own_id).expect("missing player");
+	let locale = player.locale.clone();
+	let own_username = player.username.clone();
+	let username = data.resolve_username(username);
+	if username == own_username {
+		return vec![tr!(&locale, "command.setperm.self")];
+	}
+	let current = data
+		.config
+		.player_perms
+		.get(&username)
+		.copied()
+		.unwrap_or_default();
+	let permissions = if promote {
+		current.promoted()
+	} else {
+		current.demoted()
+	};
+	let Some(permissions) = permissions else {
+		let key = if promote {
+			"command.promote.highest"
+		} else {
+			"command.demote.lowest"
+		};
+		return vec![tr!(&locale, key, username)];
+	};
+	Command::SetPermissions {
+		player_username: Cow::Owned(username),
+		permissions,
+	}
+	.process(data, own_id)
+}
+
+/// builds a colored disconnect reason for a player being removed by staff, optionally naming the staff member
+fn staff_disconnect_reason(
+	locale: &str,
+	action: &str,
+	staff: Option<&str>,
+	reason: Option<&str>,
+) -> String {
+	let reason = reason
+		.map(|r| r.to_string())
+		.unwrap_or_else(|| tr!(locale, "command.no_reason"));
+	let message = if let Some(staff) = staff {
+		locale::translate(
+			locale,
+			&format!("command.{action}.reason_by"),
+			&[staff.to_string(), reason],
+		)
+	} else {
+		locale::translate(locale, &format!("command.{action}.reason"), &[reason])
+	};
+	truncate_to_string_length(&message)
+}
+
+/// parses a color given as hex, such as `#ff8000`
+fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
+	let s = s.strip_prefix('#').unwrap_or(s);
+	if s.len() != 6 || !s.is_ascii() {
+		return None;
+	}
+	let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+	Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// joins items with commas, wrapping them across as many messages as needed to fit the message length
+fn wrap_list<'a>(prefix: &str, items: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+	let mut msgs = Vec::new();
+	let mut current_message = prefix.to_string();
+	let mut first = true;
+	for item in items {
+		if current_message.len() + 3 + item.len() > STRING_LENGTH {
+			msgs.push(format!("{current_message},"));
+			current_message = "&f".to_string();
+			first = true;
+		}
+		if first {
+			current_message = format!("{current_message}{item}");
+			first = false;
+		} else {
+			current_message = format!("{current_message}, {item}");
+		}
+	}
+	msgs.push(current_message);
+	msgs
+}
+
+/// starts a selection for the given player, replacing any selection already in progress
+fn start_selection(data: &mut ServerData, own_id: i8, action: SelectionAction) {
+	if let Some(player) = data.player_mut(own_id) {
+		player.pending_selection = Some(PendingSelection::new(action));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{sync::Arc, time::Duration};
+
+	use half::f16;
+	use tokio::sync::RwLock;
+
+	use crate::{
+		level::{Level, WeatherType},
+		locale::{Translatable, DEFAULT_LOCALE, TEST_LOCALE},
+		packet::STRING_LENGTH,
+		player::{PlayerSpawn, PlayerType},
+		server::testing::{finish_pending_commands, test_addr, test_data, test_dir, TestClient},
+	};
+
+	use super::{staff_disconnect_reason, Command, Coordinate};
+
+	#[test]
+	fn next_string_splits_on_spaces() {
+		let mut args = "a b";
+		assert_eq!(Command::next_string(&mut args).unwrap(), "a");
+		assert_eq!(args, "b");
+		assert_eq!(Command::next_string(&mut args).unwrap(), "b");
+		assert_eq!(args, "");
+	}
+
+	#[test]
+	fn next_string_unescapes_quotes() {
+		let mut args = r#""a \" b" c"#;
+		assert_eq!(Command::next_string(&mut args).unwrap(), r#"a " b"#);
+		assert_eq!(args, "c");
+	}
+
+	#[test]
+	fn next_coordinate_mixes_absolute_and_relative() {
+		let mut args = "10 ~ ~-2.5 ~3";
+		let coordinates: Vec<_> = (0..4)
+			.map(|_| Command::next_coordinate(&mut args).unwrap())
+			.collect();
+		assert_eq!(
+			coordinates,
+			[
+				Coordinate::Absolute(10.0),
+				Coordinate::Relative(0.0),
+				Coordinate::Relative(-2.5),
+				Coordinate::Relative(3.0),
+			]
+		);
+		assert_eq!(args, "");
+		assert_eq!(
+			coordinates
+				.iter()
+				.map(|c| c.resolve(4.0))
+				.collect::<Vec<_>>(),
+			[10.0, 4.0, 1.5, 7.0]
+		);
+
+		let mut args = "~a 5";
+		assert_eq!(
+			Command::next_coordinate(&mut args),
+			Err(Translatable::new("command.expected_number"))
+		);
+	}
+
+	#[test]
+	fn next_string_rejects_unterminated_quotes() {
+		let mut args = r#""abc"#;
+		assert_eq!(
+			Command::next_string(&mut args),
+			Err(Translatable::new("command.unterminated_quote"))
+		);
+	}
+
+	#[test]
+	fn next_string_ignores_trailing_spaces() {
+		let mut args = "a   ";
+		assert_eq!(Command::next_string(&mut args).unwrap(), "a");
+		assert_eq!(
+			Command::next_string(&mut args),
+			Err(Translatable::new("command.missing_argument"))
+		);
+
+		let mut args = r#"  "a b"   "#;
+		assert_eq!(Command::next_string(&mut args).unwrap(), "a b");
+		assert_eq!(args, "");
+	}
+
+	#[test]
+	fn staff_disconnect_reasons_are_colored() {
+		assert_eq!(
+			staff_disconnect_reason(DEFAULT_LOCALE, "kick", None, Some("griefing")),
+			"&cKicked: &fgriefing"
+		);
+		assert_eq!(
+			staff_disconnect_reason(DEFAULT_LOCALE, "ban", Some("Alice"), None),
+			"&cBanned by Alice: &f<no message>"
+		);
+
+		let reason = "x".repeat(100);
+		let message = staff_disconnect_reason(DEFAULT_LOCALE, "kick", Some("Alice"), Some(&reason));
+		assert_eq!(message.len(), STRING_LENGTH);
+		assert!(message.starts_with("&cKicked by Alice: &fxxx"));
+	}
+
+	#[tokio::test]
+	async fn replies_use_the_players_locale() {
+		let data = Arc::new(RwLock::new(test_data()));
+		let mut bob = TestClient::join(&data, test_addr(2), "bob").await;
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		bob.packets().await;
+		alice.packets().await;
+
+		alice.chat(&format!("/lang {TEST_LOCALE}")).await;
+		assert_eq!(alice.messages().await, ["Langue: test"]);
+		alice.chat("/stop").await;
+		assert_eq!(alice.messages().await, ["&cVous n'avez pas la permission"]);
+		bob.chat("/stop").await;
+		// bob may also be sent alice joining along with his reply
+		assert!(bob
+			.messages()
+			.await
+			.contains(&"&cPermissions do not allow you to use this command".to_string()));
+	}
+
+	#[tokio::test]
+	async fn goto_sends_blocks_changed_while_loading() {
+		let mut data = test_data();
+		data.levels.insert("other".to_string(), Level::new(8, 8, 8));
+		let data = Arc::new(RwLock::new(data));
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		alice.packets().await;
+
+		alice.chat("/goto other").await;
+		while data.read().await.pending_commands.is_empty() {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+		// nothing changes for the player until the level is ready
+		assert!(alice.packets().await.is_empty());
+		{
+			let mut data = data.write().await;
+			let level = data.levels.get_mut("other").unwrap();
+			let index = level.index(1, 2, 3);
+			level.blocks[index] = 1;
+		}
+		finish_pending_commands(&data).await;
+
+		let packets = alice.packets().await;
+		let finalize = packets
+			.iter()
+			.position(|p| p.id == 0x04)
+			.expect("level should be sent");
+		assert_eq!(packets[finalize].body, [0, 8, 0, 8, 0, 8]);
+		assert!(packets[finalize..]
+			.iter()
+			.any(|p| p.id == 0x06 && p.body == [0, 1, 0, 2, 0, 3, 1]));
+		assert!(packets
+			.iter()
+			.any(|p| p.id == 0x0d && p.string_at(1) == "Moved to other."));
+		assert_eq!(data.read().await.players[0].current_level, "other");
+	}
+
+	#[tokio::test]
+	async fn newworld_adds_the_level_once_generated() {
+		let data = Arc::new(RwLock::new(test_data()));
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		alice.packets().await;
+		data.write().await.players[0].permissions = PlayerType::Operator;
+
+		alice.chat("/newworld big 32 16 32 flat 5").await;
+		assert_eq!(alice.messages().await, ["Generating level big..."]);
+		alice.chat("/newworld big 32 16 32 flat 5").await;
+		assert_eq!(
+			alice.messages().await,
+			["&cA level named big already exists!"]
+		);
+		assert!(!data.read().await.levels.contains_key("big"));
+
+		finish_pending_commands(&data).await;
+		assert_eq!(
+			alice.messages().await,
+			["Created level big, use /goto to visit it.", "&7Seed: 5"]
+		);
+		let data = data.read().await;
+		let level = &data.levels["big"];
+		assert_eq!((level.x_size, level.y_size, level.z_size), (32, 16, 32));
+		assert_eq!(level.seed, Some(5));
+		assert!(level.save_now);
+	}
+
+	#[tokio::test]
+	async fn previews_are_capped_and_generated_in_the_background() {
+		let mut data = test_data();
+		let main_level = data.config.level_name.clone();
+		data.levels.insert(main_level, Level::new(200, 16, 200));
+		let data = Arc::new(RwLock::new(data));
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		alice.packets().await;
+		data.write().await.players[0].permissions = PlayerType::Operator;
+
+		alice.chat("/generate preview 7 flat").await;
+		alice.chat("/generate preview 8 flat").await;
+		assert_eq!(
+			alice.messages().await,
+			["&cYour last preview hasn't finished yet!"]
+		);
+
+		finish_pending_commands(&data).await;
+		let messages = alice.messages().await;
+		assert_eq!(messages[0], "Blocks from seed 7 at 128x16x128:");
+		assert!(messages[1].starts_with("&7"));
+	}
+
+	#[tokio::test]
+	async fn clear_resets_connected_players() {
+		let data = Arc::new(RwLock::new(test_data()));
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		let mut bob = TestClient::join(&data, test_addr(2), "bob").await;
+		alice.packets().await;
+		bob.packets().await;
+		{
+			let mut data = data.write().await;
+			data.players[0].permissions = PlayerType::Operator;
+			data.players[1].savable_data.spawn = Some(PlayerSpawn {
+				x: f16::from_f32(1.0),
+				y: f16::from_f32(2.0),
+				z: f16::from_f32(3.0),
+				yaw: 0,
+				pitch: 0,
+			});
+		}
+
+		alice.chat("/clear bob").await;
+		assert_eq!(alice.messages().await, ["Cleared bob's saved data."]);
+		assert!(data.read().await.players[1].savable_data.spawn.is_none());
+
+		data.write().await.players[1].permissions = PlayerType::Operator;
+		alice.chat("/clear bob").await;
+		assert_eq!(
+			alice.messages().await,
+			["&cThis player outranks or is the same rank as you"]
+		);
+	}
+
+	#[tokio::test]
+	async fn weather_is_saved_with_the_level() {
+		let data = Arc::new(RwLock::new(test_data()));
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		alice.packets().await;
+		data.write().await.players[0].permissions = PlayerType::Operator;
+
+		alice.chat("/weather raining").await;
+		assert_eq!(alice.messages().await, ["Weather updated!"]);
+		let path = test_dir("weather-saved");
+		{
+			let mut data = data.write().await;
+			let main_level = data.config.level_name.clone();
+			let level = data.levels.get_mut(&main_level).unwrap();
+			assert!(level.save_now);
+			level
+				.save(&path, flate2::Compression::default())
+				.await
+				.unwrap();
+		}
+		let loaded = Level::load(&path).await.unwrap();
+		assert!(matches!(loaded.weather, WeatherType::Raining));
+		std::fs::remove_dir_all(path).unwrap();
+	}
+}
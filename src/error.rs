@@ -5,6 +5,12 @@ pub enum GeneralError {
 	Io(#[from] std::io::Error),
 	#[error(transparent)]
 	Json(#[from] serde_json::Error),
+	#[error(transparent)]
+	NbtIo(#[from] quartz_nbt::io::NbtIoError),
+	#[error(transparent)]
+	NbtRepr(#[from] quartz_nbt::NbtReprError),
+	#[error(transparent)]
+	Join(#[from] tokio::task::JoinError),
 	#[error("{0}")]
 	Custom(String),
 	#[allow(unused)]
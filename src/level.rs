@@ -1,22 +1,46 @@
 use std::{
 	collections::{BTreeMap, BTreeSet},
-	io::{Read, Write},
-	path::Path,
+	io::{ErrorKind, Read, Write},
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
 };
 
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
 
 use crate::{
-	error::GeneralError, packet::server::ServerPacket, player::SavablePlayerData, util::neighbors,
+	error::GeneralError,
+	packet::server::ServerPacket,
+	player::{PlayerType, SavablePlayerData},
+	selection::Cuboid,
+	util::{cube_around, NeighborPattern},
 };
 
-use self::block::BLOCK_INFO;
+use self::{
+	block::{is_lava, is_water, BLOCK_INFO, ID_SPONGE, MAX_BYTE_BLOCK_ID},
+	rules::LevelRules,
+};
 
 pub mod block;
+pub mod classic_world;
 pub mod generation;
+pub mod rules;
 
+/// the level's size, rules and other metadata
 const LEVEL_INFO_PATH: &str = "info.json";
+/// the gzipped low bytes of the level's blocks
 const LEVEL_DATA_PATH: &str = "level.dat";
+/// the high bytes of the level's blocks, only saved if the level has blocks with ids above 255
+const LEVEL_HIGH_DATA_PATH: &str = "level_high.dat";
+/// directory holding the level's previous saves
+const LEVEL_BACKUPS_PATH: &str = "backups";
+/// the number of backups kept for each level
+const MAX_LEVEL_BACKUPS: usize = 5;
+/// added to the name of a corrupted level's directory when it's moved aside, followed by a timestamp
+pub const CORRUPTED_LEVEL_MARKER: &str = ".corrupt-";
+/// the length of a full day in a level, in ticks
+pub const DAY_LENGTH_TICKS: u32 = 24000;
 
 /// a classic level
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,17 +54,62 @@ pub struct Level {
 
 	/// the blocks which make up the level
 	#[serde(skip)]
-	pub blocks: Vec<u8>,
+	pub blocks: Vec<u16>,
+	/// the server name shown to players in this level, instead of the configured one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+	/// the motd shown to players in this level, instead of the configured one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub motd: Option<String>,
 	/// the level's weather
 	pub weather: WeatherType,
+	/// the level's custom environment colors, for clients supporting EnvColors
+	#[serde(default)]
+	pub env_colors: BTreeMap<EnvColorType, [u8; 3]>,
+	/// the level's time of day in ticks, starting from midday
+	#[serde(default)]
+	pub time: u32,
+	/// the environment colors currently driven by the level's time of day
+	#[serde(skip)]
+	pub time_colors: BTreeMap<EnvColorType, [u8; 3]>,
+	/// the level's rules
+	#[serde(default)]
+	pub rules: LevelRules,
+	/// whether fluids, falling blocks and other block physics are ticked
+	#[serde(default = "default_physics_enabled")]
+	pub physics_enabled: bool,
+	/// the block string ids shown in players' inventories in order, hiding unlisted blocks, or none for every block in id order
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub inventory_order: Option<Vec<String>>,
+	/// regions of the level which only players of a high enough rank may edit
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub protected_regions: Vec<ProtectedRegion>,
+	/// the seed the level was generated from, if the server generated it
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub seed: Option<u64>,
 
 	/// index of blocks which need to be updated in the next tick
 	pub awaiting_update: BTreeSet<usize>,
 	/// list of updates to apply to the world on the next tick
 	#[serde(skip)]
 	pub updates: Vec<BlockUpdate>,
+	/// ticks remaining before each lit explosive detonates
+	#[serde(skip)]
+	pub fuses: BTreeMap<usize, usize>,
+	/// how far each fluid block has spread from its source, sources and fluids placed by players have no entry
+	#[serde(skip)]
+	pub fluid_distances: BTreeMap<usize, usize>,
 	#[serde(skip)]
 	pub save_now: bool,
+	/// whether the level's blocks have changed since it was last saved
+	#[serde(skip)]
+	pub blocks_dirty: bool,
+	/// the level's info as of its last save, so it isn't rewritten if it hasn't changed
+	#[serde(skip)]
+	saved_info: Option<String>,
+	/// file to export the level to in the ClassicWorld format on the next save check
+	#[serde(skip)]
+	pub export_to: Option<PathBuf>,
 
 	#[serde(default)]
 	pub player_data: BTreeMap<String, SavablePlayerData>,
@@ -54,10 +123,25 @@ impl Level {
 			y_size,
 			z_size,
 			blocks: vec![0; x_size * y_size * z_size],
+			name: None,
+			motd: None,
 			weather: WeatherType::Sunny,
+			env_colors: Default::default(),
+			time: 0,
+			time_colors: Default::default(),
+			rules: Default::default(),
+			physics_enabled: true,
+			inventory_order: None,
+			protected_regions: Vec::new(),
+			seed: None,
 			awaiting_update: Default::default(),
 			updates: Default::default(),
+			fuses: Default::default(),
+			fluid_distances: Default::default(),
 			save_now: false,
+			blocks_dirty: true,
+			saved_info: None,
+			export_to: None,
 			player_data: Default::default(),
 		}
 	}
@@ -71,36 +155,132 @@ impl Level {
 	pub fn coordinates(&self, index: usize) -> (usize, usize, usize) {
 		let y = index / (self.x_size * self.z_size);
 		let z = (index / self.x_size) % self.z_size;
-		let x = index % self.z_size;
+		let x = index % self.x_size;
 		(x, y, z)
 	}
 
 	/// gets the block at the given position
-	pub fn get_block(&self, x: usize, y: usize, z: usize) -> u8 {
+	pub fn get_block(&self, x: usize, y: usize, z: usize) -> u16 {
 		self.blocks[self.index(x, y, z)]
 	}
 
+	/// counts how many of each block the level contains
+	pub fn block_counts(&self) -> BTreeMap<u16, usize> {
+		let mut counts = BTreeMap::new();
+		for block in &self.blocks {
+			*counts.entry(*block).or_default() += 1;
+		}
+		counts
+	}
+
+	/// gets the height of the highest non-air block in the given column, if there is one
+	pub fn surface_height(&self, x: usize, z: usize) -> Option<usize> {
+		if x >= self.x_size || z >= self.z_size {
+			return None;
+		}
+		(0..self.y_size)
+			.rev()
+			.find(|y| self.get_block(x, *y, z) != 0)
+	}
+
+	/// gets the lowest height at or above the given one where a player fits in the given column, counting space above the level as clear
+	///
+	/// returns `None` if the column is outside the level
+	pub fn clear_height_above(&self, x: usize, y: usize, z: usize) -> Option<usize> {
+		if x >= self.x_size || z >= self.z_size {
+			return None;
+		}
+		let passable = |y: usize| {
+			y >= self.y_size
+				|| BLOCK_INFO
+					.get(&self.get_block(x, y, z))
+					.is_none_or(|info| info.block_type.is_passable())
+		};
+		(y..).find(|y| passable(*y) && passable(y + 1))
+	}
+
+	/// gets the rank needed to edit the block at the given position, based on the regions protecting it
+	pub fn edit_permissions(&self, x: usize, y: usize, z: usize) -> PlayerType {
+		self.protected_regions
+			.iter()
+			.filter(|region| region.cuboid.contains(x, y, z))
+			.map(|region| region.permissions)
+			.max()
+			.unwrap_or_default()
+	}
+
 	/// sets the block at the given position
-	pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u8) {
+	pub fn set_block(&mut self, x: usize, y: usize, z: usize, block: u16) {
 		let index = self.index(x, y, z);
 		self.blocks[index] = block;
+		self.blocks_dirty = true;
+	}
+
+	/// splits the level's blocks into their low bytes and, if any block needs them, their high bytes
+	pub fn block_planes(&self) -> (Vec<u8>, Option<Vec<u8>>) {
+		let low = self.blocks.iter().map(|b| *b as u8).collect();
+		let high = self
+			.blocks
+			.iter()
+			.any(|b| *b > MAX_BYTE_BLOCK_ID)
+			.then(|| self.blocks.iter().map(|b| (*b >> 8) as u8).collect());
+		(low, high)
+	}
+
+	/// sets the level's blocks from their low bytes and optionally their high bytes
+	pub fn set_block_planes(&mut self, low: &[u8], high: Option<&[u8]>) {
+		self.blocks = match high {
+			Some(high) => low
+				.iter()
+				.zip(high)
+				.map(|(low, high)| u16::from_be_bytes([*high, *low]))
+				.collect(),
+			None => low.iter().map(|b| *b as u16).collect(),
+		};
+	}
+
+	/// queues a block change for the next update, marking the block for physics if it needs it
+	pub fn queue_block(&mut self, index: usize, block: u16) {
+		self.updates.push(BlockUpdate { index, block });
+		if BLOCK_INFO
+			.get(&block)
+			.is_some_and(|info| info.block_type.needs_update_on_place())
+		{
+			self.awaiting_update.insert(index);
+		}
 	}
 
 	/// applies the level's queued updates
 	pub fn apply_updates(&mut self) -> Vec<ServerPacket> {
 		self.updates.dedup_by(|a, b| a.index == b.index);
 		let mut packets = Vec::with_capacity(self.updates.len());
+		if !self.updates.is_empty() {
+			self.blocks_dirty = true;
+		}
 
 		for update in std::mem::take(&mut self.updates) {
 			let (x, y, z) = self.coordinates(update.index);
+			let removed_sponge =
+				self.blocks[update.index] == ID_SPONGE && update.block != ID_SPONGE;
 			self.blocks[update.index] = update.block;
+			if !is_water(update.block) && !is_lava(update.block) {
+				self.fluid_distances.remove(&update.index);
+			}
+			if removed_sponge {
+				// let water which was held back by the sponge flow again
+				for (nx, ny, nz) in cube_around(self, x, y, z, self.rules.sponge_radius + 1) {
+					if is_water(self.get_block(nx, ny, nz)) {
+						self.awaiting_update.insert(self.index(nx, ny, nz));
+					}
+				}
+			}
 			packets.push(ServerPacket::SetBlock {
 				x: x as i16,
 				y: y as i16,
 				z: z as i16,
 				block_type: update.block,
 			});
-			for (nx, ny, nz) in neighbors(self, x, y, z) {
+			for (nx, ny, nz) in NeighborPattern::Direct.neighbors(self, x, y, z) {
 				let info = BLOCK_INFO
 					.get(&self.get_block(nx, ny, nz))
 					.expect("missing block");
@@ -113,6 +293,69 @@ impl Level {
 		packets
 	}
 
+	/// lights the fuse of the explosive at the given index if it isn't already lit
+	pub fn light_fuse(&mut self, index: usize, ticks: usize) {
+		if let std::collections::btree_map::Entry::Vacant(entry) = self.fuses.entry(index) {
+			entry.insert(ticks);
+			self.awaiting_update.insert(index);
+		}
+	}
+
+	/// sets the level's time of day, wrapping around to the next day
+	pub fn set_time(&mut self, time: u32) {
+		self.time = time % DAY_LENGTH_TICKS;
+	}
+
+	/// gets how bright the level's daylight is, from 0 at midnight to 1 at midday
+	pub fn daylight(&self) -> f32 {
+		let progress = self.time as f32 / DAY_LENGTH_TICKS as f32;
+		((progress * std::f32::consts::TAU).cos() + 1.0) / 2.0
+	}
+
+	/// gets the environment color shown for the given variable, preferring the level's custom colors over its time of day colors
+	pub fn env_color(&self, variable: EnvColorType) -> Option<[u8; 3]> {
+		self.env_colors
+			.get(&variable)
+			.or_else(|| self.time_colors.get(&variable))
+			.copied()
+	}
+
+	/// updates the colors driven by the level's time of day, returning packets for the shown colors which changed
+	///
+	/// only colors with both a day and night color are driven by the time
+	pub fn update_time_colors(
+		&mut self,
+		day_colors: &BTreeMap<EnvColorType, [u8; 3]>,
+		night_colors: &BTreeMap<EnvColorType, [u8; 3]>,
+	) -> Vec<ServerPacket> {
+		let daylight = self.daylight();
+		let mut packets = Vec::new();
+		for (variable, day) in day_colors {
+			let Some(night) = night_colors.get(variable) else {
+				continue;
+			};
+			let color: [u8; 3] = std::array::from_fn(|i| {
+				(night[i] as f32 + (day[i] as f32 - night[i] as f32) * daylight).round() as u8
+			});
+			if self.time_colors.insert(*variable, color) != Some(color)
+				&& !self.env_colors.contains_key(variable)
+			{
+				packets.push(ServerPacket::EnvSetColor {
+					variable: *variable,
+					color: Some(color),
+				});
+			}
+		}
+		packets
+	}
+
+	/// gets whether the given coordinates are within range of a sponge
+	pub fn is_near_sponge(&self, x: usize, y: usize, z: usize) -> bool {
+		cube_around(self, x, y, z, self.rules.sponge_radius)
+			.into_iter()
+			.any(|(nx, ny, nz)| self.get_block(nx, ny, nz) == ID_SPONGE)
+	}
+
 	/// updates player data for the level
 	pub fn update_player_data(&mut self, player_data: Vec<(String, SavablePlayerData)>) {
 		for (username, data) in player_data {
@@ -120,41 +363,243 @@ impl Level {
 		}
 	}
 
-	/// saves the level
-	pub async fn save<P>(&self, path: P) -> Result<(), GeneralError>
+	/// saves the level, backing up the previous save first
+	pub async fn save<P>(&mut self, path: P, compression: Compression) -> Result<(), GeneralError>
+	where
+		P: AsRef<Path>,
+	{
+		let result = self
+			.start_save(path.as_ref().to_path_buf(), compression)?
+			.await?;
+		if result.is_err() {
+			self.save_failed();
+		}
+		result
+	}
+
+	/// starts saving the level in the background, backing up the previous save first
+	///
+	/// the level's info and blocks are only rewritten if they've changed since the last save,
+	/// if the save fails the caller should call `save_failed` so the next save writes everything again
+	pub fn start_save(
+		&mut self,
+		path: PathBuf,
+		compression: Compression,
+	) -> Result<JoinHandle<Result<(), GeneralError>>, GeneralError> {
+		let info = serde_json::to_string_pretty(self)?;
+		let info = (self.saved_info.as_ref() != Some(&info)
+			|| !path.join(LEVEL_INFO_PATH).exists())
+		.then_some(info);
+		if let Some(info) = &info {
+			self.saved_info = Some(info.clone());
+		}
+		let planes = (self.blocks_dirty || !path.join(LEVEL_DATA_PATH).exists())
+			.then(|| self.block_planes());
+		self.blocks_dirty = false;
+
+		Ok(tokio::spawn(async move {
+			// backing up an unchanged level would only push out older backups
+			if info.is_none() && planes.is_none() {
+				return Ok(());
+			}
+			tokio::fs::create_dir_all(&path).await?;
+			Self::backup(&path).await?;
+			if let Some(info) = info {
+				tokio::fs::write(path.join(LEVEL_INFO_PATH), info).await?;
+			}
+			let Some((low, high)) = planes else {
+				return Ok(());
+			};
+
+			// compressing large levels takes a while, so it's kept off the async runtime
+			let (low, high) = tokio::task::spawn_blocking(move || -> Result<_, GeneralError> {
+				Ok((
+					Self::compress(&low, compression)?,
+					high.map(|high| Self::compress(&high, compression))
+						.transpose()?,
+				))
+			})
+			.await??;
+			tokio::fs::write(path.join(LEVEL_DATA_PATH), low).await?;
+			let high_path = path.join(LEVEL_HIGH_DATA_PATH);
+			if let Some(high) = high {
+				tokio::fs::write(high_path, high).await?;
+			} else if high_path.exists() {
+				tokio::fs::remove_file(high_path).await?;
+			}
+			Ok(())
+		}))
+	}
+
+	/// marks the level as needing to be written in full, after a save failed
+	pub fn save_failed(&mut self) {
+		self.blocks_dirty = true;
+		self.saved_info = None;
+	}
+
+	/// gzips the given level data
+	fn compress(data: &[u8], compression: Compression) -> Result<Vec<u8>, GeneralError> {
+		let mut encoder = flate2::write::GzEncoder::new(Vec::new(), compression);
+		encoder.write_all(data)?;
+		Ok(encoder.finish()?)
+	}
+
+	/// reads and decompresses the gzipped level data at the given path
+	async fn decompress(path: &Path) -> Result<Vec<u8>, GeneralError> {
+		let data = tokio::fs::read(path).await?;
+		let mut decoder = flate2::read::GzDecoder::new(data.as_slice());
+		let mut decoded = Vec::new();
+		decoder.read_to_end(&mut decoded)?;
+		Ok(decoded)
+	}
+
+	/// copies the level's current save into its backups directory, removing the oldest backups past the limit
+	async fn backup(path: &Path) -> Result<(), GeneralError> {
+		let info_path = path.join(LEVEL_INFO_PATH);
+		let data_path = path.join(LEVEL_DATA_PATH);
+		if !info_path.exists() || !data_path.exists() {
+			return Ok(());
+		}
+
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_millis();
+		let backup_path = path.join(LEVEL_BACKUPS_PATH).join(timestamp.to_string());
+		tokio::fs::create_dir_all(&backup_path).await?;
+		tokio::fs::copy(info_path, backup_path.join(LEVEL_INFO_PATH)).await?;
+		tokio::fs::copy(data_path, backup_path.join(LEVEL_DATA_PATH)).await?;
+		let high_data_path = path.join(LEVEL_HIGH_DATA_PATH);
+		if high_data_path.exists() {
+			tokio::fs::copy(high_data_path, backup_path.join(LEVEL_HIGH_DATA_PATH)).await?;
+		}
+
+		for old_backup in Self::backups(path)
+			.await?
+			.into_iter()
+			.skip(MAX_LEVEL_BACKUPS)
+		{
+			tokio::fs::remove_dir_all(old_backup).await?;
+		}
+
+		Ok(())
+	}
+
+	/// gets the paths of the level's backups, newest first
+	async fn backups(path: &Path) -> Result<Vec<PathBuf>, GeneralError> {
+		let backups_path = path.join(LEVEL_BACKUPS_PATH);
+		if !backups_path.exists() {
+			return Ok(Vec::new());
+		}
+
+		let mut backups = Vec::new();
+		let mut entries = tokio::fs::read_dir(backups_path).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			if let Some(timestamp) = entry
+				.file_name()
+				.to_str()
+				.and_then(|n| n.parse::<u128>().ok())
+			{
+				backups.push((timestamp, entry.path()));
+			}
+		}
+		backups.sort_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+
+		Ok(backups.into_iter().map(|(_, path)| path).collect())
+	}
+
+	/// loads the level, falling back to the most recent valid backup if the level is corrupted
+	///
+	/// returns `None` if the level is corrupted and none of its backups could be loaded,
+	/// other errors such as failing to read the level's files are returned as they are
+	pub async fn load_or_recover<P>(path: P) -> Result<Option<Self>, GeneralError>
+	where
+		P: AsRef<Path>,
+	{
+		let path = path.as_ref();
+		let error = match Self::load(path).await {
+			Ok(level) => return Ok(Some(level)),
+			Err(e) if Self::is_corruption(&e) => e,
+			Err(e) => return Err(e),
+		};
+		eprintln!("failed to load level at {}: {error}", path.display());
+
+		for backup in Self::backups(path).await? {
+			match Self::load(&backup).await {
+				Ok(mut level) => {
+					println!("recovered level from backup at {}", backup.display());
+					// the corrupted save is only replaced if the whole level is written again
+					level.save_failed();
+					return Ok(Some(level));
+				}
+				Err(e) => eprintln!("backup at {} is also invalid: {e}", backup.display()),
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// gets whether an error from loading a level means its save is corrupted, rather than that it couldn't be read
+	fn is_corruption(error: &GeneralError) -> bool {
+		match error {
+			GeneralError::Json(_) | GeneralError::Custom(_) => true,
+			// gzip checksum and format errors, along with missing or truncated files
+			GeneralError::Io(e) => matches!(
+				e.kind(),
+				ErrorKind::InvalidData
+					| ErrorKind::InvalidInput
+					| ErrorKind::UnexpectedEof
+					| ErrorKind::NotFound
+			),
+			_ => false,
+		}
+	}
+
+	/// moves a corrupted level's directory aside so a new level can take its place, returning where it was moved
+	pub async fn move_corrupted<P>(path: P) -> Result<PathBuf, GeneralError>
 	where
 		P: AsRef<Path>,
 	{
 		let path = path.as_ref();
-		tokio::fs::create_dir_all(path).await?;
-		tokio::fs::write(
-			path.join(LEVEL_INFO_PATH),
-			serde_json::to_string_pretty(self)?,
-		)
-		.await?;
-		let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
-		encoder.write_all(&self.blocks)?;
-		Ok(tokio::fs::write(path.join(LEVEL_DATA_PATH), encoder.finish()?).await?)
+		let timestamp = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_secs();
+		let mut name = path.file_name().unwrap_or_default().to_os_string();
+		name.push(format!("{CORRUPTED_LEVEL_MARKER}{timestamp}"));
+		let corrupted_path = path.with_file_name(name);
+		tokio::fs::rename(path, &corrupted_path).await?;
+		Ok(corrupted_path)
 	}
 
 	/// loads the level
+	///
+	/// the level data's integrity is checked by the gzip checksum and the expected level size
 	pub async fn load<P>(path: P) -> Result<Self, GeneralError>
 	where
 		P: AsRef<Path>,
 	{
 		let path = path.as_ref();
-		let mut info: Self =
-			serde_json::from_str(&tokio::fs::read_to_string(path.join(LEVEL_INFO_PATH)).await?)?;
-		let blocks_data = tokio::fs::read(path.join(LEVEL_DATA_PATH)).await?;
-		let mut decoder = flate2::read::GzDecoder::new(blocks_data.as_slice());
-		decoder.read_to_end(&mut info.blocks)?;
+		let saved_info = tokio::fs::read_to_string(path.join(LEVEL_INFO_PATH)).await?;
+		let mut info: Self = serde_json::from_str(&saved_info)?;
+		let low = Self::decompress(&path.join(LEVEL_DATA_PATH)).await?;
+		let high_path = path.join(LEVEL_HIGH_DATA_PATH);
+		let high = if high_path.exists() {
+			Some(Self::decompress(&high_path).await?)
+		} else {
+			None
+		};
 		let len = info.x_size * info.y_size * info.z_size;
-		if info.blocks.len() != len {
-			panic!(
-				"level data is not correct size! expected {len}, got {}",
-				info.blocks.len()
-			);
+		for data in std::iter::once(&low).chain(&high) {
+			if data.len() != len {
+				return Err(GeneralError::Custom(format!(
+					"level data is not correct size! expected {len}, got {}",
+					data.len()
+				)));
+			}
 		}
+		info.set_block_planes(&low, high.as_deref());
+		info.saved_info = Some(saved_info);
 		Ok(info)
 	}
 }
@@ -165,24 +610,35 @@ pub struct BlockUpdate {
 	/// the index of the block to be updated
 	pub index: usize,
 	/// the block type to set the block to
-	pub block: u8,
+	pub block: u16,
+}
+
+/// a region of a level which only players of at least a given rank may edit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtectedRegion {
+	/// the blocks covered by the region
+	pub cuboid: Cuboid,
+	/// the rank needed to edit blocks in the region
+	pub permissions: PlayerType,
+}
+
+/// levels have physics enabled unless they've been paused
+fn default_physics_enabled() -> bool {
+	true
 }
 
 /// weather types for a level
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, strum::EnumString, strum::IntoStaticStr)]
+#[derive(
+	Debug, Default, Clone, Copy, Serialize, Deserialize, strum::EnumString, strum::IntoStaticStr,
+)]
 #[strum(ascii_case_insensitive)]
 pub enum WeatherType {
+	#[default]
 	Sunny,
 	Raining,
 	Snowing,
 }
 
-impl Default for WeatherType {
-	fn default() -> Self {
-		Self::Sunny
-	}
-}
-
 impl From<&WeatherType> for u8 {
 	fn from(value: &WeatherType) -> Self {
 		match value {
@@ -193,6 +649,42 @@ impl From<&WeatherType> for u8 {
 	}
 }
 
+/// parts of the environment which can be recolored with EnvColors
+#[derive(
+	Debug,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	Serialize,
+	Deserialize,
+	strum::EnumString,
+	strum::IntoStaticStr,
+	strum::EnumIter,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum EnvColorType {
+	Sky,
+	Cloud,
+	Fog,
+	Shadow,
+	Sunlight,
+}
+
+impl From<&EnvColorType> for u8 {
+	fn from(value: &EnvColorType) -> Self {
+		match value {
+			EnvColorType::Sky => 0,
+			EnvColorType::Cloud => 1,
+			EnvColorType::Fog => 2,
+			EnvColorType::Shadow => 3,
+			EnvColorType::Sunlight => 4,
+		}
+	}
+}
+
 impl From<u8> for WeatherType {
 	fn from(value: u8) -> Self {
 		match value {
@@ -202,3 +694,116 @@ impl From<u8> for WeatherType {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use flate2::Compression;
+
+	use crate::server::testing::test_dir;
+
+	use super::{Level, WeatherType, CORRUPTED_LEVEL_MARKER, LEVEL_BACKUPS_PATH, LEVEL_DATA_PATH};
+
+	#[test]
+	fn index_and_coordinates_round_trip() {
+		let level = Level::new(128, 64, 256);
+		for (x, y, z) in [
+			(0, 0, 0),
+			(127, 0, 0),
+			(0, 63, 0),
+			(0, 0, 255),
+			(127, 63, 255),
+			(5, 17, 200),
+		] {
+			let index = level.index(x, y, z);
+			assert!(index < level.blocks.len());
+			assert_eq!(level.coordinates(index), (x, y, z));
+		}
+		for index in (0..level.blocks.len()).step_by(997) {
+			let (x, y, z) = level.coordinates(index);
+			assert_eq!(level.index(x, y, z), index);
+		}
+	}
+
+	#[tokio::test]
+	async fn corrupted_levels_are_recovered_from_backups() {
+		let path = test_dir("level-backups");
+		let mut level = Level::new(8, 8, 8);
+		level.set_block(1, 2, 3, 1);
+		level.save(&path, Compression::default()).await.unwrap();
+		// saving again backs up the first save
+		level.set_block(1, 2, 3, 4);
+		level.save(&path, Compression::default()).await.unwrap();
+
+		std::fs::write(path.join(LEVEL_DATA_PATH), b"not a level").unwrap();
+		assert!(Level::load(&path).await.is_err());
+		let mut recovered = Level::load_or_recover(&path).await.unwrap().unwrap();
+		assert_eq!(recovered.get_block(1, 2, 3), 1);
+
+		// saving the recovered level replaces the corrupted save, even though nothing's changed since loading it
+		recovered.save(&path, Compression::default()).await.unwrap();
+		assert_eq!(Level::load(&path).await.unwrap().get_block(1, 2, 3), 1);
+
+		// saving an unchanged level doesn't push out older backups
+		let backups = || {
+			std::fs::read_dir(path.join(LEVEL_BACKUPS_PATH))
+				.unwrap()
+				.count()
+		};
+		let backup_count = backups();
+		recovered.save(&path, Compression::default()).await.unwrap();
+		assert_eq!(backups(), backup_count);
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn corrupted_levels_without_backups_are_moved_aside() {
+		let path = test_dir("level-no-backups");
+		let level_path = path.join("level");
+		Level::new(8, 8, 8)
+			.save(&level_path, Compression::default())
+			.await
+			.unwrap();
+		std::fs::write(level_path.join(LEVEL_DATA_PATH), b"not a level").unwrap();
+		assert!(Level::load_or_recover(&level_path).await.unwrap().is_none());
+
+		let corrupted_path = Level::move_corrupted(&level_path).await.unwrap();
+		assert!(!level_path.exists());
+		assert!(corrupted_path
+			.file_name()
+			.unwrap()
+			.to_str()
+			.unwrap()
+			.starts_with(&format!("level{CORRUPTED_LEVEL_MARKER}")));
+		assert!(corrupted_path.join(LEVEL_DATA_PATH).exists());
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn levels_reload_after_saves_skipping_blocks() {
+		let path = test_dir("level-unchanged-blocks");
+		let mut level = Level::new(8, 8, 8);
+		level.set_block(1, 2, 3, 1);
+		level.save(&path, Compression::default()).await.unwrap();
+		let modified = || {
+			std::fs::metadata(path.join(LEVEL_DATA_PATH))
+				.unwrap()
+				.modified()
+				.unwrap()
+		};
+		let saved_at = modified();
+
+		// only the level's info changes, so its blocks aren't written again
+		level.weather = WeatherType::Raining;
+		assert!(!level.blocks_dirty);
+		level.save(&path, Compression::best()).await.unwrap();
+		assert_eq!(modified(), saved_at);
+
+		let loaded = Level::load(&path).await.unwrap();
+		assert_eq!(loaded.blocks, level.blocks);
+		assert!(matches!(loaded.weather, WeatherType::Raining));
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+}
@@ -2,23 +2,60 @@ use std::{collections::BTreeMap, sync::LazyLock};
 
 use internment::Intern;
 
-use crate::player::PlayerType;
+use crate::{player::PlayerType, util::NeighborPattern};
+
+use self::definition::{block_definitions, BlockDefinition, BlockSolidity};
+
+pub mod definition;
 
 /// the level of custom blocks supported by the server
 pub const CUSTOM_BLOCKS_SUPPORT_LEVEL: u8 = 1;
+/// the highest block id in classic
+pub const MAX_CLASSIC_BLOCK_ID: u16 = 0x31;
+/// the highest block id from the CustomBlocks extension
+pub const MAX_CUSTOM_BLOCK_ID: u16 = 0x41;
+/// the highest block id which fits in a single byte
+pub const MAX_BYTE_BLOCK_ID: u16 = 0xff;
+/// the highest block id supported with the ExtendedBlocks extension
+pub const MAX_EXTENDED_BLOCK_ID: u16 = 0x2ff;
 
-pub const ID_STONE: u8 = 0x01;
-pub const ID_WATER_FLOWING: u8 = 0x08;
-pub const ID_WATER_STATIONARY: u8 = 0x09;
-pub const ID_LAVA_FLOWING: u8 = 0x0a;
-pub const ID_LAVA_STATIONARY: u8 = 0x0b;
+pub const ID_STONE: u16 = 0x01;
+pub const ID_GRASS: u16 = 0x02;
+pub const ID_DIRT: u16 = 0x03;
+pub const ID_COBBLESTONE: u16 = 0x04;
+pub const ID_BEDROCK: u16 = 0x07;
+pub const ID_WATER_FLOWING: u16 = 0x08;
+pub const ID_WATER_STATIONARY: u16 = 0x09;
+pub const ID_LAVA_FLOWING: u16 = 0x0a;
+pub const ID_LAVA_STATIONARY: u16 = 0x0b;
+pub const ID_WOOD: u16 = 0x11;
+pub const ID_LEAVES: u16 = 0x12;
+pub const ID_SPONGE: u16 = 0x13;
+pub const ID_TNT: u16 = 0x2e;
+pub const ID_OBSIDIAN: u16 = 0x31;
 
-/// information about all blocks implemented
-pub static BLOCK_INFO: LazyLock<BTreeMap<u8, BlockInfo>> = LazyLock::new(|| {
-	[
+/// the number of ticks before tnt broken by a player explodes
+pub const TNT_FUSE_TICKS: usize = 40;
+/// the number of ticks before tnt caught in another explosion explodes
+pub const TNT_CHAIN_FUSE_TICKS: usize = 10;
+
+/// information about all blocks implemented, including the server's block definitions
+pub static BLOCK_INFO: LazyLock<BTreeMap<u16, BlockInfo>> = LazyLock::new(|| {
+	let mut blocks = builtin_blocks();
+	blocks.extend(
+		block_definitions()
+			.iter()
+			.map(|definition| (definition.id, BlockInfo::from_definition(definition))),
+	);
+	blocks.into_iter().collect()
+});
+
+/// gets information about the blocks built into the server
+fn builtin_blocks() -> Vec<(u16, BlockInfo)> {
+	vec![
 		(0x00, BlockInfo::new("air").block_type(BlockType::NonSolid)),
 		(ID_STONE, BlockInfo::new("stone")),
-		(0x02, BlockInfo::new("grass")),
+		(ID_GRASS, BlockInfo::new("grass")),
 		(0x03, BlockInfo::new("dirt")),
 		(0x04, BlockInfo::new("cobblestone")),
 		(0x05, BlockInfo::new("planks")),
@@ -27,7 +64,7 @@ pub static BLOCK_INFO: LazyLock<BTreeMap<u8, BlockInfo>> = LazyLock::new(|| {
 			BlockInfo::new("sapling").block_type(BlockType::NonSolid),
 		),
 		(
-			0x07,
+			ID_BEDROCK,
 			BlockInfo::new("bedrock").perm(PlayerType::Moderator, PlayerType::Moderator),
 		),
 		(
@@ -60,14 +97,20 @@ pub static BLOCK_INFO: LazyLock<BTreeMap<u8, BlockInfo>> = LazyLock::new(|| {
 				.block_type(BlockType::FluidStationary { moving: 0x0a })
 				.perm(PlayerType::Moderator, PlayerType::Normal),
 		),
-		(0x0c, BlockInfo::new("sand")),
-		(0x0d, BlockInfo::new("gravel")),
+		(0x0c, BlockInfo::new("sand").block_type(BlockType::Falling)),
+		(
+			0x0d,
+			BlockInfo::new("gravel").block_type(BlockType::Falling),
+		),
 		(0x0e, BlockInfo::new("gold_ore")),
 		(0x0f, BlockInfo::new("iron_ore")),
 		(0x10, BlockInfo::new("coal_ore")),
-		(0x11, BlockInfo::new("wood")),
-		(0x12, BlockInfo::new("leaves")),
-		(0x13, BlockInfo::new("sponge")),
+		(ID_WOOD, BlockInfo::new("wood")),
+		(ID_LEAVES, BlockInfo::new("leaves")),
+		(
+			ID_SPONGE,
+			BlockInfo::new("sponge").block_type(BlockType::Sponge),
+		),
 		(0x14, BlockInfo::new("glass")),
 		(0x15, BlockInfo::new("cloth_red")),
 		(0x16, BlockInfo::new("cloth_orange")),
@@ -103,7 +146,10 @@ pub static BLOCK_INFO: LazyLock<BTreeMap<u8, BlockInfo>> = LazyLock::new(|| {
 		(0x2b, BlockInfo::new("double_slab")),
 		(0x2c, BlockInfo::new("slab").block_type(BlockType::Slab)),
 		(0x2d, BlockInfo::new("bricks")),
-		(0x2e, BlockInfo::new("tnt")),
+		(
+			ID_TNT,
+			BlockInfo::new("tnt").block_type(BlockType::Explosive),
+		),
 		(0x2f, BlockInfo::new("bookshelf")),
 		(0x30, BlockInfo::new("mossy_cobblestone")),
 		(0x31, BlockInfo::new("obsidian")),
@@ -145,17 +191,26 @@ pub static BLOCK_INFO: LazyLock<BTreeMap<u8, BlockInfo>> = LazyLock::new(|| {
 		(0x40, BlockInfo::new("crate").fallback(0x05)),
 		(0x41, BlockInfo::new("stone_brick").fallback(0x01)),
 	]
-	.into()
-});
+}
 
 /// map of block string ids to their byte ids
-pub static BLOCK_STRING_ID_MAP: LazyLock<BTreeMap<Intern<String>, u8>> = LazyLock::new(|| {
+pub static BLOCK_STRING_ID_MAP: LazyLock<BTreeMap<Intern<String>, u16>> = LazyLock::new(|| {
 	BLOCK_INFO
 		.iter()
 		.map(|(id, info)| (info.str_id, *id))
 		.collect()
 });
 
+/// gets whether the given block id is a water block
+pub fn is_water(id: u16) -> bool {
+	id == ID_WATER_FLOWING || id == ID_WATER_STATIONARY
+}
+
+/// gets whether the given block id is a lava block
+pub fn is_lava(id: u16) -> bool {
+	id == ID_LAVA_FLOWING || id == ID_LAVA_STATIONARY
+}
+
 /// information about a block type
 #[derive(Debug)]
 pub struct BlockInfo {
@@ -168,7 +223,7 @@ pub struct BlockInfo {
 	/// permissions needed to break this block (includes replacing fluids)
 	pub break_permissions: PlayerType,
 	/// the block used as fallback if the client doesn't support it
-	pub fallback: Option<u8>,
+	pub fallback: Option<u16>,
 }
 
 impl BlockInfo {
@@ -183,6 +238,20 @@ impl BlockInfo {
 		}
 	}
 
+	/// creates block info for a block defined by the server owner
+	pub fn from_definition(definition: &BlockDefinition) -> Self {
+		Self {
+			str_id: Intern::new(definition.name.clone()),
+			block_type: match definition.solidity {
+				BlockSolidity::Solid => BlockType::Solid,
+				BlockSolidity::WalkThrough | BlockSolidity::SwimThrough => BlockType::NonSolid,
+			},
+			place_permissions: definition.place_permissions,
+			break_permissions: definition.break_permissions,
+			fallback: Some(definition.fallback),
+		}
+	}
+
 	/// sets the info's block type
 	pub const fn block_type(mut self, block_type: BlockType) -> Self {
 		self.block_type = block_type;
@@ -197,8 +266,11 @@ impl BlockInfo {
 	}
 
 	/// sets the block's fallback block
-	pub const fn fallback(mut self, fallback: u8) -> Self {
-		assert!(fallback <= 0x31, "fallback must be under 0x31!");
+	pub const fn fallback(mut self, fallback: u16) -> Self {
+		assert!(
+			fallback <= MAX_CLASSIC_BLOCK_ID,
+			"fallback must be under 0x31!"
+		);
 		self.fallback = Some(fallback);
 		self
 	}
@@ -215,30 +287,58 @@ pub enum BlockType {
 	Slab,
 	/// fluid which is actively flowing
 	FluidFlowing {
-		stationary: u8,
+		stationary: u16,
 		ticks_to_spread: usize,
 	},
 	/// fluid which is stationary
-	FluidStationary { moving: u8 },
+	FluidStationary { moving: u16 },
 	/// a block which is climbable like the rope block
 	Rope,
+	/// a block which falls when there's nothing solid below it
+	Falling,
+	/// a block which absorbs nearby water
+	Sponge,
+	/// a block which explodes after a fuse when broken
+	Explosive,
 }
 
 impl BlockType {
+	/// gets whether players can be inside blocks of this type without being stuck
+	pub fn is_passable(&self) -> bool {
+		matches!(
+			self,
+			BlockType::NonSolid
+				| BlockType::FluidFlowing { .. }
+				| BlockType::FluidStationary { .. }
+				| BlockType::Rope
+		)
+	}
+
 	/// gets whether this block type needs an update after being placed
 	#[allow(clippy::match_like_matches_macro)]
 	pub fn needs_update_on_place(&self) -> bool {
 		match self {
-			BlockType::FluidFlowing { .. } => true,
+			BlockType::FluidFlowing { .. } | BlockType::Falling | BlockType::Sponge => true,
 			_ => false,
 		}
 	}
 
+	/// gets the pattern of neighbors this block type's physics updates spread to
+	pub fn neighbor_pattern(&self) -> NeighborPattern {
+		match self {
+			BlockType::FluidFlowing { .. } | BlockType::FluidStationary { .. } => {
+				NeighborPattern::DirectMinusUp
+			}
+			BlockType::Falling => NeighborPattern::Below,
+			_ => NeighborPattern::None,
+		}
+	}
+
 	/// gets whether this block type needs an update when one of it's direct neighbors changes
 	#[allow(clippy::match_like_matches_macro)]
 	pub fn needs_update_when_neighbor_changed(&self) -> bool {
 		match self {
-			BlockType::FluidStationary { .. } => true,
+			BlockType::FluidStationary { .. } | BlockType::Falling => true,
 			_ => false,
 		}
 	}
@@ -0,0 +1,144 @@
+use std::{collections::BTreeSet, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::GeneralError, player::PlayerType};
+
+use super::{builtin_blocks, MAX_CLASSIC_BLOCK_ID, MAX_CUSTOM_BLOCK_ID, MAX_EXTENDED_BLOCK_ID};
+
+/// blocks defined by the server owner, set once when the server starts
+static BLOCK_DEFINITIONS: OnceLock<Vec<BlockDefinition>> = OnceLock::new();
+
+/// a block defined by the server owner, sent to clients supporting BlockDefinitions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockDefinition {
+	/// the block's id, which must be above the CustomBlocks blocks and at most 0x2ff
+	pub id: u16,
+	/// the block's name, also used as its string id
+	pub name: String,
+	/// the classic block shown to clients which don't support BlockDefinitions
+	pub fallback: u16,
+	/// how players collide with the block
+	pub solidity: BlockSolidity,
+	/// how fast players move through the block, with 128 being normal speed
+	pub movement_speed: u8,
+	/// the terrain texture id for the top of the block
+	pub top_texture: u8,
+	/// the terrain texture id for the sides of the block
+	pub side_texture: u8,
+	/// the terrain texture id for the bottom of the block
+	pub bottom_texture: u8,
+	/// whether light passes through the block
+	pub transmits_light: bool,
+	/// the sound played when walking on the block
+	pub walk_sound: u8,
+	/// whether the block is always fully lit
+	pub full_bright: bool,
+	/// the height of the block in sixteenths, or 0 to draw it as a sprite
+	pub shape: u8,
+	/// how the block is drawn
+	pub draw: BlockDraw,
+	/// the density of fog while inside the block, 0 for none
+	pub fog_density: u8,
+	/// the color of fog while inside the block
+	pub fog_color: [u8; 3],
+	/// permissions needed to place this block
+	pub place_permissions: PlayerType,
+	/// permissions needed to break this block
+	pub break_permissions: PlayerType,
+}
+
+impl Default for BlockDefinition {
+	fn default() -> Self {
+		Self {
+			id: 0,
+			name: String::new(),
+			fallback: 0x01,
+			solidity: BlockSolidity::Solid,
+			movement_speed: 128,
+			top_texture: 1,
+			side_texture: 1,
+			bottom_texture: 1,
+			transmits_light: false,
+			walk_sound: 4,
+			full_bright: false,
+			shape: 16,
+			draw: BlockDraw::Opaque,
+			fog_density: 0,
+			fog_color: [0; 3],
+			place_permissions: PlayerType::Normal,
+			break_permissions: PlayerType::Normal,
+		}
+	}
+}
+
+/// how players collide with a defined block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockSolidity {
+	WalkThrough = 0,
+	SwimThrough = 1,
+	Solid = 2,
+}
+
+/// how a defined block is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockDraw {
+	Opaque = 0,
+	Transparent = 1,
+	TransparentNoCulling = 2,
+	Translucent = 3,
+	Gas = 4,
+}
+
+/// registers the server's block definitions, which must happen before any block information is used
+pub fn register_block_definitions(definitions: Vec<BlockDefinition>) -> Result<(), GeneralError> {
+	let builtin_blocks = builtin_blocks();
+	let builtin_names: BTreeSet<&str> = builtin_blocks
+		.iter()
+		.map(|(_, info)| info.str_id.as_str())
+		.collect();
+	let mut ids = BTreeSet::new();
+	let mut names = BTreeSet::new();
+	for definition in &definitions {
+		if definition.id <= MAX_CUSTOM_BLOCK_ID || definition.id > MAX_EXTENDED_BLOCK_ID {
+			return Err(GeneralError::Custom(format!(
+				"block definition {} must have an id above {MAX_CUSTOM_BLOCK_ID:#x} and at most {MAX_EXTENDED_BLOCK_ID:#x}",
+				definition.name
+			)));
+		}
+		if definition.fallback > MAX_CLASSIC_BLOCK_ID {
+			return Err(GeneralError::Custom(format!(
+				"block definition {} must fall back to a classic block",
+				definition.name
+			)));
+		}
+		if definition.name.is_empty()
+			|| builtin_names.contains(definition.name.as_str())
+			|| !names.insert(definition.name.as_str())
+		{
+			return Err(GeneralError::Custom(format!(
+				"block definition {:#x} must have a unique name",
+				definition.id
+			)));
+		}
+		if !ids.insert(definition.id) {
+			return Err(GeneralError::Custom(format!(
+				"block id {:#x} is defined more than once",
+				definition.id
+			)));
+		}
+	}
+
+	BLOCK_DEFINITIONS
+		.set(definitions)
+		.map_err(|_| GeneralError::Custom("block definitions were already registered".to_string()))
+}
+
+/// gets the server's block definitions
+pub fn block_definitions() -> &'static [BlockDefinition] {
+	BLOCK_DEFINITIONS
+		.get()
+		.map(Vec::as_slice)
+		.unwrap_or_default()
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use quartz_nbt::{
+	io::{read_nbt, write_nbt, Flavor},
+	NbtCompound,
+};
+use rand::Rng;
+
+use crate::{error::GeneralError, server::config::ConfigCoordinatesWithOrientation};
+
+use super::Level;
+
+/// the file extension for ClassicWorld levels
+pub const CW_EXTENSION: &str = "cw";
+/// the name of the root tag in ClassicWorld files
+const CW_ROOT_NAME: &str = "ClassicWorld";
+/// the ClassicWorld format version written by the server
+const CW_FORMAT_VERSION: i8 = 1;
+
+impl Level {
+	/// imports a level in the ClassicWorld format, along with its spawn point if it has one
+	pub async fn load_cw<P>(
+		path: P,
+	) -> Result<(Self, Option<ConfigCoordinatesWithOrientation>), GeneralError>
+	where
+		P: AsRef<Path>,
+	{
+		let data = tokio::fs::read(path).await?;
+		let (root, _) = read_nbt(&mut data.as_slice(), Flavor::GzCompressed)?;
+
+		let x_size = root.get::<_, i16>("X")? as usize;
+		let y_size = root.get::<_, i16>("Y")? as usize;
+		let z_size = root.get::<_, i16>("Z")? as usize;
+		let blocks: &[u8] = root.get("BlockArray")?;
+		// high bytes of blocks with ids above 255, as written by ExtendedBlocks servers
+		let high_blocks = root.get::<_, &[u8]>("BlockArray2").ok();
+		let len = x_size * y_size * z_size;
+		for data in std::iter::once(blocks).chain(high_blocks) {
+			if data.len() != len {
+				return Err(GeneralError::Custom(format!(
+					"level data is not correct size! expected {len}, got {}",
+					data.len()
+				)));
+			}
+		}
+
+		let mut level = Self::new(x_size, y_size, z_size);
+		level.set_block_planes(blocks, high_blocks);
+
+		let spawn = root.get::<_, &NbtCompound>("Spawn").ok().and_then(|spawn| {
+			Some(ConfigCoordinatesWithOrientation {
+				x: spawn.get::<_, i16>("X").ok()? as f32 + 0.5,
+				y: spawn.get::<_, i16>("Y").ok()? as f32,
+				z: spawn.get::<_, i16>("Z").ok()? as f32 + 0.5,
+				yaw: spawn.get::<_, i8>("H").unwrap_or_default() as u8,
+				pitch: spawn.get::<_, i8>("P").unwrap_or_default() as u8,
+			})
+		});
+
+		Ok((level, spawn))
+	}
+
+	/// exports the level in the ClassicWorld format, with the given spawn point if there is one
+	pub async fn save_cw<P>(
+		&self,
+		path: P,
+		spawn: Option<&ConfigCoordinatesWithOrientation>,
+	) -> Result<(), GeneralError>
+	where
+		P: AsRef<Path>,
+	{
+		let path = path.as_ref();
+		let name = path
+			.file_stem()
+			.and_then(|s| s.to_str())
+			.unwrap_or_default();
+
+		let mut root = NbtCompound::new();
+		root.insert("FormatVersion", CW_FORMAT_VERSION);
+		root.insert("Name", name);
+		root.insert("UUID", rand::thread_rng().gen::<[u8; 16]>().to_vec());
+		root.insert("X", self.x_size as i16);
+		root.insert("Y", self.y_size as i16);
+		root.insert("Z", self.z_size as i16);
+		if let Some(spawn) = spawn {
+			let mut spawn_tag = NbtCompound::new();
+			spawn_tag.insert("X", spawn.x as i16);
+			spawn_tag.insert("Y", spawn.y as i16);
+			spawn_tag.insert("Z", spawn.z as i16);
+			spawn_tag.insert("H", spawn.yaw as i8);
+			spawn_tag.insert("P", spawn.pitch as i8);
+			root.insert("Spawn", spawn_tag);
+		}
+		let (blocks, high_blocks) = self.block_planes();
+		root.insert("BlockArray", blocks);
+		if let Some(high_blocks) = high_blocks {
+			root.insert("BlockArray2", high_blocks);
+		}
+
+		let mut data = Vec::new();
+		write_nbt(&mut data, Some(CW_ROOT_NAME), &root, Flavor::GzCompressed)?;
+		if let Some(parent) = path.parent() {
+			tokio::fs::create_dir_all(parent).await?;
+		}
+		Ok(tokio::fs::write(path, data).await?)
+	}
+}
@@ -1,8 +1,16 @@
 use internment::Intern;
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-use super::{block::BLOCK_STRING_ID_MAP, Level};
+use crate::util::get_relative_coords;
+
+use super::{
+	block::{BLOCK_STRING_ID_MAP, ID_GRASS, ID_LEAVES, ID_STONE, ID_WOOD},
+	BlockUpdate, Level,
+};
+
+/// the number of columns in a chunk, used to scale ore frequency to the level's size
+const CHUNK_COLUMNS: usize = 16 * 16;
 
 /// enum for different kinds of level generation
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -35,9 +43,88 @@ pub struct FlatLayer {
 	pub depth: usize,
 }
 
+/// extra features added to a level after its terrain is generated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationFeatures {
+	/// ores scattered through stone
+	pub ores: Vec<OreSettings>,
+	/// the number of trees to try placing on grass, 0 to disable
+	pub tree_count: usize,
+}
+
+impl Default for GenerationFeatures {
+	fn default() -> Self {
+		Self {
+			ores: vec![
+				OreSettings {
+					block: "coal_ore".to_string(),
+					veins_per_chunk: 10.0,
+					vein_size: 8,
+					max_height: 0.5,
+				},
+				OreSettings {
+					block: "iron_ore".to_string(),
+					veins_per_chunk: 6.0,
+					vein_size: 6,
+					max_height: 0.35,
+				},
+				OreSettings {
+					block: "gold_ore".to_string(),
+					veins_per_chunk: 2.0,
+					vein_size: 5,
+					max_height: 0.2,
+				},
+			],
+			tree_count: 0,
+		}
+	}
+}
+
+/// settings for how an ore is scattered through stone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OreSettings {
+	/// the ore's block
+	pub block: String,
+	/// the average number of veins in each 16x16 column area
+	pub veins_per_chunk: f32,
+	/// the most blocks a single vein can contain
+	pub vein_size: usize,
+	/// the highest veins can start, as a fraction of the level's height
+	pub max_height: f32,
+}
+
 impl LevelGeneration {
-	/// generates the level
-	pub fn generate<R>(&self, level: &mut Level, rng: &mut R)
+	/// gets a generator by its name in commands, for a level of the given height
+	pub fn from_name(name: &str, y_size: usize) -> Option<Self> {
+		Some(match name {
+			"empty" => Self::Empty,
+			"flat" => Self::Flat(FlatPreset::StoneAndGrass),
+			"random" => Self::FullRandom { height: y_size / 2 },
+			_ => return None,
+		})
+	}
+
+	/// generates the level from the given seed and records the seed in it, so the same seed and generator always make the same level
+	pub fn generate_seeded(&self, level: &mut Level, features: &GenerationFeatures, seed: u64) {
+		self.generate(level, features, &mut StdRng::seed_from_u64(seed));
+		level.seed = Some(seed);
+	}
+
+	/// generates the level, adding the given features afterwards
+	pub fn generate<R>(&self, level: &mut Level, features: &GenerationFeatures, rng: &mut R)
+	where
+		R: Rng,
+	{
+		self.generate_terrain(level, rng);
+		for ore in &features.ores {
+			ore.scatter(level, rng);
+		}
+		scatter_trees(level, features.tree_count, rng);
+	}
+
+	/// generates the level's terrain
+	fn generate_terrain<R>(&self, level: &mut Level, rng: &mut R)
 	where
 		R: Rng,
 	{
@@ -106,3 +193,122 @@ impl LevelGeneration {
 		}
 	}
 }
+
+impl OreSettings {
+	/// scatters veins of the ore through the level's stone
+	fn scatter<R>(&self, level: &mut Level, rng: &mut R)
+	where
+		R: Rng,
+	{
+		let Some(block) = BLOCK_STRING_ID_MAP
+			.get(&Intern::new(self.block.clone()))
+			.copied()
+		else {
+			eprintln!("unknown ore block: {}", self.block);
+			return;
+		};
+		let max_y = ((level.y_size as f32 * self.max_height) as usize).min(level.y_size);
+		if max_y == 0 || self.vein_size == 0 {
+			return;
+		}
+
+		let veins = (self.veins_per_chunk * (level.x_size * level.z_size) as f32
+			/ CHUNK_COLUMNS as f32) as usize;
+		for _ in 0..veins {
+			let mut pos = (
+				rng.gen_range(0..level.x_size),
+				rng.gen_range(0..max_y),
+				rng.gen_range(0..level.z_size),
+			);
+			for _ in 0..rng.gen_range(1..=self.vein_size) {
+				if level.get_block(pos.0, pos.1, pos.2) == ID_STONE {
+					level.set_block(pos.0, pos.1, pos.2, block);
+				}
+				let (rx, ry, rz) = (
+					rng.gen_range(-1..=1),
+					rng.gen_range(-1..=1),
+					rng.gen_range(-1..=1),
+				);
+				if let Some(next) = get_relative_coords(level, pos.0, pos.1, pos.2, rx, ry, rz) {
+					pos = next;
+				}
+			}
+		}
+	}
+}
+
+/// tries to place the given number of trees on grass at the surface of the level
+fn scatter_trees<R>(level: &mut Level, count: usize, rng: &mut R)
+where
+	R: Rng,
+{
+	let mut placed = 0;
+	for _ in 0..count * 10 {
+		if placed >= count {
+			break;
+		}
+		let (x, z) = (
+			rng.gen_range(0..level.x_size),
+			rng.gen_range(0..level.z_size),
+		);
+		let Some(surface) = (0..level.y_size)
+			.rev()
+			.find(|y| level.get_block(x, *y, z) != 0)
+		else {
+			continue;
+		};
+		if level.get_block(x, surface, z) != ID_GRASS {
+			continue;
+		}
+		if let Some(blocks) = tree_blocks(level, x, surface + 1, z, rng.gen_range(4..=6)) {
+			for update in blocks {
+				level.blocks[update.index] = update.block;
+			}
+			placed += 1;
+		}
+	}
+}
+
+/// gets the block updates making up a tree with its trunk starting at the given coordinates
+///
+/// returns `None` if any part of the tree would be out of bounds or replace something other than air
+pub fn tree_blocks(
+	level: &Level,
+	x: usize,
+	y: usize,
+	z: usize,
+	height: usize,
+) -> Option<Vec<BlockUpdate>> {
+	let mut blocks = Vec::new();
+	for ty in 0..height {
+		blocks.push(((x, y + ty, z), ID_WOOD));
+	}
+
+	// two wide layers around the top of the trunk, then two narrow layers above them
+	let top = (height - 1) as isize;
+	let layers: [(isize, isize); 4] = [(top - 1, 2), (top, 2), (top + 1, 1), (top + 2, 1)];
+	for (ry, radius) in layers {
+		for rx in -radius..=radius {
+			for rz in -radius..=radius {
+				let is_corner = rx.abs() == radius && rz.abs() == radius;
+				if (rx == 0 && rz == 0 && ry <= top) || (is_corner && ry > top) {
+					continue;
+				}
+				blocks.push((get_relative_coords(level, x, y, z, rx, ry, rz)?, ID_LEAVES));
+			}
+		}
+	}
+
+	blocks
+		.iter()
+		.all(|((bx, by, bz), _)| *by < level.y_size && level.get_block(*bx, *by, *bz) == 0)
+		.then(|| {
+			blocks
+				.into_iter()
+				.map(|((bx, by, bz), block)| BlockUpdate {
+					index: level.index(bx, by, bz),
+					block,
+				})
+				.collect()
+		})
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	level::block::{is_lava, is_water, BLOCK_INFO, ID_COBBLESTONE, ID_OBSIDIAN},
+	locale::Translatable,
+	util::edit_distance,
+};
+
+/// rules for a level's behavior, which can be changed at runtime by name
+///
+/// rules are read and written by name through their serialized form, so any new field is automatically settable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelRules {
+	/// whether blocks such as sand and gravel fall when unsupported
+	pub block_physics: bool,
+	/// how far sponges absorb water in each direction
+	pub sponge_radius: usize,
+	/// whether tnt explodes when broken
+	pub tnt_enabled: bool,
+	/// the radius of tnt explosions
+	pub tnt_radius: usize,
+	/// whether players may fly, if their rank also allows it
+	pub allow_flying: bool,
+	/// whether players may noclip, if their rank also allows it
+	pub allow_noclip: bool,
+	/// whether players may speed, if their rank also allows it
+	pub allow_speeding: bool,
+	/// whether players may respawn or set their spawn, if their rank also allows it
+	pub allow_respawn: bool,
+	/// whether players may use third person view, if their rank also allows it
+	pub allow_third_person: bool,
+	/// the jump height players have in the level, or -1 to use their rank's
+	pub jump_height: i16,
+	/// the furthest players can reach blocks in the level, in blocks
+	pub click_distance: f32,
+	/// whether the level's time of day advances
+	pub daylight_cycle: bool,
+	/// the chance of exposed dirt next to grass becoming grass each time it's randomly ticked
+	pub grass_spread_chance: f32,
+	/// how many blocks fluids can flow from their source before stopping, or 0 for no limit
+	///
+	/// flowing downwards doesn't count towards the distance
+	pub fluid_spread_distance: usize,
+	/// the block made when water flows into lava
+	pub water_into_lava_block: u16,
+	/// the block made when lava flows into water
+	pub lava_into_water_block: u16,
+	/// how many blocks horizontally around the level's spawn normal players can't edit, or 0 to disable
+	pub spawn_protection_radius: usize,
+	/// the highest y non-operators can place blocks at, or -1 for no limit
+	pub max_build_height: i16,
+}
+
+/// the furthest a mistyped rule name can be from a rule for it to be suggested
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// the inclusive ranges rules with limits beyond their type's can be set within
+const RULE_RANGES: &[(&str, f64, f64)] = &[
+	("sponge_radius", 0.0, 16.0),
+	("tnt_radius", 0.0, 16.0),
+	("jump_height", -1.0, i16::MAX as f64),
+	// click distances are sent in fixed point units
+	("click_distance", 0.0, 1023.0),
+	("grass_spread_chance", 0.0, 1.0),
+	("max_build_height", -1.0, i16::MAX as f64),
+];
+
+/// gets a readable name for the type of a rule's value
+fn value_type(value: &Value) -> &'static str {
+	match value {
+		Value::Bool(_) => "boolean",
+		Value::Number(n) if n.is_f64() => "decimal",
+		Value::Number(_) => "integer",
+		_ => "text",
+	}
+}
+
+impl Default for LevelRules {
+	fn default() -> Self {
+		Self {
+			block_physics: true,
+			sponge_radius: 2,
+			tnt_enabled: false,
+			tnt_radius: 3,
+			allow_flying: true,
+			allow_noclip: true,
+			allow_speeding: true,
+			allow_respawn: true,
+			allow_third_person: true,
+			jump_height: -1,
+			click_distance: 5.0,
+			daylight_cycle: true,
+			grass_spread_chance: 0.25,
+			fluid_spread_distance: 0,
+			water_into_lava_block: ID_COBBLESTONE,
+			lava_into_water_block: ID_OBSIDIAN,
+			spawn_protection_radius: 0,
+			max_build_height: -1,
+		}
+	}
+}
+
+impl LevelRules {
+	/// gets the block made when a flowing fluid touches another fluid, if any
+	///
+	/// unknown blocks configured for a contact are treated as no interaction
+	pub fn fluid_contact_block(&self, flowing: u16, touched: u16) -> Option<u16> {
+		let block = if is_water(flowing) && is_lava(touched) {
+			self.water_into_lava_block
+		} else if is_lava(flowing) && is_water(touched) {
+			self.lava_into_water_block
+		} else {
+			return None;
+		};
+		BLOCK_INFO.contains_key(&block).then_some(block)
+	}
+
+	/// gets the rules as a map of rule names to values
+	fn as_map(&self) -> serde_json::Map<String, Value> {
+		match serde_json::to_value(self).expect("rules should always serialize") {
+			Value::Object(map) => map,
+			_ => unreachable!("rules should always serialize to an object"),
+		}
+	}
+
+	/// gets the value of a rule by name
+	pub fn get_rule(&self, name: &str) -> Option<String> {
+		self.as_map().get(name).map(|v| v.to_string())
+	}
+
+	/// gets a list of all rules with their types and values, sorted by name
+	pub fn get_all_rules_info(&self) -> Vec<String> {
+		let mut rules: Vec<_> = self.as_map().into_iter().collect();
+		rules.sort_by(|(a, _), (b, _)| a.cmp(b));
+		rules
+			.into_iter()
+			.map(|(name, value)| format!("{name} ({}) = {value}", value_type(&value)))
+			.collect()
+	}
+
+	/// gets the names of all rules, sorted
+	pub fn rule_names(&self) -> Vec<String> {
+		let mut names: Vec<_> = self.as_map().into_iter().map(|(name, _)| name).collect();
+		names.sort();
+		names
+	}
+
+	/// gets the rule whose name is closest to the given one, if any is close enough to be a likely typo
+	pub fn closest_rule(&self, name: &str) -> Option<String> {
+		self.rule_names()
+			.into_iter()
+			.map(|rule| (edit_distance(name, &rule), rule))
+			.filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+			.min_by_key(|(distance, _)| *distance)
+			.map(|(_, rule)| rule)
+	}
+
+	/// sets a rule by name, parsing the value into the rule's type
+	pub fn set_rule(&mut self, name: &str, value: &str) -> Result<(), Translatable> {
+		let mut map = self.as_map();
+		let current = map
+			.get(name)
+			.ok_or_else(|| Translatable::new("levelrule.unknown").arg(name))?;
+		let invalid = || Translatable::new("levelrule.invalid_value").arg(value);
+		let new_value = match current {
+			Value::Bool(_) => Value::Bool(value.parse().map_err(|_| invalid())?),
+			// signed rules currently holding positive values look unsigned, so whole numbers are always parsed as signed
+			// and rules which can't be negative are left for deserializing to reject
+			Value::Number(n) if n.is_u64() || n.is_i64() => {
+				Value::from(value.parse::<i64>().map_err(|_| invalid())?)
+			}
+			Value::Number(_) => Value::from(value.parse::<f64>().map_err(|_| invalid())?),
+			Value::String(_) => Value::String(value.to_string()),
+			_ => return Err(invalid()),
+		};
+		if let (Some(n), Some((_, min, max))) = (
+			new_value.as_f64(),
+			RULE_RANGES.iter().find(|(rule, ..)| *rule == name),
+		) {
+			if !(*min..=*max).contains(&n) {
+				return Err(Translatable::new("levelrule.out_of_range")
+					.arg(name)
+					.arg(min)
+					.arg(max));
+			}
+		}
+		map.insert(name.to_string(), new_value);
+		*self = serde_json::from_value(Value::Object(map)).map_err(|_| invalid())?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::locale::Translatable;
+
+	use super::LevelRules;
+
+	#[test]
+	fn set_rule_accepts_the_ends_of_a_range() {
+		let mut rules = LevelRules::default();
+		rules.set_rule("sponge_radius", "0").unwrap();
+		assert_eq!(rules.sponge_radius, 0);
+		rules.set_rule("sponge_radius", "16").unwrap();
+		assert_eq!(rules.sponge_radius, 16);
+		rules.set_rule("tnt_radius", "0").unwrap();
+		assert_eq!(rules.tnt_radius, 0);
+	}
+
+	#[test]
+	fn set_rule_rejects_values_over_the_max() {
+		let mut rules = LevelRules::default();
+		assert_eq!(
+			rules.set_rule("sponge_radius", "17"),
+			Err(Translatable::new("levelrule.out_of_range")
+				.arg("sponge_radius")
+				.arg(0.0)
+				.arg(16.0))
+		);
+		assert_eq!(
+			rules.set_rule("grass_spread_chance", "1.5"),
+			Err(Translatable::new("levelrule.out_of_range")
+				.arg("grass_spread_chance")
+				.arg(0.0)
+				.arg(1.0))
+		);
+		assert_eq!(rules.sponge_radius, LevelRules::default().sponge_radius);
+	}
+}
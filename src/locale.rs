@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, sync::LazyLock};
+
+/// the locale used when a message is missing from a player's locale
+pub const DEFAULT_LOCALE: &str = "en";
+/// the directory locale files are loaded from
+const LOCALES_PATH: &str = "locales";
+
+/// the built-in english locale
+const BUILTIN_ENGLISH: &str = include_str!("../locales/en.json");
+/// a locale only loaded in tests, translating a few messages
+#[cfg(test)]
+pub const TEST_LOCALE: &str = "test";
+
+/// all loaded locales, mapping locale codes to their message tables
+static LOCALES: LazyLock<BTreeMap<String, BTreeMap<String, String>>> = LazyLock::new(|| {
+	let mut locales = BTreeMap::new();
+	locales.insert(
+		DEFAULT_LOCALE.to_string(),
+		serde_json::from_str::<BTreeMap<String, String>>(BUILTIN_ENGLISH)
+			.expect("built-in locale should always be valid"),
+	);
+	#[cfg(test)]
+	locales.insert(
+		TEST_LOCALE.to_string(),
+		BTreeMap::from([
+			("command.lang.success".to_string(), "Langue: {}".to_string()),
+			(
+				"command.no_permission".to_string(),
+				"&cVous n'avez pas la permission".to_string(),
+			),
+		]),
+	);
+
+	let locales_path = PathBuf::from(LOCALES_PATH);
+	let entries = match std::fs::read_dir(&locales_path) {
+		Ok(entries) => entries,
+		Err(_) => return locales,
+	};
+	for entry in entries.flatten() {
+		let path = entry.path();
+		if path.extension().and_then(|e| e.to_str()) != Some("json") {
+			continue;
+		}
+		let Some(code) = path.file_stem().and_then(|s| s.to_str()) else {
+			continue;
+		};
+		let table = match std::fs::read_to_string(&path)
+			.map_err(|e| e.to_string())
+			.and_then(|s| {
+				serde_json::from_str::<BTreeMap<String, String>>(&s).map_err(|e| e.to_string())
+			}) {
+			Ok(table) => table,
+			Err(e) => {
+				eprintln!("failed to load locale {code}: {e}");
+				continue;
+			}
+		};
+		locales
+			.entry(code.to_lowercase())
+			.or_insert_with(BTreeMap::new)
+			.extend(table);
+	}
+
+	locales
+});
+
+/// gets whether the given locale has been loaded
+pub fn is_available(locale: &str) -> bool {
+	LOCALES.contains_key(locale)
+}
+
+/// gets a list of all loaded locales
+pub fn available_locales() -> Vec<&'static str> {
+	LOCALES.keys().map(|k| k.as_str()).collect()
+}
+
+/// translates the given message key into the given locale, substituting each `{}` with the next argument
+///
+/// falls back to english if the key is missing from the locale, and to the key itself if it's missing entirely
+pub fn translate(locale: &str, key: &str, args: &[String]) -> String {
+	let template = LOCALES
+		.get(locale)
+		.and_then(|t| t.get(key))
+		.or_else(|| LOCALES.get(DEFAULT_LOCALE).and_then(|t| t.get(key)))
+		.map(String::as_str)
+		.unwrap_or(key);
+
+	let mut args = args.iter();
+	let mut parts = template.split("{}");
+	let mut result = parts.next().unwrap_or_default().to_string();
+	for part in parts {
+		if let Some(arg) = args.next() {
+			result.push_str(arg);
+		}
+		result.push_str(part);
+	}
+	result
+}
+
+/// a message which can be translated once the receiving player's locale is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translatable {
+	/// the message's key
+	pub key: &'static str,
+	/// the arguments to substitute into the message
+	pub args: Vec<String>,
+}
+
+impl Translatable {
+	/// creates a new translatable message with no arguments
+	pub fn new(key: &'static str) -> Self {
+		Self {
+			key,
+			args: Vec::new(),
+		}
+	}
+
+	/// adds an argument to the message
+	pub fn arg<T>(mut self, arg: T) -> Self
+	where
+		T: Display,
+	{
+		self.args.push(arg.to_string());
+		self
+	}
+
+	/// translates the message into the given locale
+	pub fn translate(&self, locale: &str) -> String {
+		translate(locale, self.key, &self.args)
+	}
+}
+
+/// translates a message key into the given locale with the given arguments
+macro_rules! tr {
+	($locale:expr, $key:expr $(, $arg:expr)* $(,)?) => {
+		$crate::locale::translate($locale, $key, &[$($arg.to_string()),*])
+	};
+}
+
+pub(crate) use tr;
@@ -9,8 +9,10 @@ use server::{
 mod command;
 mod error;
 mod level;
+mod locale;
 mod packet;
 mod player;
+mod selection;
 mod server;
 mod util;
 
@@ -29,7 +31,7 @@ async fn main() -> Result<(), GeneralError> {
 
 	println!("starting server with config: {config:#?}");
 
-	let server = Server::new(config).await?;
+	let server = Server::new(config, None).await?;
 
 	server.run().await?;
 
@@ -1,14 +1,19 @@
 use half::f16;
 use safer_bytes::{error::Truncated, SafeBuf};
 
+use crate::util::truncate_to_string_length;
+
 pub mod client;
 pub mod client_extended;
+pub mod cp437;
 pub mod server;
 
 /// length of classic strings
 pub const STRING_LENGTH: usize = 64;
 /// length of classic level chunk arrays
 pub const ARRAY_LENGTH: usize = 1024;
+/// the most block changes a single BulkBlockUpdate packet can hold
+pub const BULK_BLOCK_UPDATE_COUNT: usize = 256;
 /// units in an f16 unit
 pub const F16_UNITS: f32 = 32.0;
 /// the magic number to check whether the client supports extensions
@@ -42,6 +47,8 @@ pub trait SafeBufExtension: SafeBuf {
 	fn try_get_f16(&mut self) -> Result<f16, Truncated>;
 	/// tries to get the next string in the buffer
 	fn try_get_string(&mut self) -> Result<String, Truncated>;
+	/// tries to get the next block id in the buffer, which is two bytes if ExtendedBlocks is supported
+	fn try_get_block(&mut self, extended_blocks: bool) -> Result<u16, Truncated>;
 }
 
 impl<T> SafeBufExtension for T
@@ -56,19 +63,43 @@ where
 	fn try_get_string(&mut self) -> Result<String, Truncated> {
 		let mut chars: Vec<char> = Vec::new();
 		for _ in 0..STRING_LENGTH {
-			chars.push(self.try_get_u8()? as char);
+			chars.push(cp437::decode(self.try_get_u8()?));
 		}
 		Ok(String::from_iter(chars).trim().to_string())
 	}
+
+	fn try_get_block(&mut self, extended_blocks: bool) -> Result<u16, Truncated> {
+		if extended_blocks {
+			self.try_get_u16()
+		} else {
+			self.try_get_u8().map(|b| b as u16)
+		}
+	}
 }
 
 /// helper for writing a packet
 #[derive(Debug, Default)]
 pub struct PacketWriter {
 	raw_packet: Vec<u8>,
+	/// whether block ids are written as two bytes for clients supporting ExtendedBlocks
+	extended_blocks: bool,
+	/// whether strings may use the upper half of CP437 for clients supporting FullCP437
+	full_cp437: bool,
 }
 
 impl PacketWriter {
+	/// sets whether block ids should be written as two bytes
+	pub fn extended_blocks(mut self, extended_blocks: bool) -> Self {
+		self.extended_blocks = extended_blocks;
+		self
+	}
+
+	/// sets whether strings may use the upper half of CP437
+	pub fn full_cp437(mut self, full_cp437: bool) -> Self {
+		self.full_cp437 = full_cp437;
+		self
+	}
+
 	/// gets the actual raw packet data from the writer
 	pub fn into_raw_packet(self) -> Vec<u8> {
 		self.raw_packet
@@ -99,6 +130,32 @@ impl PacketWriter {
 		s
 	}
 
+	/// writes a block id to the packet, as two bytes if the client supports ExtendedBlocks
+	fn write_block(self, block: u16) -> Self {
+		if self.extended_blocks {
+			self.write_u16(block)
+		} else {
+			self.write_u8(block as u8)
+		}
+	}
+
+	/// writes the block ids of a BulkBlockUpdate, padded to the packet's full length
+	///
+	/// with ExtendedBlocks, the high bits of each block are packed two bits per block after the low bytes
+	fn write_bulk_blocks(self, blocks: &[u16]) -> Self {
+		let extended_blocks = self.extended_blocks;
+		let low: Vec<u8> = blocks.iter().map(|b| *b as u8).collect();
+		let mut s = self.write_array_of_length(&low, BULK_BLOCK_UPDATE_COUNT);
+		if extended_blocks {
+			let mut high = vec![0; BULK_BLOCK_UPDATE_COUNT / 4];
+			for (i, block) in blocks.iter().enumerate() {
+				high[i / 4] |= (((block >> 8) & 0b11) as u8) << ((i % 4) * 2);
+			}
+			s = s.write_array_of_length(&high, high.len());
+		}
+		s
+	}
+
 	/// writes an i16 to the packet
 	fn write_i16(self, sh: i16) -> Self {
 		self.write_u16(sh as u16)
@@ -119,13 +176,18 @@ impl PacketWriter {
 		s
 	}
 
-	/// writes a string to the packet
+	/// writes a string to the packet as CP437, truncating it without splitting a trailing color code
+	///
+	/// characters the client can't display are replaced with `?`
 	fn write_string(self, str: &str) -> Self {
+		let full_cp437 = self.full_cp437;
 		let mut s = self;
-		for b in str
-			.as_bytes()
-			.iter()
-			.copied()
+		for b in truncate_to_string_length(str)
+			.chars()
+			.map(|c| match cp437::encode(c) {
+				Some(b) if b < 0x80 || full_cp437 => b,
+				_ => b'?',
+			})
 			.chain(Some(0x20).into_iter().cycle())
 			.take(STRING_LENGTH)
 		{
@@ -196,6 +258,9 @@ impl ExtBitmask {
 	fn info(self) -> Option<ExtInfo> {
 		// TODO: add entries as extensions are supported
 		Some(match self {
+			Self::ClickDistance => {
+				ExtInfo::new("ClickDistance".to_string(), 1, Self::ClickDistance)
+			}
 			Self::CustomBlocks => ExtInfo::new("CustomBlocks".to_string(), 1, Self::CustomBlocks),
 			// this isn't actually used by the server at all, but it technically sort of implements it
 			Self::HeldBlock => ExtInfo::new("HeldBlock".to_string(), 1, Self::HeldBlock),
@@ -203,14 +268,37 @@ impl ExtBitmask {
 			Self::LongerMessages => {
 				ExtInfo::new("LongerMessages".to_string(), 1, Self::LongerMessages)
 			}
-			// TODO: render CP437 properly in server output
 			Self::FullCP437 => ExtInfo::new("FullCP437".to_string(), 1, Self::FullCP437),
+			Self::ChangeModel => ExtInfo::new("ChangeModel".to_string(), 1, Self::ChangeModel),
+			Self::EnvColors => ExtInfo::new("EnvColors".to_string(), 1, Self::EnvColors),
+			Self::SelectionCuboid => {
+				ExtInfo::new("SelectionCuboid".to_string(), 1, Self::SelectionCuboid)
+			}
+			Self::BlockPermissions => {
+				ExtInfo::new("BlockPermissions".to_string(), 1, Self::BlockPermissions)
+			}
 			Self::EnvWeatherType => {
 				ExtInfo::new("EnvWeatherType".to_string(), 1, Self::EnvWeatherType)
 			}
+			Self::HackControl => ExtInfo::new("HackControl".to_string(), 1, Self::HackControl),
+			Self::MessageTypes => ExtInfo::new("MessageTypes".to_string(), 1, Self::MessageTypes),
+			Self::BlockDefinitions => {
+				ExtInfo::new("BlockDefinitions".to_string(), 1, Self::BlockDefinitions)
+			}
+			Self::BulkBlockUpdate => {
+				ExtInfo::new("BulkBlockUpdate".to_string(), 1, Self::BulkBlockUpdate)
+			}
+			Self::TwoWayPing => ExtInfo::new("TwoWayPing".to_string(), 1, Self::TwoWayPing),
 			Self::InventoryOrder => {
 				ExtInfo::new("InventoryOrder".to_string(), 1, Self::InventoryOrder)
 			}
+			Self::FastMap => ExtInfo::new("FastMap".to_string(), 1, Self::FastMap),
+			Self::ExtendedBlocks => {
+				ExtInfo::new("ExtendedBlocks".to_string(), 1, Self::ExtendedBlocks)
+			}
+			Self::SetSpawnpoint => {
+				ExtInfo::new("SetSpawnpoint".to_string(), 1, Self::SetSpawnpoint)
+			}
 			Self::ExtEntityTeleport => {
 				ExtInfo::new("ExtEntityTeleport".to_string(), 1, Self::ExtEntityTeleport)
 			}
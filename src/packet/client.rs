@@ -23,12 +23,12 @@ pub enum ClientPacket {
 		z: i16,
 		/// 0x00 for destroy, 0x01 for create
 		mode: u8,
-		block_type: u8,
+		block_type: u16,
 	},
 	/// sent to update the player's current position and orientation with the server
 	PositionOrientation {
 		/// if the HeldBlock extension is supported, this should contain the block the player is currently holding
-		_player_id_or_held_block: i8,
+		_player_id_or_held_block: u16,
 		x: f16,
 		y: f16,
 		z: f16,
@@ -48,18 +48,21 @@ pub enum ClientPacket {
 
 impl ClientPacket {
 	/// gets the size of the packet from the given id (minus one byte for the id)
-	pub const fn get_size_from_id(id: u8) -> Option<usize> {
+	///
+	/// clients supporting ExtendedBlocks send block ids as two bytes, including the held block
+	pub const fn get_size_from_id(id: u8, extended_blocks: bool) -> Option<usize> {
+		let block_size = if extended_blocks { 2 } else { 1 };
 		Some(match id {
 			0x00 => 1 + STRING_LENGTH + STRING_LENGTH + 1,
-			0x05 => 2 + 2 + 2 + 1 + 1,
-			0x08 => 1 + 2 + 2 + 2 + 1 + 1,
+			0x05 => 2 + 2 + 2 + 1 + block_size,
+			0x08 => block_size + 2 + 2 + 2 + 1 + 1,
 			0x0d => 1 + STRING_LENGTH,
 			_ => return ExtendedClientPacket::get_size_from_id(id),
 		})
 	}
 
 	/// reads the packet
-	pub fn read<B>(id: u8, buf: &mut B) -> Option<Self>
+	pub fn read<B>(id: u8, buf: &mut B, extended_blocks: bool) -> Option<Self>
 	where
 		B: SafeBufExtension,
 	{
@@ -75,10 +78,10 @@ impl ClientPacket {
 				y: buf.try_get_i16().ok()?,
 				z: buf.try_get_i16().ok()?,
 				mode: buf.try_get_u8().ok()?,
-				block_type: buf.try_get_u8().ok()?,
+				block_type: buf.try_get_block(extended_blocks).ok()?,
 			},
 			0x08 => Self::PositionOrientation {
-				_player_id_or_held_block: buf.try_get_i8().ok()?,
+				_player_id_or_held_block: buf.try_get_block(extended_blocks).ok()?,
 				x: buf.try_get_f16().ok()?,
 				y: buf.try_get_f16().ok()?,
 				z: buf.try_get_f16().ok()?,
@@ -12,6 +12,12 @@ pub enum ExtendedClientPacket {
 	ExtEntry { ext_name: String, version: i32 },
 	/// packet containing the support level for custom blocks from the client
 	CustomBlockSupportLevel { support_level: u8 },
+	/// packet either starting a ping from the client or answering a ping from the server
+	TwoWayPing {
+		/// 0 for pings started by the client, 1 for answers to pings started by the server
+		direction: u8,
+		data: i16,
+	},
 }
 
 impl ExtendedClientPacket {
@@ -21,6 +27,7 @@ impl ExtendedClientPacket {
 			0x10 => STRING_LENGTH + 2,
 			0x11 => STRING_LENGTH + 4,
 			0x13 => 1,
+			0x2b => 3,
 			_ => return None,
 		})
 	}
@@ -42,6 +49,10 @@ impl ExtendedClientPacket {
 			0x13 => Self::CustomBlockSupportLevel {
 				support_level: buf.try_get_u8().ok()?,
 			},
+			0x2b => Self::TwoWayPing {
+				direction: buf.try_get_u8().ok()?,
+				data: buf.try_get_i16().ok()?,
+			},
 			_ => return None,
 		})
 	}
@@ -0,0 +1,64 @@
+//! conversion between the CP437 codepage used by classic clients and unicode
+
+/// the glyphs for the control bytes below 0x20, which classic clients draw as symbols
+const LOW_GLYPHS: [char; 32] = [
+	'\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', '►', '◄', '↕',
+	'‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// the glyphs for the bytes from 0x80 upwards
+const HIGH_GLYPHS: [char; 128] = [
+	'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+	'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+	'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+	'╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+	'╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+	'▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+	'≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// the glyph for 0x7f
+const DELETE_GLYPH: char = '⌂';
+
+/// decodes a single CP437 byte into its unicode character
+pub fn decode(byte: u8) -> char {
+	match byte {
+		0x00..=0x1f => LOW_GLYPHS[byte as usize],
+		0x7f => DELETE_GLYPH,
+		0x80.. => HIGH_GLYPHS[byte as usize - 0x80],
+		_ => byte as char,
+	}
+}
+
+/// encodes a unicode character into its CP437 byte, if it has one
+pub fn encode(c: char) -> Option<u8> {
+	match c {
+		' '..='~' => Some(c as u8),
+		DELETE_GLYPH => Some(0x7f),
+		_ => LOW_GLYPHS
+			.iter()
+			.position(|g| *g == c)
+			.or_else(|| HIGH_GLYPHS.iter().position(|g| *g == c).map(|i| i + 0x80))
+			.map(|i| i as u8),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, encode};
+
+	#[test]
+	fn every_byte_round_trips() {
+		for byte in 0..=u8::MAX {
+			assert_eq!(encode(decode(byte)), Some(byte), "byte {byte:#x}");
+		}
+	}
+
+	#[test]
+	fn characters_outside_the_codepage_have_no_byte() {
+		assert_eq!(encode('é'), Some(0x82));
+		assert_eq!(encode('☺'), Some(0x01));
+		assert_eq!(encode('€'), None);
+		assert_eq!(encode('漢'), None);
+	}
+}
@@ -1,12 +1,14 @@
 use half::f16;
 
 use crate::{
-	level::{block::CUSTOM_BLOCKS_SUPPORT_LEVEL, WeatherType},
-	player::PlayerType,
+	level::{
+		block::{definition::BlockDefinition, CUSTOM_BLOCKS_SUPPORT_LEVEL},
+		EnvColorType, WeatherType,
+	},
 	SERVER_NAME,
 };
 
-use super::ExtBitmask;
+use super::{ExtBitmask, BULK_BLOCK_UPDATE_COUNT};
 
 #[derive(Debug, Clone)]
 #[allow(unused)]
@@ -17,13 +19,16 @@ pub enum ServerPacket {
 		protocol_version: u8,
 		server_name: String,
 		server_motd: String,
-		user_type: PlayerType,
+		/// 0x00 for normal, 0x64 for op
+		user_type: u8,
 	},
 	/// since clients do not notify the server when leaving, the ping packet is used to check if the client is still connected
-	/// TODO: implement pinging? classicube works fine without it
 	Ping,
 	/// informs clients that there is incoming level data
-	LevelInitialize,
+	LevelInitialize {
+		/// the level's volume, sent to clients supporting FastMap
+		volume: Option<i32>,
+	},
 	/// packet to send a chunk (not minecraft chunk) of gzipped level data
 	LevelDataChunk {
 		chunk_length: i16,
@@ -43,7 +48,7 @@ pub enum ServerPacket {
 		x: i16,
 		y: i16,
 		z: i16,
-		block_type: u8,
+		block_type: u16,
 	},
 	/// packet sent when a new player spawns
 	/// also contains their spawn point
@@ -90,12 +95,17 @@ pub enum ServerPacket {
 	DespawnPlayer { player_id: i8 },
 	/// packet sent when there's a chat message to go out
 	Message { player_id: i8, message: String },
+	/// packet sent for a message shown somewhere other than chat, for clients supporting MessageTypes
+	TypedMessage {
+		message_type: MessageType,
+		message: String,
+	},
 	/// informs a client that they're being disconnected from the server and why
 	DisconnectPlayer { disconnect_reason: String },
 	/// packet sent to a user to inform them that their user type has changed
 	UpdateUserType {
 		/// 0x00 for normal, 0x64 for op
-		user_type: PlayerType,
+		user_type: u8,
 	},
 
 	// extension packets
@@ -103,14 +113,80 @@ pub enum ServerPacket {
 	ExtInfo,
 	/// packet to send info about an extension on the server
 	ExtEntry { ext_name: String, version: i32 },
+	/// packet to set how far away the player can reach blocks, in units of 1/32 of a block
+	SetClickDistance { distance: i16 },
 	/// packet to send the server's supported custom blocks
 	CustomBlockSupportLevel,
 	/// packet to set a player's currently held block
-	HoldThis { block: u8, prevent_change: bool },
+	HoldThis { block: u16, prevent_change: bool },
+	/// packet to set a color in the client's environment
+	EnvSetColor {
+		variable: EnvColorType,
+		/// the new color, or `None` to reset it to the client's default
+		color: Option<[u8; 3]>,
+	},
+	/// packet to highlight a cuboid in the client's world
+	MakeSelection {
+		selection_id: u8,
+		label: String,
+		start_x: i16,
+		start_y: i16,
+		start_z: i16,
+		end_x: i16,
+		end_y: i16,
+		end_z: i16,
+		red: i16,
+		green: i16,
+		blue: i16,
+		opacity: i16,
+	},
+	/// packet to remove a highlighted cuboid from the client's world
+	RemoveSelection { selection_id: u8 },
+	/// packet to set whether the client may place or break a block
+	SetBlockPermission {
+		block: u16,
+		allow_placement: bool,
+		allow_deletion: bool,
+	},
+	/// packet to change the model an entity is shown with
+	ChangeModel { entity_id: i8, model: String },
 	/// informs the client that it should update the current weather
 	EnvWeatherType { weather_type: WeatherType },
-	/// packet to set a block's position in the client's inventory
-	SetInventoryOrder { order: u8, block: u8 },
+	/// packet to control which hacks the client is allowed to use
+	HackControl {
+		flying: bool,
+		no_clip: bool,
+		speeding: bool,
+		spawn_control: bool,
+		third_person_view: bool,
+		/// the player's jump height, or -1 for the client's default
+		jump_height: i16,
+	},
+	/// packet defining a new block for the client
+	DefineBlock { definition: BlockDefinition },
+	/// packet changing many blocks at once, for up to `BULK_BLOCK_UPDATE_COUNT` blocks
+	BulkBlockUpdate {
+		/// the level indices of the changed blocks
+		indices: Vec<i32>,
+		/// the new blocks, in the same order as their indices
+		blocks: Vec<u16>,
+	},
+	/// packet either answering a ping from the client or starting a ping from the server
+	TwoWayPing {
+		/// 0 for answers to pings started by the client, 1 for pings started by the server
+		direction: u8,
+		data: i16,
+	},
+	/// packet to set a block's position in the client's inventory, with position 0 hiding it
+	SetInventoryOrder { block: u16, order: u16 },
+	/// packet to set where the client respawns, without moving them
+	SetSpawnpoint {
+		x: f16,
+		y: f16,
+		z: f16,
+		yaw: u8,
+		pitch: u8,
+	},
 	ExtEntityTeleport {
 		entity_id: i8,
 		teleport_behavior: TeleportBehavior,
@@ -128,7 +204,7 @@ impl ServerPacket {
 		match self {
 			Self::ServerIdentification { .. } => 0x00,
 			Self::Ping => 0x01,
-			Self::LevelInitialize => 0x02,
+			Self::LevelInitialize { .. } => 0x02,
 			Self::LevelDataChunk { .. } => 0x03,
 			Self::LevelFinalize { .. } => 0x04,
 			Self::SetBlock { .. } => 0x06,
@@ -139,15 +215,27 @@ impl ServerPacket {
 			Self::UpdateOrientation { .. } => 0x0b,
 			Self::DespawnPlayer { .. } => 0x0c,
 			Self::Message { .. } => 0x0d,
+			Self::TypedMessage { .. } => 0x0d,
 			Self::DisconnectPlayer { .. } => 0x0e,
 			Self::UpdateUserType { .. } => 0x0f,
 
 			Self::ExtInfo => 0x10,
 			Self::ExtEntry { .. } => 0x11,
+			Self::SetClickDistance { .. } => 0x12,
 			Self::CustomBlockSupportLevel { .. } => 0x13,
 			Self::HoldThis { .. } => 0x14,
+			Self::EnvSetColor { .. } => 0x19,
+			Self::MakeSelection { .. } => 0x1a,
+			Self::RemoveSelection { .. } => 0x1b,
+			Self::SetBlockPermission { .. } => 0x1c,
+			Self::ChangeModel { .. } => 0x1d,
 			Self::EnvWeatherType { .. } => 0x1f,
+			Self::HackControl { .. } => 0x20,
+			Self::DefineBlock { .. } => 0x23,
+			Self::BulkBlockUpdate { .. } => 0x26,
+			Self::TwoWayPing { .. } => 0x2b,
 			Self::SetInventoryOrder { .. } => 0x2c,
+			Self::SetSpawnpoint { .. } => 0x2e,
 			Self::ExtEntityTeleport { .. } => 0x36,
 		}
 	}
@@ -164,9 +252,12 @@ impl ServerPacket {
 				.write_u8(*protocol_version)
 				.write_string(server_name)
 				.write_string(server_motd)
-				.write_u8(user_type.into()),
+				.write_u8(*user_type),
 			Self::Ping => writer,
-			Self::LevelInitialize => writer,
+			Self::LevelInitialize { volume } => match volume {
+				Some(volume) => writer.write_i32(*volume),
+				None => writer,
+			},
 			Self::LevelDataChunk {
 				chunk_length,
 				chunk_data,
@@ -192,7 +283,7 @@ impl ServerPacket {
 				.write_i16(*x)
 				.write_i16(*y)
 				.write_i16(*z)
-				.write_u8(*block_type),
+				.write_block(*block_type),
 			Self::SpawnPlayer {
 				player_id,
 				player_name,
@@ -256,8 +347,12 @@ impl ServerPacket {
 			Self::Message { player_id, message } => {
 				writer.write_i8(*player_id).write_string(message)
 			}
+			Self::TypedMessage {
+				message_type,
+				message,
+			} => writer.write_u8(*message_type as u8).write_string(message),
 			Self::DisconnectPlayer { disconnect_reason } => writer.write_string(disconnect_reason),
-			Self::UpdateUserType { user_type } => writer.write_u8(user_type.into()),
+			Self::UpdateUserType { user_type } => writer.write_u8(*user_type),
 
 			Self::ExtInfo => writer
 				.write_string(SERVER_NAME)
@@ -265,13 +360,113 @@ impl ServerPacket {
 			Self::ExtEntry { ext_name, version } => {
 				writer.write_string(ext_name).write_i32(*version)
 			}
+			Self::SetClickDistance { distance } => writer.write_i16(*distance),
 			Self::CustomBlockSupportLevel => writer.write_u8(CUSTOM_BLOCKS_SUPPORT_LEVEL),
 			Self::HoldThis {
 				block,
 				prevent_change,
-			} => writer.write_u8(*block).write_bool(*prevent_change),
+			} => writer.write_block(*block).write_bool(*prevent_change),
+			Self::EnvSetColor { variable, color } => {
+				let [red, green, blue] = color.map(|c| c.map(i16::from)).unwrap_or([-1; 3]);
+				writer
+					.write_u8(variable.into())
+					.write_i16(red)
+					.write_i16(green)
+					.write_i16(blue)
+			}
+			Self::MakeSelection {
+				selection_id,
+				label,
+				start_x,
+				start_y,
+				start_z,
+				end_x,
+				end_y,
+				end_z,
+				red,
+				green,
+				blue,
+				opacity,
+			} => writer
+				.write_u8(*selection_id)
+				.write_string(label)
+				.write_i16(*start_x)
+				.write_i16(*start_y)
+				.write_i16(*start_z)
+				.write_i16(*end_x)
+				.write_i16(*end_y)
+				.write_i16(*end_z)
+				.write_i16(*red)
+				.write_i16(*green)
+				.write_i16(*blue)
+				.write_i16(*opacity),
+			Self::RemoveSelection { selection_id } => writer.write_u8(*selection_id),
+			Self::SetBlockPermission {
+				block,
+				allow_placement,
+				allow_deletion,
+			} => writer
+				.write_block(*block)
+				.write_bool(*allow_placement)
+				.write_bool(*allow_deletion),
+			Self::ChangeModel { entity_id, model } => {
+				writer.write_i8(*entity_id).write_string(model)
+			}
 			Self::EnvWeatherType { weather_type } => writer.write_u8(weather_type.into()),
-			Self::SetInventoryOrder { order, block } => writer.write_u8(*order).write_u8(*block),
+			Self::HackControl {
+				flying,
+				no_clip,
+				speeding,
+				spawn_control,
+				third_person_view,
+				jump_height,
+			} => writer
+				.write_bool(*flying)
+				.write_bool(*no_clip)
+				.write_bool(*speeding)
+				.write_bool(*spawn_control)
+				.write_bool(*third_person_view)
+				.write_i16(*jump_height),
+			Self::DefineBlock { definition } => writer
+				.write_block(definition.id)
+				.write_string(&definition.name)
+				.write_u8(definition.solidity as u8)
+				.write_u8(definition.movement_speed)
+				.write_u8(definition.top_texture)
+				.write_u8(definition.side_texture)
+				.write_u8(definition.bottom_texture)
+				.write_bool(definition.transmits_light)
+				.write_u8(definition.walk_sound)
+				.write_bool(definition.full_bright)
+				.write_u8(definition.shape)
+				.write_u8(definition.draw as u8)
+				.write_u8(definition.fog_density)
+				.write_u8(definition.fog_color[0])
+				.write_u8(definition.fog_color[1])
+				.write_u8(definition.fog_color[2]),
+			Self::BulkBlockUpdate { indices, blocks } => {
+				let mut writer = writer.write_u8((indices.len() - 1) as u8);
+				for i in 0..BULK_BLOCK_UPDATE_COUNT {
+					writer = writer.write_i32(indices.get(i).copied().unwrap_or_default());
+				}
+				writer.write_bulk_blocks(blocks)
+			}
+			Self::TwoWayPing { direction, data } => writer.write_u8(*direction).write_i16(*data),
+			Self::SetInventoryOrder { block, order } => {
+				writer.write_block(*block).write_block(*order)
+			}
+			Self::SetSpawnpoint {
+				x,
+				y,
+				z,
+				yaw,
+				pitch,
+			} => writer
+				.write_f16(*x)
+				.write_f16(*y)
+				.write_f16(*z)
+				.write_u8(*yaw)
+				.write_u8(*pitch),
 			Self::ExtEntityTeleport {
 				entity_id,
 				teleport_behavior,
@@ -302,6 +497,7 @@ impl ServerPacket {
 			Self::DespawnPlayer { player_id, .. } => *player_id,
 			Self::Message { player_id, .. } => *player_id,
 			Self::ExtEntityTeleport { entity_id, .. } => *entity_id,
+			Self::ChangeModel { entity_id, .. } => *entity_id,
 			_ => return None,
 		})
 	}
@@ -317,6 +513,7 @@ impl ServerPacket {
 			Self::DespawnPlayer { player_id, .. } => *player_id = new_player_id,
 			Self::Message { player_id, .. } => *player_id = new_player_id,
 			Self::ExtEntityTeleport { entity_id, .. } => *entity_id = new_player_id,
+			Self::ChangeModel { entity_id, .. } => *entity_id = new_player_id,
 			_ => {}
 		}
 	}
@@ -325,11 +522,34 @@ impl ServerPacket {
 	pub fn should_echo(&self) -> bool {
 		matches!(
 			self,
-			Self::SetBlock { .. } | Self::SpawnPlayer { .. } | Self::Message { .. }
+			Self::SetBlock { .. }
+				| Self::SpawnPlayer { .. }
+				| Self::Message { .. }
+				| Self::ChangeModel { .. }
 		)
 	}
 }
 
+/// where a message is shown on clients supporting MessageTypes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)]
+pub enum MessageType {
+	/// a normal chat message
+	Chat = 0,
+	/// the top line of the status area in the top right
+	Status1 = 1,
+	/// the middle line of the status area in the top right
+	Status2 = 2,
+	/// the bottom line of the status area in the top right
+	Status3 = 3,
+	/// the bottom line of the status area in the bottom right
+	BottomRight1 = 11,
+	/// the middle line of the status area in the bottom right
+	BottomRight2 = 12,
+	/// the top line of the status area in the bottom right
+	BottomRight3 = 13,
+}
+
 /// bitmask for ExtEntityTeleport's teleport behavior
 #[bitmask_enum::bitmask(u8)]
 pub enum TeleportBehavior {
@@ -1,12 +1,20 @@
 use std::{
+	collections::VecDeque,
 	net::SocketAddr,
 	ops::{Deref, DerefMut},
+	time::Instant,
 };
 
 use half::f16;
 use serde::{Deserialize, Serialize};
 
-use crate::packet::{server::ServerPacket, ExtBitmask};
+use crate::{
+	packet::{server::ServerPacket, ExtBitmask},
+	selection::PendingSelection,
+};
+
+/// the model players are shown with by default
+pub const DEFAULT_MODEL: &str = "humanoid";
 
 /// struct for players
 #[derive(Debug)]
@@ -19,9 +27,13 @@ pub struct Player {
 	pub savable_data: SavablePlayerData,
 	/// the player's permission state
 	pub permissions: PlayerType,
+	/// the locale server messages are sent to the player in
+	pub locale: String,
+	/// the name of the level the player is in
+	pub current_level: String,
 
 	/// the player's IP address
-	pub _addr: SocketAddr,
+	pub addr: SocketAddr,
 	/// the player's supported extensions
 	pub extensions: ExtBitmask,
 	/// the level of custom blocks this client supports
@@ -30,6 +42,142 @@ pub struct Player {
 	pub packets_to_send: Vec<ServerPacket>,
 	/// whether this player should be kicked and the message to give
 	pub should_be_kicked: Option<String>,
+	/// the selection the player is currently making, if any
+	pub pending_selection: Option<PendingSelection>,
+	/// the player's most recent block edits, oldest first
+	pub edit_history: VecDeque<BlockEdit>,
+	/// the state of pings sent to the player, for clients supporting TwoWayPing
+	pub ping: PingState,
+	/// the model the player is shown with, for clients supporting ChangeModel
+	pub model: String,
+	/// limits how quickly the player can change blocks
+	pub block_rate: BlockRateLimiter,
+	/// where the player was before they were last teleported, for /back
+	pub previous_position: Option<(f16, f16, f16, u8, u8)>,
+	/// when the last packet was received from the player
+	pub last_activity: Instant,
+}
+
+impl Player {
+	/// records a block edit made by the player, dropping the oldest edits past the given limit
+	pub fn record_edit(&mut self, edit: BlockEdit, limit: usize) {
+		self.edit_history.push_back(edit);
+		while self.edit_history.len() > limit {
+			self.edit_history.pop_front();
+		}
+	}
+}
+
+/// state for measuring a player's latency
+#[derive(Debug, Default)]
+pub struct PingState {
+	/// the id to give the next ping sent
+	next_id: i16,
+	/// the id of the ping awaiting an answer and when it was sent
+	pending: Option<(i16, Instant)>,
+	/// the rolling average round trip time in milliseconds, if any pings have been answered
+	pub rtt_ms: Option<u32>,
+}
+
+impl PingState {
+	/// gets the id for a new ping
+	pub fn next_id(&mut self) -> i16 {
+		let id = self.next_id;
+		self.next_id = self.next_id.wrapping_add(1);
+		id
+	}
+
+	/// marks the ping with the given id as sent just now
+	pub fn sent(&mut self, id: i16) {
+		self.pending = Some((id, Instant::now()));
+	}
+
+	/// handles an answer to a ping, returning whether it matched the pending ping
+	pub fn answer(&mut self, id: i16) -> bool {
+		let Some((pending_id, sent)) = self.pending else {
+			return false;
+		};
+		if pending_id != id {
+			return false;
+		}
+		self.pending = None;
+		let rtt = sent.elapsed().as_millis() as u32;
+		self.rtt_ms = Some(match self.rtt_ms {
+			Some(average) => (average * 3 + rtt) / 4,
+			None => rtt,
+		});
+		true
+	}
+}
+
+/// token bucket limiting how quickly a player can change blocks
+#[derive(Debug)]
+pub struct BlockRateLimiter {
+	/// the block changes the player can currently make
+	tokens: f32,
+	/// when tokens were last added to the bucket
+	last_refill: Instant,
+	/// the number of block changes rejected since the player last stayed under the limit
+	pub rejected_in_a_row: u32,
+	/// the number of block changes made this session
+	pub changed: u64,
+	/// the number of block changes rejected this session
+	pub rejected: u64,
+}
+
+impl Default for BlockRateLimiter {
+	fn default() -> Self {
+		Self {
+			tokens: f32::INFINITY,
+			last_refill: Instant::now(),
+			rejected_in_a_row: 0,
+			changed: 0,
+			rejected: 0,
+		}
+	}
+}
+
+impl BlockRateLimiter {
+	/// tries to use up a block change, returning whether the player is still under the limit
+	///
+	/// players can make bursts of up to a second's worth of changes at once, a limit of 0 allows every change
+	pub fn allow(&mut self, per_second: u32) -> bool {
+		if per_second == 0 {
+			self.changed += 1;
+			return true;
+		}
+
+		let now = Instant::now();
+		let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+		self.last_refill = now;
+		self.tokens = (self.tokens + elapsed * per_second as f32).min(per_second as f32);
+
+		if self.tokens >= 1.0 {
+			self.tokens -= 1.0;
+			self.rejected_in_a_row = 0;
+			self.changed += 1;
+			true
+		} else {
+			self.rejected_in_a_row += 1;
+			self.rejected += 1;
+			false
+		}
+	}
+}
+
+/// a block changed by a player, along with what it was before
+#[derive(Debug, Clone)]
+pub struct BlockEdit {
+	/// the name of the level the block was changed in
+	pub level: String,
+	/// the X coordinate of the block
+	pub x: usize,
+	/// the Y coordinate of the block
+	pub y: usize,
+	/// the Z coordinate of the block
+	pub z: usize,
+	/// the block before it was changed
+	pub previous_block: u16,
 }
 
 impl Deref for Player {
@@ -59,11 +207,30 @@ pub struct SavablePlayerData {
 	pub yaw: u8,
 	/// the player's pitch
 	pub pitch: u8,
+	/// the player's personal spawn point, if they've set one
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub spawn: Option<PlayerSpawn>,
+}
+
+/// a spawn point set by a player for themselves
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PlayerSpawn {
+	/// the spawn's X coordinate
+	pub x: f16,
+	/// the spawn's Y coordinate
+	pub y: f16,
+	/// the spawn's Z coordinate
+	pub z: f16,
+	/// the spawn's yaw
+	pub yaw: u8,
+	/// the spawn's pitch
+	pub pitch: u8,
 }
 
 /// enum describing types of players
 #[derive(
 	Debug,
+	Default,
 	Clone,
 	Copy,
 	PartialEq,
@@ -78,6 +245,7 @@ pub struct SavablePlayerData {
 #[strum(ascii_case_insensitive)]
 pub enum PlayerType {
 	/// a normal player
+	#[default]
 	Normal,
 	/// moderator of the server
 	Moderator,
@@ -85,18 +253,22 @@ pub enum PlayerType {
 	Operator,
 }
 
-impl Default for PlayerType {
-	fn default() -> Self {
-		Self::Normal
+impl PlayerType {
+	/// gets the rank above this one, if there is one
+	pub fn promoted(self) -> Option<Self> {
+		match self {
+			Self::Normal => Some(Self::Moderator),
+			Self::Moderator => Some(Self::Operator),
+			Self::Operator => None,
+		}
 	}
-}
 
-impl From<&PlayerType> for u8 {
-	fn from(val: &PlayerType) -> Self {
-		match val {
-			PlayerType::Normal => 0,
-			PlayerType::Moderator => 0x64,
-			PlayerType::Operator => 0x64,
+	/// gets the rank below this one, if there is one
+	pub fn demoted(self) -> Option<Self> {
+		match self {
+			Self::Normal => None,
+			Self::Moderator => Some(Self::Normal),
+			Self::Operator => Some(Self::Moderator),
 		}
 	}
 }
@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	level::{block::BLOCK_INFO, BlockUpdate, ProtectedRegion},
+	locale::tr,
+	packet::server::ServerPacket,
+	player::PlayerType,
+	server::ServerData,
+};
+
+/// the id used for previewing a player's selection on clients supporting SelectionCuboid
+pub const SELECTION_PREVIEW_ID: u8 = 0;
+
+/// an axis-aligned cuboid of blocks, inclusive of both corners
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cuboid {
+	/// the corner with the lowest coordinates
+	pub min: (usize, usize, usize),
+	/// the corner with the highest coordinates
+	pub max: (usize, usize, usize),
+}
+
+impl Cuboid {
+	/// creates a cuboid from any two opposite corners
+	pub fn from_corners(a: (usize, usize, usize), b: (usize, usize, usize)) -> Self {
+		Self {
+			min: (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+			max: (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+		}
+	}
+
+	/// gets the size of the cuboid on each axis
+	pub fn dimensions(&self) -> (usize, usize, usize) {
+		(
+			self.max.0 - self.min.0 + 1,
+			self.max.1 - self.min.1 + 1,
+			self.max.2 - self.min.2 + 1,
+		)
+	}
+
+	/// gets the number of blocks in the cuboid
+	pub fn volume(&self) -> usize {
+		let (x, y, z) = self.dimensions();
+		x * y * z
+	}
+
+	/// gets the number of blocks on the outside of the cuboid
+	pub fn shell_volume(&self) -> usize {
+		let (x, y, z) = self.dimensions();
+		self.volume() - x.saturating_sub(2) * y.saturating_sub(2) * z.saturating_sub(2)
+	}
+
+	/// gets whether the given coordinates are inside the cuboid
+	pub fn contains(&self, x: usize, y: usize, z: usize) -> bool {
+		(self.min.0..=self.max.0).contains(&x)
+			&& (self.min.1..=self.max.1).contains(&y)
+			&& (self.min.2..=self.max.2).contains(&z)
+	}
+
+	/// gets whether the given coordinates are on the outside of the cuboid
+	pub fn is_shell(&self, x: usize, y: usize, z: usize) -> bool {
+		self.contains(x, y, z)
+			&& (x == self.min.0
+				|| x == self.max.0
+				|| y == self.min.1
+				|| y == self.max.1
+				|| z == self.min.2
+				|| z == self.max.2)
+	}
+
+	/// creates a packet highlighting the cuboid as the player's selection preview
+	pub fn preview_packet(&self) -> ServerPacket {
+		ServerPacket::MakeSelection {
+			selection_id: SELECTION_PREVIEW_ID,
+			label: "selection".to_string(),
+			start_x: self.min.0 as i16,
+			start_y: self.min.1 as i16,
+			start_z: self.min.2 as i16,
+			end_x: self.max.0 as i16,
+			end_y: self.max.1 as i16,
+			end_z: self.max.2 as i16,
+			red: 0x40,
+			green: 0xc0,
+			blue: 0xff,
+			opacity: 0x80,
+		}
+	}
+
+	/// iterates over every block position in the cuboid
+	pub fn cells(&self) -> impl Iterator<Item = (usize, usize, usize)> {
+		let (min, max) = (self.min, self.max);
+		(min.1..=max.1).flat_map(move |y| {
+			(min.2..=max.2).flat_map(move |z| (min.0..=max.0).map(move |x| (x, y, z)))
+		})
+	}
+}
+
+/// action to take once a player has selected both corners of a cuboid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionAction {
+	/// fills the whole selection with the given block
+	Fill { block: u16 },
+	/// fills the outside of the selection with the given block, leaving air inside
+	Hollow { block: u16 },
+	/// reports how many blocks the selection would affect
+	Count,
+	/// reports the size of the selection
+	Measure,
+	/// protects the selection so only players of at least the given rank may edit it
+	Protect { permissions: PlayerType },
+}
+
+/// a selection which a player is in the middle of making
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingSelection {
+	/// what to do once the selection is complete
+	pub action: SelectionAction,
+	/// the first corner, if it's been selected yet
+	pub first_corner: Option<(usize, usize, usize)>,
+}
+
+impl PendingSelection {
+	/// creates a new pending selection for the given action
+	pub fn new(action: SelectionAction) -> Self {
+		Self {
+			action,
+			first_corner: None,
+		}
+	}
+}
+
+impl SelectionAction {
+	/// applies the action to the completed selection, returning messages for the player
+	pub fn apply(self, data: &mut ServerData, own_id: i8, cuboid: Cuboid) -> Vec<String> {
+		let player = data.player(own_id).expect("missing player");
+		let locale = player.locale.clone();
+		let locale = locale.as_str();
+		let player_type = player.permissions;
+		let level_name = player.current_level.clone();
+		let max_selection_volume = data.config.max_selection_volume;
+		let level = data
+			.levels
+			.get_mut(&level_name)
+			.expect("player should always be in a loaded level");
+		let (x_size, y_size, z_size) = cuboid.dimensions();
+
+		match self {
+			Self::Fill { block } | Self::Hollow { block } => {
+				if cuboid.volume() > max_selection_volume {
+					return vec![tr!(
+						locale,
+						"selection.too_large",
+						cuboid.volume(),
+						max_selection_volume
+					)];
+				}
+
+				let needs_update = BLOCK_INFO
+					.get(&block)
+					.expect("missing block")
+					.block_type
+					.needs_update_on_place();
+				let mut changed = 0;
+				for (x, y, z) in cuboid.cells() {
+					let new_block = if matches!(self, Self::Fill { .. }) || cuboid.is_shell(x, y, z)
+					{
+						block
+					} else {
+						0
+					};
+					let current = level.get_block(x, y, z);
+					if current == new_block
+						|| BLOCK_INFO
+							.get(&current)
+							.expect("missing block")
+							.break_permissions > player_type
+						|| level.edit_permissions(x, y, z) > player_type
+					{
+						continue;
+					}
+					let index = level.index(x, y, z);
+					level.updates.push(BlockUpdate {
+						index,
+						block: new_block,
+					});
+					if needs_update && new_block == block {
+						level.awaiting_update.insert(index);
+					}
+					changed += 1;
+				}
+				vec![tr!(locale, "selection.changed", changed)]
+			}
+			Self::Count => vec![
+				tr!(locale, "selection.dimensions", x_size, y_size, z_size),
+				tr!(
+					locale,
+					"selection.count",
+					cuboid.volume(),
+					cuboid.shell_volume(),
+					max_selection_volume
+				),
+			],
+			Self::Measure => vec![tr!(
+				locale,
+				"selection.measure",
+				x_size,
+				y_size,
+				z_size,
+				cuboid.volume()
+			)],
+			Self::Protect { permissions } => {
+				level.protected_regions.push(ProtectedRegion {
+					cuboid,
+					permissions,
+				});
+				let rank: &'static str = permissions.into();
+				vec![tr!(
+					locale,
+					"selection.protected",
+					x_size,
+					y_size,
+					z_size,
+					rank
+				)]
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeSet;
+
+	use crate::{
+		player::PlayerType,
+		server::testing::{add_player, test_data},
+	};
+
+	use super::{Cuboid, SelectionAction};
+
+	#[test]
+	fn hollow_cuboids_keep_only_their_shell() {
+		let cuboid = Cuboid::from_corners((5, 14, 2), (2, 11, 6));
+		assert_eq!(cuboid.dimensions(), (4, 4, 5));
+		assert_eq!(cuboid.cells().count(), cuboid.volume());
+		assert_eq!(cuboid.cells().collect::<BTreeSet<_>>().len(), 80);
+
+		let inside: BTreeSet<_> = (12..=13)
+			.flat_map(|y| (3..=5).flat_map(move |z| (3..=4).map(move |x| (x, y, z))))
+			.collect();
+		let shell: BTreeSet<_> = cuboid.cells().filter(|c| !inside.contains(c)).collect();
+		assert!(cuboid
+			.cells()
+			.all(|(x, y, z)| cuboid.is_shell(x, y, z) == shell.contains(&(x, y, z))));
+		assert_eq!(shell.len(), cuboid.shell_volume());
+
+		let mut data = test_data();
+		let id = add_player(&mut data, "alice", PlayerType::Operator);
+		let level_name = data.config.level_name.clone();
+		let level = data.levels.get_mut(&level_name).unwrap();
+		// the whole box starts out solid so the inside has to be cleared
+		for (x, y, z) in cuboid.cells() {
+			level.set_block(x, y, z, 2);
+		}
+		let messages = SelectionAction::Hollow { block: 1 }.apply(&mut data, id, cuboid);
+		assert_eq!(messages, [format!("Changed {} blocks.", cuboid.volume())]);
+
+		let level = data.levels.get_mut(&level_name).unwrap();
+		level.apply_updates();
+		for (x, y, z) in cuboid.cells() {
+			let expected = if shell.contains(&(x, y, z)) { 1 } else { 0 };
+			assert_eq!(level.get_block(x, y, z), expected, "at {x}, {y}, {z}");
+		}
+	}
+}
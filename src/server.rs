@@ -1,29 +1,55 @@
+mod audit;
 pub mod config;
+mod heartbeat;
+pub mod hooks;
 pub(crate) mod network;
+pub(crate) mod password;
+#[cfg(test)]
+pub(crate) mod testing;
 
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, net::IpAddr, path::PathBuf, sync::Arc, time::Instant};
 
-use tokio::{net::TcpListener, sync::RwLock};
+use half::f16;
+use rand::{seq::SliceRandom, Rng};
+use tokio::{net::TcpListener, sync::RwLock, task::JoinHandle};
 
 use crate::{
+	command::PendingCommand,
 	error::GeneralError,
 	level::{
+		block::definition::register_block_definitions,
 		block::{
-			BlockType, BLOCK_INFO, ID_LAVA_FLOWING, ID_LAVA_STATIONARY, ID_STONE, ID_WATER_FLOWING,
-			ID_WATER_STATIONARY,
+			is_water, BlockType, BLOCK_INFO, ID_BEDROCK, ID_DIRT, ID_GRASS, TNT_CHAIN_FUSE_TICKS,
 		},
-		BlockUpdate, Level,
+		classic_world::CW_EXTENSION,
+		BlockUpdate, Level, CORRUPTED_LEVEL_MARKER,
 	},
-	packet::server::ServerPacket,
-	player::Player,
-	util::neighbors_minus_up,
+	locale::{tr, Translatable},
+	packet::{
+		server::{MessageType, ServerPacket},
+		ExtBitmask,
+	},
+	player::{Player, PlayerType},
+	util::{cube_around, neighbors},
 	CONFIG_FILE,
 };
 
-use self::config::ServerConfig;
+pub(crate) use self::audit::AuditEvent;
+use self::{
+	audit::AuditLog,
+	config::{ConfigCoordinatesWithOrientation, ConfigSpawn, ServerConfig},
+	hooks::ServerHook,
+};
 
 const TICK_DURATION: std::time::Duration = std::time::Duration::from_millis(50);
-const LEVELS_PATH: &str = "levels";
+/// how often players are pinged
+const PING_INTERVAL_TICKS: usize = 100;
+/// how often the colors driven by each level's time of day are updated, in ticks
+const TIME_COLOR_INTERVAL_TICKS: usize = 20;
+/// the number of blocks randomly ticked each tick for every 16x16x16 blocks in a level
+const RANDOM_TICKS_PER_SECTION: usize = 3;
+/// directory every level is saved in, each in its own directory named after the level
+pub(crate) const LEVELS_PATH: &str = "levels";
 
 /// the server
 #[derive(Debug)]
@@ -37,8 +63,8 @@ pub struct Server {
 /// shared server data
 #[derive(Debug)]
 pub struct ServerData {
-	/// the level
-	pub level: Level,
+	/// the loaded levels, by name
+	pub levels: BTreeMap<String, Level>,
 	/// list of players connected to the server
 	pub players: Vec<Player>,
 	/// list of player ids which have been freed up
@@ -49,66 +75,477 @@ pub struct ServerData {
 	pub config_needs_saving: bool,
 	/// whether the server should be stopped
 	pub stop: bool,
+	/// recent failed login attempts by address
+	pub failed_logins: BTreeMap<IpAddr, FailedLogins>,
+	/// random salt sent with heartbeats, generated each time the server starts
+	pub salt: String,
+	/// log of chat, commands and moderation events
+	pub audit: AuditLog,
+	/// custom logic run when things happen on the server, if any
+	pub hook: Option<Box<dyn ServerHook>>,
+	/// commands whose work is still being done on blocking threads
+	pub pending_commands: Vec<PendingCommand>,
+}
+
+/// record of recent failed login attempts from an address
+#[derive(Debug, Clone, Copy)]
+pub struct FailedLogins {
+	/// the number of failed attempts
+	pub count: u32,
+	/// when the last failed attempt happened
+	pub last_attempt: Instant,
 }
 
 impl ServerData {
-	/// spreads a packet to all players
-	pub fn spread_packet(&mut self, packet: ServerPacket) {
+	/// creates the server's data with the given levels, which must include the main level
+	pub fn new(
+		config: ServerConfig,
+		levels: BTreeMap<String, Level>,
+		hook: Option<Box<dyn ServerHook>>,
+	) -> Self {
+		let audit = AuditLog::start(&config.audit_log);
+		Self {
+			levels,
+			players: Default::default(),
+			free_player_ids: Vec::new(),
+			config,
+			config_needs_saving: true,
+			stop: false,
+			failed_logins: Default::default(),
+			salt: heartbeat::generate_salt(),
+			audit,
+			hook,
+			pending_commands: Vec::new(),
+		}
+	}
+
+	/// runs the server's hook if it has one, returning whether the action it was run for should be cancelled
+	pub fn run_hook(&mut self, f: impl FnOnce(&mut dyn ServerHook, &mut Self) -> bool) -> bool {
+		let Some(mut hook) = self.hook.take() else {
+			return false;
+		};
+		let cancelled = f(hook.as_mut(), self);
+		self.hook = Some(hook);
+		cancelled
+	}
+
+	/// gets the number of players connected to the server
+	pub fn player_count(&self) -> usize {
+		self.players.len()
+	}
+
+	/// gets the usernames of all players connected to the server
+	pub fn online_usernames(&self) -> impl Iterator<Item = &str> {
+		self.players.iter().map(|p| p.username.as_str())
+	}
+
+	/// finds the index of the connected player matching the given name, ignoring case
+	///
+	/// an exact match is preferred, otherwise the name may be a prefix of exactly one player's username
+	fn find_player_index(&self, name: &str) -> Result<Option<usize>, Translatable> {
+		let lowercase = name.to_lowercase();
+		if let Some(index) = self
+			.players
+			.iter()
+			.position(|p| p.username.to_lowercase() == lowercase)
+		{
+			return Ok(Some(index));
+		}
+
+		let mut matches = self
+			.players
+			.iter()
+			.enumerate()
+			.filter(|(_, p)| p.username.to_lowercase().starts_with(&lowercase))
+			.map(|(index, _)| index);
+		let index = matches.next();
+		if matches.next().is_some() {
+			return Err(Translatable::new("command.player_ambiguous").arg(name));
+		}
+		Ok(index)
+	}
+
+	/// finds a connected player by name, ignoring case and accepting a unique prefix of their username
+	pub fn find_player(&self, name: &str) -> Result<&Player, Translatable> {
+		self.find_player_index(name)?
+			.map(|index| &self.players[index])
+			.ok_or_else(|| Translatable::new("command.player_not_connected"))
+	}
+
+	/// finds a connected player by name, ignoring case and accepting a unique prefix of their username
+	pub fn find_player_mut(&mut self, name: &str) -> Result<&mut Player, Translatable> {
+		self.find_player_index(name)?
+			.map(|index| &mut self.players[index])
+			.ok_or_else(|| Translatable::new("command.player_not_connected"))
+	}
+
+	/// resolves a name to the username of the connected player it matches ignoring case, or returns it unchanged if none do
+	///
+	/// prefixes aren't accepted, since the name may be meant for a player who isn't online
+	pub fn resolve_username(&self, name: &str) -> String {
+		let lowercase = name.to_lowercase();
+		self.players
+			.iter()
+			.find(|p| p.username.to_lowercase() == lowercase)
+			.map_or_else(|| name.to_string(), |p| p.username.clone())
+	}
+
+	/// gets a connected player by their id
+	pub fn player(&self, id: i8) -> Option<&Player> {
+		self.players.iter().find(|p| p.id == id)
+	}
+
+	/// gets a connected player by their id
+	pub fn player_mut(&mut self, id: i8) -> Option<&mut Player> {
+		self.players.iter_mut().find(|p| p.id == id)
+	}
+
+	/// checks whether logins from the given address are currently being refused, clearing expired records
+	pub fn login_throttled(&mut self, ip: IpAddr) -> bool {
+		let Some(failed) = self.failed_logins.get(&ip) else {
+			return false;
+		};
+		if failed.last_attempt.elapsed().as_secs() >= self.config.login_cooldown_seconds {
+			self.failed_logins.remove(&ip);
+			return false;
+		}
+		failed.count >= self.config.max_login_attempts
+	}
+
+	/// records a failed login attempt from the given address
+	pub fn record_failed_login(&mut self, ip: IpAddr) {
+		let failed = self.failed_logins.entry(ip).or_insert(FailedLogins {
+			count: 0,
+			last_attempt: Instant::now(),
+		});
+		failed.count += 1;
+		failed.last_attempt = Instant::now();
+	}
+
+	/// gets the level the given player is in
+	pub fn player_level(&self, id: i8) -> Option<&Level> {
+		self.levels.get(&self.player(id)?.current_level)
+	}
+
+	/// gets the level the given player is in
+	pub fn player_level_mut(&mut self, id: i8) -> Option<&mut Level> {
+		let level_name = self.player(id)?.current_level.clone();
+		self.levels.get_mut(&level_name)
+	}
+
+	/// gets the block at the given position in a level
+	pub fn get_block(
+		&self,
+		level_name: &str,
+		x: usize,
+		y: usize,
+		z: usize,
+	) -> Result<u16, GeneralError> {
+		let level = self
+			.levels
+			.get(level_name)
+			.ok_or_else(|| GeneralError::Custom(format!("unknown level {level_name}")))?;
+		if x >= level.x_size || y >= level.y_size || z >= level.z_size {
+			return Err(GeneralError::Custom(format!(
+				"{x}, {y}, {z} is outside of level {level_name}"
+			)));
+		}
+		Ok(level.get_block(x, y, z))
+	}
+
+	/// changes a block in a level, sending the change to everyone in the level right away
+	pub fn set_block_broadcast(
+		&mut self,
+		level_name: &str,
+		x: usize,
+		y: usize,
+		z: usize,
+		block: u16,
+	) -> Result<(), GeneralError> {
+		self.get_block(level_name, x, y, z)?;
+		if !BLOCK_INFO.contains_key(&block) {
+			return Err(GeneralError::Custom(format!("unknown block id {block:#x}")));
+		}
+		let level = self
+			.levels
+			.get_mut(level_name)
+			.expect("level should exist after getting a block from it");
+		level.queue_block(level.index(x, y, z), block);
+		let packets = level.apply_updates();
+		self.spread_packets(level_name, &packets);
+		Ok(())
+	}
+
+	/// gets the spawn points for the given level
+	///
+	/// the configured spawn only applies to the main level
+	fn level_spawn_points(&self, level_name: &str) -> Vec<ConfigCoordinatesWithOrientation> {
+		let points = self
+			.config
+			.spawn
+			.as_ref()
+			.filter(|_| level_name == self.config.level_name)
+			.map(|spawn| spawn.points().to_vec())
+			.unwrap_or_default();
+		if !points.is_empty() {
+			return points;
+		}
+		let level = self.levels.get(level_name);
+		// players spawn standing on the center of the level, or at a fixed point if there's nothing to stand on there
+		let (x, y, z) =
+			match level.and_then(|l| Some((l, l.surface_height(l.x_size / 2, l.z_size / 2)?))) {
+				Some((level, top)) => (
+					(level.x_size / 2) as f32 + 0.5,
+					(top + 3) as f32,
+					(level.z_size / 2) as f32 + 0.5,
+				),
+				None => {
+					let y_size = level.map(|l| l.y_size).unwrap_or(0);
+					(16.5, (y_size / 2 + 2) as f32, 16.5)
+				}
+			};
+		vec![ConfigCoordinatesWithOrientation {
+			x,
+			y,
+			z,
+			yaw: 0,
+			pitch: 0,
+		}]
+	}
+
+	/// gets the spawn point for the given level
+	pub fn level_spawn(&self, level_name: &str) -> (f16, f16, f16, u8, u8) {
+		// the main level may have several spawn points to spread players out between
+		let spawn = self
+			.level_spawn_points(level_name)
+			.choose(&mut rand::thread_rng())
+			.cloned()
+			.expect("levels should always have a spawn point");
+		(
+			f16::from_f32(spawn.x),
+			f16::from_f32(spawn.y),
+			f16::from_f32(spawn.z),
+			spawn.yaw,
+			spawn.pitch,
+		)
+	}
+
+	/// checks whether the given block is within the level's spawn protection radius of one of its spawn points
+	pub fn is_spawn_protected(&self, level_name: &str, x: usize, z: usize) -> bool {
+		let Some(level) = self.levels.get(level_name) else {
+			return false;
+		};
+		let radius = level.rules.spawn_protection_radius;
+		radius != 0
+			&& self.level_spawn_points(level_name).iter().any(|spawn| {
+				(x as f32 - spawn.x.floor()).abs() <= radius as f32
+					&& (z as f32 - spawn.z.floor()).abs() <= radius as f32
+			})
+	}
+
+	/// spreads a packet to all players in the given level
+	pub fn spread_packet(&mut self, level_name: &str, packet: ServerPacket) {
+		self.spread_packets(level_name, &[packet]);
+	}
+
+	/// spreads multiple packets to all players in the given level
+	pub fn spread_packets(&mut self, level_name: &str, packets: &[ServerPacket]) {
+		for player in self
+			.players
+			.iter_mut()
+			.filter(|p| p.current_level == level_name)
+		{
+			for packet in packets {
+				player.packets_to_send.push(packet.clone());
+			}
+		}
+	}
+
+	/// spreads a packet to all players in every level
+	pub fn spread_packet_global(&mut self, packet: ServerPacket) {
 		for player in &mut self.players {
 			player.packets_to_send.push(packet.clone());
 		}
 	}
 
-	/// spreads multiple packets to all players
-	pub fn spread_packets(&mut self, packets: &[ServerPacket]) {
+	/// sends a message of the given type to a player, falling back to chat for clients without MessageTypes
+	pub fn send_typed_message(
+		&mut self,
+		player_id: i8,
+		message_type: MessageType,
+		message: String,
+	) {
+		let Some(player) = self.player_mut(player_id) else {
+			return;
+		};
+		let packet = if player.extensions.contains(ExtBitmask::MessageTypes) {
+			ServerPacket::TypedMessage {
+				message_type,
+				message,
+			}
+		} else {
+			ServerPacket::Message {
+				player_id: -1,
+				message,
+			}
+		};
+		player.packets_to_send.push(packet);
+	}
+
+	/// shows the name of the player's current level in their status bar
+	pub fn send_level_status(&mut self, player_id: i8) {
+		let Some(player) = self.player(player_id) else {
+			return;
+		};
+		let message = tr!(&player.locale, "status.level", player.current_level);
+		self.send_typed_message(player_id, MessageType::Status1, message);
+	}
+
+	/// refreshes the online count and level name in the bottom right of every player's screen which supports it
+	pub fn send_online_status(&mut self) {
+		let online = self.players.len();
+		let max_players = self.config.max_players;
 		for player in &mut self.players {
-			for packet in packets {
-				player.packets_to_send.push(packet.clone());
+			if !player.extensions.contains(ExtBitmask::MessageTypes) {
+				continue;
 			}
+			let message = tr!(
+				&player.locale,
+				"status.online",
+				online,
+				max_players,
+				player.current_level
+			);
+			player.packets_to_send.push(ServerPacket::TypedMessage {
+				message_type: MessageType::BottomRight2,
+				message,
+			});
+		}
+	}
+
+	/// finishes the commands whose work has been done since this was last called
+	pub async fn finish_pending_commands(&mut self) {
+		let finished: Vec<_> = self
+			.pending_commands
+			.extract_if(.., |command| command.is_finished())
+			.collect();
+		for command in finished {
+			command.finish(self).await;
 		}
 	}
 }
 
 impl Server {
-	/// creates a new server with a generated level
-	pub async fn new(config: ServerConfig) -> Result<Self, GeneralError> {
+	/// creates a new server, loading all saved levels and generating the main level if needed
+	pub async fn new(
+		mut config: ServerConfig,
+		hook: Option<Box<dyn ServerHook>>,
+	) -> Result<Self, GeneralError> {
+		config.validate_level_size()?;
+		config.warn_long_strings();
+		register_block_definitions(config.block_definitions.clone())?;
+
 		let levels_path = PathBuf::from(LEVELS_PATH);
 		if !levels_path.exists() {
 			std::fs::create_dir_all(&levels_path)?;
 		}
-		let level_path = levels_path.join(&config.level_name);
-		let level = if level_path.exists() {
-			Level::load(level_path).await?
+		let mut level_path = levels_path.join(&config.level_name);
+
+		// ClassicWorld levels are imported once, then kept in the native format under the same name
+		let mut imported_level = None;
+		if level_path.is_file()
+			&& level_path.extension().and_then(|e| e.to_str()) == Some(CW_EXTENSION)
+		{
+			println!("importing level from {}", level_path.display());
+			let (mut level, spawn) = Level::load_cw(&level_path).await?;
+			if spawn.is_some() {
+				config.spawn = spawn.map(ConfigSpawn::Single);
+			}
+			config.level_name = level_path
+				.file_stem()
+				.and_then(|s| s.to_str())
+				.unwrap_or_default()
+				.to_string();
+			level_path = levels_path.join(&config.level_name);
+			level.save(&level_path, config.compression()).await?;
+			imported_level = Some(level);
+		}
+
+		let level = if imported_level.is_some() {
+			imported_level
+		} else if level_path.exists() {
+			let level = Level::load_or_recover(&level_path).await?;
+			if level.is_none() {
+				// the corrupted level is kept in case it can be repaired by hand
+				let corrupted_path = Level::move_corrupted(&level_path).await?;
+				eprintln!(
+					"level has no valid backups, moved it to {} and generating a new one",
+					corrupted_path.display()
+				);
+			}
+			level
+		} else {
+			None
+		};
+		let level = if let Some(level) = level {
+			level
 		} else {
 			println!("generating level");
-			let mut rng = rand::thread_rng();
-			let mut level = Level::new(
-				config.level_size.x,
-				config.level_size.y,
-				config.level_size.z,
-			);
-			config.generation.generate(&mut level, &mut rng);
-			level.save(level_path).await?;
+			let size = config.level_size.clone();
+			let generation = config.generation.clone();
+			let features = config.generation_features.clone();
+			let seed = config.generation_seed.unwrap_or_else(rand::random);
+			let mut level = tokio::task::spawn_blocking(move || {
+				let mut level = Level::new(size.x, size.y, size.z);
+				generation.generate_seeded(&mut level, &features, seed);
+				level
+			})
+			.await?;
+			level.save(level_path, config.compression()).await?;
 			println!("done!");
 			level
 		};
 
-		Self::new_with_level(config, level).await
+		let mut levels = BTreeMap::new();
+		for entry in std::fs::read_dir(&levels_path)?.flatten() {
+			let path = entry.path();
+			let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+				continue;
+			};
+			if !path.is_dir() || name == config.level_name || name.contains(CORRUPTED_LEVEL_MARKER)
+			{
+				continue;
+			}
+			match Level::load_or_recover(&path).await {
+				Ok(Some(level)) => {
+					levels.insert(name.to_string(), level);
+				}
+				Ok(None) => eprintln!("level {name} is corrupted and has no valid backups"),
+				Err(e) => eprintln!("failed to load level {name}: {e}"),
+			}
+		}
+		levels.insert(config.level_name.clone(), level);
+
+		Self::new_with_levels(config, levels, hook).await
 	}
 
-	/// creates a new server with the given level
-	pub async fn new_with_level(config: ServerConfig, level: Level) -> Result<Self, GeneralError> {
-		let listener = TcpListener::bind("0.0.0.0:25565").await?;
+	/// creates a new server with the given levels, which must include the main level
+	pub async fn new_with_levels(
+		config: ServerConfig,
+		levels: BTreeMap<String, Level>,
+		hook: Option<Box<dyn ServerHook>>,
+	) -> Result<Self, GeneralError> {
+		let listener = TcpListener::bind((config.bind_address.as_str(), config.port))
+			.await
+			.map_err(|e| {
+				GeneralError::Custom(format!(
+					"failed to listen on {}:{}: {e}",
+					config.bind_address, config.port
+				))
+			})?;
 
 		Ok(Self {
-			data: Arc::new(RwLock::new(ServerData {
-				level,
-				players: Default::default(),
-				free_player_ids: Vec::new(),
-				config,
-				config_needs_saving: true,
-				stop: false,
-			})),
+			data: Arc::new(RwLock::new(ServerData::new(config, levels, hook))),
 			listener,
 		})
 	}
@@ -126,10 +563,12 @@ impl Server {
 				println!("connection from {addr}");
 				let data = data.clone();
 				tokio::spawn(async move {
-					network::handle_stream(stream, addr, data).await;
+					network::handle_connection(stream, addr, data).await;
 				});
 			}
 		});
+		tokio::spawn(heartbeat::handle_heartbeats(self.data.clone()));
+		tokio::spawn(handle_ctrl_c(self.data.clone()));
 		println!("server is started!");
 		handle_ticks(self.data.clone()).await?;
 		tokio::time::sleep(std::time::Duration::from_millis(1)).await;
@@ -138,28 +577,51 @@ impl Server {
 		// rn the message isn't guaranteed to actually go out........
 
 		let mut data = self.data.write().await;
-		let player_data = data
-			.players
-			.iter()
-			.map(|p| (p.username.clone(), p.savable_data.clone()))
-			.collect();
-		data.level.update_player_data(player_data);
-		data.level
-			.save(PathBuf::from(LEVELS_PATH).join(&data.config.level_name))
-			.await?;
+		let data = &mut *data;
+		let compression = data.config.compression();
+		for (name, level) in &mut data.levels {
+			let player_data = data
+				.players
+				.iter()
+				.filter(|p| p.current_level == *name)
+				.map(|p| (p.username.clone(), p.savable_data.clone()))
+				.collect();
+			level.update_player_data(player_data);
+			level
+				.save(PathBuf::from(LEVELS_PATH).join(name), compression)
+				.await?;
+		}
 
 		Ok(())
 	}
 }
 
+/// stops the server cleanly on ctrl-c, exiting immediately if it's pressed again
+async fn handle_ctrl_c(data: Arc<RwLock<ServerData>>) {
+	if let Err(e) = tokio::signal::ctrl_c().await {
+		eprintln!("failed to listen for ctrl-c: {e}");
+		return;
+	}
+	println!("stopping server, press ctrl-c again to exit without saving");
+	data.write().await.stop = true;
+
+	if tokio::signal::ctrl_c().await.is_ok() {
+		println!("exiting without saving");
+		std::process::exit(1);
+	}
+}
+
 /// function to tick the server
 async fn handle_ticks(data: Arc<RwLock<ServerData>>) -> Result<(), GeneralError> {
 	let mut current_tick = 0;
 	let mut last_auto_save = std::time::Instant::now();
+	// levels are saved in the background, new saves only start once the previous ones are done
+	let mut pending_saves: Vec<(String, JoinHandle<Result<(), GeneralError>>)> = Vec::new();
 	loop {
 		{
 			let mut data = data.write().await;
 			tick(&mut data, current_tick);
+			data.finish_pending_commands().await;
 
 			if data.config_needs_saving {
 				tokio::fs::write(CONFIG_FILE, serde_json::to_string_pretty(&data.config)?).await?;
@@ -167,31 +629,65 @@ async fn handle_ticks(data: Arc<RwLock<ServerData>>) -> Result<(), GeneralError>
 			}
 
 			if data.stop {
-				let packet = ServerPacket::DisconnectPlayer {
-					disconnect_reason: "Server is stopping!".to_string(),
-				};
 				for player in &mut data.players {
-					player.packets_to_send.push(packet.clone());
+					player.packets_to_send.push(ServerPacket::DisconnectPlayer {
+						disconnect_reason: tr!(&player.locale, "server.stopping"),
+					});
 				}
 				break;
 			}
 
-			if data.level.save_now
-				|| (data.config.auto_save_minutes != 0
-					&& last_auto_save.elapsed().as_secs() / 60 >= data.config.auto_save_minutes)
-			{
-				data.level.save_now = false;
-				data.level
-					.save(PathBuf::from(LEVELS_PATH).join(&data.config.level_name))
-					.await?;
+			let saving = !pending_saves.is_empty();
+			let auto_save = !saving
+				&& data.config.auto_save_minutes != 0
+				&& last_auto_save.elapsed().as_secs() / 60 >= data.config.auto_save_minutes;
+			let main_level_name = data.config.level_name.clone();
+			let main_spawn = data
+				.config
+				.spawn
+				.as_ref()
+				.and_then(|spawn| spawn.points().first().cloned());
+			let compression = data.config.compression();
+			for (name, level) in &mut data.levels {
+				if let Some(path) = level.export_to.take() {
+					let spawn = (*name == main_level_name)
+						.then_some(main_spawn.as_ref())
+						.flatten();
+					if let Err(e) = level.save_cw(&path, spawn).await {
+						eprintln!("failed to export level {name} to {}: {e}", path.display());
+					} else {
+						println!("exported level {name} to {}", path.display());
+					}
+				}
+				if !saving && (auto_save || level.save_now) {
+					level.save_now = false;
+					let handle =
+						level.start_save(PathBuf::from(LEVELS_PATH).join(name), compression)?;
+					pending_saves.push((name.clone(), handle));
+				}
+			}
+			if auto_save {
 				last_auto_save = std::time::Instant::now();
+			}
 
-				let packet = ServerPacket::Message {
-					player_id: -1,
-					message: "Server has saved!".to_string(),
-				};
-				for player in &mut data.players {
-					player.packets_to_send.push(packet.clone());
+			if !pending_saves.is_empty() && pending_saves.iter().all(|(_, h)| h.is_finished()) {
+				let mut saved = true;
+				for (name, handle) in pending_saves.drain(..) {
+					if let Err(e) = handle.await? {
+						eprintln!("failed to save level {name}: {e}");
+						saved = false;
+						if let Some(level) = data.levels.get_mut(&name) {
+							level.save_failed();
+						}
+					}
+				}
+				if saved {
+					for player in &mut data.players {
+						player.packets_to_send.push(ServerPacket::Message {
+							player_id: -1,
+							message: tr!(&player.locale, "server.saved"),
+						});
+					}
 				}
 			}
 		}
@@ -200,14 +696,78 @@ async fn handle_ticks(data: Arc<RwLock<ServerData>>) -> Result<(), GeneralError>
 		tokio::time::sleep(TICK_DURATION).await;
 	}
 
+	for (name, handle) in pending_saves {
+		if let Err(e) = handle.await? {
+			eprintln!("failed to save level {name}: {e}");
+		}
+	}
+
 	Ok(())
 }
 
+/// gets whether the given fluid is kept from flowing into the given coordinates
+fn fluid_blocked_at(level: &Level, fluid: u16, x: usize, y: usize, z: usize) -> bool {
+	is_water(fluid) && level.is_near_sponge(x, y, z)
+}
+
+/// gets how far fluid at the given index will have spread from its source after flowing to the given height, if it's allowed to flow that far
+fn fluid_spread_distance(level: &Level, index: usize, y: usize, ny: usize) -> Option<usize> {
+	let distance = level.fluid_distances.get(&index).copied().unwrap_or(0);
+	let distance = if ny < y { distance } else { distance + 1 };
+	let max = level.rules.fluid_spread_distance;
+	(max == 0 || distance <= max).then_some(distance)
+}
+
 /// function which ticks the server once
 fn tick(data: &mut ServerData, tick: usize) {
-	let level = &mut data.level;
+	for (name, level) in &mut data.levels {
+		let packets = tick_level(level, tick);
+		let env_packets = if tick.is_multiple_of(TIME_COLOR_INTERVAL_TICKS) {
+			level.update_time_colors(&data.config.day_colors, &data.config.night_colors)
+		} else {
+			Vec::new()
+		};
+		for player in data.players.iter_mut().filter(|p| p.current_level == *name) {
+			player.packets_to_send.extend(packets.iter().cloned());
+			if player.extensions.contains(ExtBitmask::EnvColors) {
+				player.packets_to_send.extend(env_packets.iter().cloned());
+			}
+		}
+	}
+
+	if tick.is_multiple_of(PING_INTERVAL_TICKS) {
+		let timeout = data.config.client_timeout_seconds;
+		for player in &mut data.players {
+			// writing the ping to a dead connection fails, which ends it
+			player.packets_to_send.push(ServerPacket::Ping);
+			if player.extensions.contains(ExtBitmask::TwoWayPing) {
+				let id = player.ping.next_id();
+				player.packets_to_send.push(ServerPacket::TwoWayPing {
+					direction: 1,
+					data: id,
+				});
+			}
+			// half-open connections never fail, so players who've gone quiet for too long are dropped
+			if timeout != 0
+				&& player.last_activity.elapsed().as_secs() >= timeout
+				&& player.should_be_kicked.is_none()
+			{
+				player.should_be_kicked = Some(tr!(&player.locale, "disconnect.timed_out"));
+			}
+		}
+	}
+}
 
+/// ticks a single level once, returning the packets to send to players in it
+fn tick_level(level: &mut Level, tick: usize) -> Vec<ServerPacket> {
+	if level.rules.daylight_cycle {
+		level.set_time(level.time + 1);
+	}
 	let mut packets = level.apply_updates();
+	// explicit changes are still applied while physics are paused, anything waiting on physics waits until they're resumed
+	if !level.physics_enabled {
+		return packets;
+	}
 
 	let awaiting_update = std::mem::take(&mut level.awaiting_update);
 	for index in awaiting_update {
@@ -219,41 +779,39 @@ fn tick(data: &mut ServerData, tick: usize) {
 				stationary,
 				ticks_to_spread,
 			} => {
-				if tick % ticks_to_spread == 0 {
+				if tick.is_multiple_of(*ticks_to_spread) {
 					let update = BlockUpdate {
 						index,
 						block: *stationary,
 					};
 					level.updates.push(update);
-					for (nx, ny, nz) in neighbors_minus_up(level, x, y, z) {
+					for (nx, ny, nz) in block
+						.block_type
+						.neighbor_pattern()
+						.neighbors(level, x, y, z)
+					{
 						let id = level.get_block(nx, ny, nz);
 						let block_at = BLOCK_INFO.get(&id).expect("missing block");
 						let index = level.index(nx, ny, nz);
 						let update = match block_at.block_type {
-							BlockType::NonSolid => BlockUpdate {
-								index,
-								block: block_id,
-							},
-							BlockType::FluidFlowing { .. } | BlockType::FluidStationary { .. } => {
-								let turn_to_stone = match block_id {
-									ID_WATER_FLOWING | ID_WATER_STATIONARY => {
-										id == ID_LAVA_FLOWING || id == ID_LAVA_STATIONARY
-									}
-									ID_LAVA_FLOWING | ID_LAVA_STATIONARY => {
-										id == ID_WATER_FLOWING || id == ID_WATER_STATIONARY
-									}
-									_ => panic!(
-										"unimplemented fluid interactions for fluid: {}",
-										block.str_id
-									),
-								};
-								if turn_to_stone {
-									BlockUpdate {
-										index,
-										block: ID_STONE,
-									}
-								} else {
+							BlockType::NonSolid
+								if !fluid_blocked_at(level, block_id, nx, ny, nz) =>
+							{
+								let Some(distance) =
+									fluid_spread_distance(level, level.index(x, y, z), y, ny)
+								else {
 									continue;
+								};
+								level.fluid_distances.insert(index, distance);
+								BlockUpdate {
+									index,
+									block: block_id,
+								}
+							}
+							BlockType::FluidFlowing { .. } | BlockType::FluidStationary { .. } => {
+								match level.rules.fluid_contact_block(block_id, id) {
+									Some(block) => BlockUpdate { index, block },
+									None => continue,
 								}
 							}
 							_ => continue,
@@ -267,14 +825,20 @@ fn tick(data: &mut ServerData, tick: usize) {
 			}
 			BlockType::FluidStationary { moving } => {
 				let mut needs_update = false;
-				for (nx, ny, nz) in neighbors_minus_up(level, x, y, z) {
+				for (nx, ny, nz) in block
+					.block_type
+					.neighbor_pattern()
+					.neighbors(level, x, y, z)
+				{
 					if matches!(
 						BLOCK_INFO
 							.get(&level.get_block(nx, ny, nz))
 							.expect("missing block")
 							.block_type,
 						BlockType::NonSolid
-					) {
+					) && !fluid_blocked_at(level, block_id, nx, ny, nz)
+						&& fluid_spread_distance(level, index, y, ny).is_some()
+					{
 						needs_update = true;
 						break;
 					}
@@ -288,14 +852,270 @@ fn tick(data: &mut ServerData, tick: usize) {
 					level.awaiting_update.insert(index);
 				}
 			}
+			BlockType::Falling => {
+				if !level.rules.block_physics {
+					continue;
+				}
+				for (nx, ny, nz) in block
+					.block_type
+					.neighbor_pattern()
+					.neighbors(level, x, y, z)
+				{
+					let below_type = &BLOCK_INFO
+						.get(&level.get_block(nx, ny, nz))
+						.expect("missing block")
+						.block_type;
+					if matches!(
+						below_type,
+						BlockType::NonSolid
+							| BlockType::FluidFlowing { .. }
+							| BlockType::FluidStationary { .. }
+					) {
+						let below_index = level.index(nx, ny, nz);
+						level.updates.push(BlockUpdate { index, block: 0 });
+						level.updates.push(BlockUpdate {
+							index: below_index,
+							block: block_id,
+						});
+						level.awaiting_update.insert(below_index);
+					}
+				}
+			}
+			BlockType::Explosive => {
+				let Some(fuse) = level.fuses.get_mut(&index) else {
+					continue;
+				};
+				if *fuse > 1 {
+					*fuse -= 1;
+					level.awaiting_update.insert(index);
+					continue;
+				}
+				level.fuses.remove(&index);
+				if !level.rules.tnt_enabled {
+					continue;
+				}
+
+				let radius = level.rules.tnt_radius;
+				for (nx, ny, nz) in cube_around(level, x, y, z, radius) {
+					let distance_squared =
+						x.abs_diff(nx).pow(2) + y.abs_diff(ny).pow(2) + z.abs_diff(nz).pow(2);
+					if distance_squared > radius.pow(2) {
+						continue;
+					}
+					let id = level.get_block(nx, ny, nz);
+					let info = BLOCK_INFO.get(&id).expect("missing block");
+					if id == 0 || id == ID_BEDROCK || info.break_permissions > PlayerType::Normal {
+						continue;
+					}
+					let n_index = level.index(nx, ny, nz);
+					if n_index != index && matches!(info.block_type, BlockType::Explosive) {
+						level.light_fuse(n_index, TNT_CHAIN_FUSE_TICKS);
+						continue;
+					}
+					level.updates.push(BlockUpdate {
+						index: n_index,
+						block: 0,
+					});
+				}
+			}
+			BlockType::Sponge => {
+				for (nx, ny, nz) in cube_around(level, x, y, z, level.rules.sponge_radius) {
+					if is_water(level.get_block(nx, ny, nz)) {
+						level.updates.push(BlockUpdate {
+							index: level.index(nx, ny, nz),
+							block: 0,
+						});
+					}
+				}
+			}
 			_ => {}
 		}
 	}
 
+	let random_ticks = (level.blocks.len() * RANDOM_TICKS_PER_SECTION / 4096).max(1);
+	let mut rng = rand::thread_rng();
+	for _ in 0..random_ticks {
+		random_tick(level, rng.gen_range(0..level.blocks.len()), &mut rng);
+	}
+
 	packets.extend(level.apply_updates());
-	for packet in packets {
-		for player in &mut data.players {
-			player.packets_to_send.push(packet.clone());
+	packets
+}
+
+/// applies the rules for blocks which change over time without needing an update
+fn random_tick(level: &mut Level, index: usize, rng: &mut impl Rng) {
+	let (x, y, z) = level.coordinates(index);
+	let covered = y + 1 < level.y_size && level.get_block(x, y + 1, z) != 0;
+	let block = match level.get_block(x, y, z) {
+		// grass can't live with anything on top of it
+		ID_GRASS if covered => ID_DIRT,
+		ID_DIRT
+			if !covered
+				&& rng.gen_bool(level.rules.grass_spread_chance.clamp(0.0, 1.0) as f64)
+				&& neighbors(level, x, y, z)
+					.into_iter()
+					.any(|(nx, ny, nz)| level.get_block(nx, ny, nz) == ID_GRASS) =>
+		{
+			ID_GRASS
+		}
+		_ => return,
+	};
+	level.updates.push(BlockUpdate { index, block });
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{
+		level::{
+			block::{
+				ID_COBBLESTONE, ID_DIRT, ID_GRASS, ID_LAVA_FLOWING, ID_LAVA_STATIONARY,
+				ID_OBSIDIAN, ID_STONE, ID_WATER_FLOWING, ID_WATER_STATIONARY,
+			},
+			Level,
+		},
+		locale::Translatable,
+		player::PlayerType,
+	};
+
+	use super::{
+		random_tick,
+		testing::{add_player, test_data},
+		tick_level,
+	};
+
+	/// the id of sand, which falls
+	const ID_SAND: u16 = 0x0c;
+
+	#[test]
+	fn player_lookups() {
+		let mut data = test_data();
+		let alice = add_player(&mut data, "Alice", PlayerType::Normal);
+		let alex = add_player(&mut data, "Alex", PlayerType::Normal);
+		let bob = add_player(&mut data, "bob", PlayerType::Normal);
+
+		assert_eq!(data.player_count(), 3);
+		assert_eq!(
+			data.online_usernames().collect::<Vec<_>>(),
+			["Alice", "Alex", "bob"]
+		);
+		assert_eq!(data.player(alex).map(|p| p.username.as_str()), Some("Alex"));
+		data.player_mut(bob).unwrap().permissions = PlayerType::Moderator;
+		assert_eq!(data.player(bob).unwrap().permissions, PlayerType::Moderator);
+		assert!(data.player(42).is_none());
+		assert!(data.player_mut(42).is_none());
+
+		assert_eq!(data.find_player("alice").unwrap().id, alice);
+		assert_eq!(data.find_player("BO").unwrap().id, bob);
+		assert_eq!(data.find_player_mut("alex").unwrap().id, alex);
+		assert_eq!(
+			data.find_player("al").unwrap_err(),
+			Translatable::new("command.player_ambiguous").arg("al")
+		);
+		assert_eq!(
+			data.find_player("carol").unwrap_err(),
+			Translatable::new("command.player_not_connected")
+		);
+		assert!(data.find_player_mut("carol").is_err());
+
+		assert_eq!(data.resolve_username("ALICE"), "Alice");
+		assert_eq!(data.resolve_username("bo"), "bo");
+	}
+
+	#[test]
+	fn physics_only_update_declared_neighbors() {
+		let mut level = Level::new(8, 8, 8);
+		let water = level.index(4, 4, 4);
+		level.queue_block(water, ID_WATER_FLOWING);
+		let sand = level.index(1, 4, 1);
+		level.queue_block(sand, ID_SAND);
+		tick_level(&mut level, 0);
+
+		// fluids spread to every direct neighbor except above
+		assert_eq!(level.get_block(4, 4, 4), ID_WATER_STATIONARY);
+		for (x, y, z) in [(4, 3, 4), (3, 4, 4), (5, 4, 4), (4, 4, 3), (4, 4, 5)] {
+			assert_eq!(
+				level.get_block(x, y, z),
+				ID_WATER_FLOWING,
+				"at {x}, {y}, {z}"
+			);
+		}
+		assert_eq!(level.get_block(4, 5, 4), 0);
+
+		// falling blocks only move into the block below
+		assert_eq!(level.get_block(1, 4, 1), 0);
+		assert_eq!(level.get_block(1, 3, 1), ID_SAND);
+		let changed = level.blocks.iter().filter(|b| **b != 0).count();
+		assert_eq!(changed, 7);
+	}
+
+	#[test]
+	fn covered_grass_turns_to_dirt() {
+		let mut level = Level::new(8, 8, 8);
+		level.set_block(1, 1, 1, ID_GRASS);
+		level.set_block(2, 1, 1, ID_GRASS);
+		level.set_block(1, 2, 1, ID_STONE);
+		let mut rng = rand::thread_rng();
+		for index in [level.index(1, 1, 1), level.index(2, 1, 1)] {
+			random_tick(&mut level, index, &mut rng);
+		}
+		level.apply_updates();
+
+		assert_eq!(level.get_block(1, 1, 1), ID_DIRT);
+		assert_eq!(level.get_block(2, 1, 1), ID_GRASS);
+	}
+
+	#[test]
+	fn water_flowing_into_lava_makes_cobblestone() {
+		let mut level = Level::new(8, 8, 8);
+		level.set_block(5, 0, 4, ID_LAVA_STATIONARY);
+		// walled in so the touched fluid has nowhere to flow itself
+		for (x, z) in [(6, 4), (5, 3), (5, 5)] {
+			level.set_block(x, 0, z, ID_STONE);
+		}
+		let water = level.index(4, 0, 4);
+		level.queue_block(water, ID_WATER_FLOWING);
+		tick_level(&mut level, 0);
+
+		assert_eq!(level.get_block(4, 0, 4), ID_WATER_STATIONARY);
+		assert_eq!(level.get_block(5, 0, 4), ID_COBBLESTONE);
+	}
+
+	#[test]
+	fn lava_flowing_into_water_makes_obsidian() {
+		let mut level = Level::new(8, 8, 8);
+		level.set_block(5, 0, 4, ID_WATER_STATIONARY);
+		// walled in so the touched fluid has nowhere to flow itself
+		for (x, z) in [(6, 4), (5, 3), (5, 5)] {
+			level.set_block(x, 0, z, ID_STONE);
+		}
+		let lava = level.index(4, 0, 4);
+		level.queue_block(lava, ID_LAVA_FLOWING);
+		tick_level(&mut level, 0);
+
+		assert_eq!(level.get_block(4, 0, 4), ID_LAVA_STATIONARY);
+		assert_eq!(level.get_block(5, 0, 4), ID_OBSIDIAN);
+	}
+
+	#[test]
+	fn water_stops_at_the_spread_distance() {
+		let mut level = Level::new(8, 8, 8);
+		level.rules.fluid_spread_distance = 2;
+		let source = level.index(0, 0, 0);
+		level.queue_block(source, ID_WATER_FLOWING);
+		for tick in 0..60 {
+			tick_level(&mut level, tick);
+		}
+
+		for x in 0..8 {
+			for z in 0..8 {
+				let block = level.get_block(x, 0, z);
+				if x + z <= 2 {
+					assert_ne!(block, 0, "at {x}, 0, {z}");
+				} else {
+					assert_eq!(block, 0, "at {x}, 0, {z}");
+				}
+			}
 		}
+		assert!(level.awaiting_update.is_empty());
 	}
 }
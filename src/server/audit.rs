@@ -0,0 +1,149 @@
+use std::{fmt, net::IpAddr, path::PathBuf, time::SystemTime};
+
+use tokio::{
+	fs::{File, OpenOptions},
+	io::AsyncWriteExt,
+	sync::mpsc,
+};
+
+use super::config::AuditLogConfig;
+use crate::util::format_timestamp;
+
+/// events recorded in the audit log
+#[derive(Debug, Clone, Copy)]
+pub enum AuditEvent<'a> {
+	/// a player sent a chat message
+	Chat { username: &'a str, message: &'a str },
+	/// a player ran a command, without the leading slash
+	Command { username: &'a str, command: &'a str },
+	/// a player joined the server
+	Join { username: &'a str, ip: IpAddr },
+	/// a player left the server
+	Leave { username: &'a str },
+	/// a player was kicked from the server
+	Kick { username: &'a str, reason: &'a str },
+	/// a username or IP address was banned
+	Ban {
+		staff: &'a str,
+		target: &'a str,
+		reason: &'a str,
+	},
+}
+
+impl fmt::Display for AuditEvent<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Chat { username, message } => write!(f, "chat <{username}> {message}"),
+			Self::Command { username, command } => write!(f, "command <{username}> /{command}"),
+			Self::Join { username, ip } => write!(f, "join <{username}> from {ip}"),
+			Self::Leave { username } => write!(f, "leave <{username}>"),
+			Self::Kick { username, reason } => write!(f, "kick <{username}> {reason}"),
+			Self::Ban {
+				staff,
+				target,
+				reason,
+			} => write!(f, "ban {target} by <{staff}> {reason}"),
+		}
+	}
+}
+
+/// handle for appending events to the audit log
+#[derive(Debug, Default)]
+pub struct AuditLog {
+	/// sender for lines to be written, none if the audit log is disabled
+	sender: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl AuditLog {
+	/// starts writing the audit log in the background, if it's enabled in the config
+	pub fn start(config: &AuditLogConfig) -> Self {
+		let Some(path) = config.path.clone() else {
+			return Self::default();
+		};
+		let (sender, receiver) = mpsc::unbounded_channel();
+		tokio::spawn(write_lines(
+			path,
+			config.max_bytes,
+			config.max_files,
+			receiver,
+		));
+		Self {
+			sender: Some(sender),
+		}
+	}
+
+	/// records an event in the audit log
+	pub fn log(&self, event: AuditEvent) {
+		if let Some(sender) = &self.sender {
+			let line = format!("[{}] {event}\n", format_timestamp(SystemTime::now()));
+			// the writer only stops if the log file can't be opened, which has already been reported
+			let _ = sender.send(line);
+		}
+	}
+}
+
+/// appends lines to the audit log as they come in, rotating the file once it gets too big
+async fn write_lines(
+	path: PathBuf,
+	max_bytes: u64,
+	max_files: usize,
+	mut receiver: mpsc::UnboundedReceiver<String>,
+) {
+	let mut file = None;
+	let mut len = 0;
+	while let Some(line) = receiver.recv().await {
+		if max_bytes > 0 && len > 0 && len + line.len() as u64 > max_bytes {
+			file = None;
+			if let Err(e) = rotate(&path, max_files).await {
+				eprintln!("failed to rotate audit log: {e}");
+			}
+		}
+		if file.is_none() {
+			match open(&path).await {
+				Ok((f, l)) => (file, len) = (Some(f), l),
+				Err(e) => {
+					eprintln!("failed to open audit log: {e}");
+					return;
+				}
+			}
+		}
+		let f = file.as_mut().expect("audit log should be open");
+		if let Err(e) = f.write_all(line.as_bytes()).await {
+			eprintln!("failed to write to audit log: {e}");
+		}
+		len += line.len() as u64;
+	}
+}
+
+/// opens the audit log for appending, returning it and its current length
+async fn open(path: &PathBuf) -> std::io::Result<(File, u64)> {
+	if let Some(parent) = path.parent() {
+		tokio::fs::create_dir_all(parent).await?;
+	}
+	let file = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(path)
+		.await?;
+	let len = file.metadata().await?.len();
+	Ok((file, len))
+}
+
+/// moves the audit log to `<path>.1`, shifting older logs up and removing any past the limit
+async fn rotate(path: &PathBuf, max_files: usize) -> std::io::Result<()> {
+	let numbered = |n: usize| {
+		let mut name = path.as_os_str().to_owned();
+		name.push(format!(".{n}"));
+		PathBuf::from(name)
+	};
+	if max_files == 0 {
+		return tokio::fs::remove_file(path).await;
+	}
+	for n in (1..max_files).rev() {
+		let from = numbered(n);
+		if tokio::fs::try_exists(&from).await? {
+			tokio::fs::rename(from, numbered(n + 1)).await?;
+		}
+	}
+	tokio::fs::rename(path, numbered(1)).await
+}
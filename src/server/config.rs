@@ -1,9 +1,20 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, net::IpAddr, path::PathBuf, time::SystemTime};
 
+use flate2::Compression;
 use optional_struct::optional_struct;
 use serde::{Deserialize, Serialize};
 
-use crate::{level::generation::LevelGeneration, player::PlayerType};
+use crate::{
+	error::GeneralError,
+	level::{
+		block::definition::BlockDefinition,
+		generation::{GenerationFeatures, LevelGeneration},
+		EnvColorType,
+	},
+	packet::STRING_LENGTH,
+	player::PlayerType,
+	util::split_message,
+};
 
 /// configuration for the server
 #[optional_struct]
@@ -13,27 +24,187 @@ pub struct ServerConfig {
 	pub name: String,
 	/// the server's motd
 	pub motd: String,
+	/// the server's rules, shown with /rules
+	pub rules: Vec<String>,
+	/// whether players are shown the rules when they join
+	pub show_rules_on_join: bool,
+	/// the address the server listens on
+	pub bind_address: String,
+	/// the port the server listens on
+	pub port: u16,
+	/// the maximum number of players which can be connected at once
+	pub max_players: usize,
 	/// the server's protection mode
 	#[serde(rename = "password")]
 	pub protection_mode: ServerProtectionMode,
 	/// map of user permissions
 	pub player_perms: BTreeMap<String, PlayerType>,
+	/// usernames made operators when the config is loaded
+	///
+	/// users also listed in `player_perms` keep the permissions given there, so ranks changed in game aren't undone on restart,
+	/// and users made normal or banned in game are removed from this list
+	pub operators: Vec<String>,
+	/// map of banned usernames to their bans
+	pub banned_users: BTreeMap<String, BanEntry>,
+	/// list of banned addresses
+	pub banned_ips: Vec<IpAddr>,
 	/// the level's name
 	pub level_name: String,
 	/// the level's size
 	pub level_size: ConfigCoordinates,
+	/// the largest number of blocks a generated level may contain
+	pub max_level_volume: usize,
 	/// the level's spawn point
-	pub spawn: Option<ConfigCoordinatesWithOrientation>,
+	pub spawn: Option<ConfigSpawn>,
 	/// the method to generate the server's level with
 	pub generation: LevelGeneration,
+	/// extra features added to newly generated levels
+	pub generation_features: GenerationFeatures,
+	/// the seed the main level is generated from, or none for a random seed
+	pub generation_seed: Option<u64>,
+	/// extra blocks defined by the server, for clients supporting BlockDefinitions
+	pub block_definitions: Vec<BlockDefinition>,
 	/// the server should auto save the world every X minutes, 0 to disable
 	pub auto_save_minutes: u64,
+	/// the locale players are sent messages in until they pick their own
+	pub default_locale: String,
+	/// hack permissions and reach given to players of each rank, for clients which support them
+	pub rank_defaults: BTreeMap<PlayerType, RankDefaults>,
+	/// the number of failed logins allowed from an address before further attempts are refused
+	pub max_login_attempts: u32,
+	/// how long logins are refused for after too many failed attempts, in seconds
+	pub login_cooldown_seconds: u64,
+	/// whether kick and ban messages name the staff member responsible
+	pub show_staff_in_kick_reasons: bool,
+	/// whether players teleported to coordinates inside blocks are moved up to the first space they fit in
+	pub safe_teleport: bool,
+	/// whether moderators are shown as op to their client, letting it break blocks such as bedrock
+	pub moderators_shown_as_op: bool,
+	/// whether a player connecting with the username of someone already online replaces them, instead of being refused
+	pub kick_old_session_on_reconnect: bool,
+	/// how long a player can go without sending any packets before they're disconnected, in seconds, 0 to never disconnect them
+	pub client_timeout_seconds: u64,
+	/// how long a connection has to finish logging in or sending a packet it's started, in seconds
+	pub read_timeout_seconds: u64,
+	/// the largest number of blocks a single selection command may change
+	pub max_selection_volume: usize,
+	/// how hard levels are compressed, from 0 for fastest to 9 for smallest
+	///
+	/// this applies both to levels sent to players without FastMap and to levels saved to disk
+	pub level_compression: u32,
+	/// the number of block edits remembered per player for undoing
+	pub undo_history_length: usize,
+	/// the most blocks non-operators can change each second, 0 for no limit
+	pub max_blocks_per_second: u32,
+	/// the number of block changes rejected in a row for going over the limit before a player is kicked, 0 to never kick
+	pub block_spam_kick_threshold: u32,
+	/// whether color codes are removed from the chat of players below operator
+	pub strip_chat_colors: bool,
+	/// environment colors shown at midday, blended with the night colors as a level's time changes
+	pub day_colors: BTreeMap<EnvColorType, [u8; 3]>,
+	/// environment colors shown at midnight, blended with the day colors as a level's time changes
+	pub night_colors: BTreeMap<EnvColorType, [u8; 3]>,
+	/// alternative command names mapped to the commands they run
+	pub aliases: BTreeMap<String, String>,
+	/// permissions required to run commands, overriding each command's default
+	pub command_permissions: BTreeMap<String, PlayerType>,
+	/// settings for logging chat, commands and moderation events to a file
+	pub audit_log: AuditLogConfig,
+	/// settings for announcing the server to a server list
+	pub heartbeat: HeartbeatConfig,
+}
+
+impl ServerConfig {
+	/// gets the user type sent to clients of the given rank, which decides whether their client treats them as op
+	///
+	/// this only changes what the client allows, the server still checks every action against the player's rank
+	pub fn user_type_for(&self, player_type: PlayerType) -> u8 {
+		let op = match player_type {
+			PlayerType::Normal => false,
+			PlayerType::Moderator => self.moderators_shown_as_op,
+			PlayerType::Operator => true,
+		};
+		if op {
+			0x64
+		} else {
+			0
+		}
+	}
+
+	/// gets the compression used for sending and saving levels
+	pub fn compression(&self) -> Compression {
+		Compression::new(self.level_compression.min(Compression::best().level()))
+	}
+
+	/// gets the hack permissions and reach for the given rank
+	pub fn rank_defaults_for(&self, player_type: &PlayerType) -> RankDefaults {
+		self.rank_defaults
+			.get(player_type)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// gets the server's rules as chat lines, splitting rules which are too long for a single line
+	pub fn rule_lines(&self) -> Vec<String> {
+		self.rules
+			.iter()
+			.flat_map(|rule| split_message(rule))
+			.collect()
+	}
+
+	/// warns about a configured name or motd which is too long to be sent in full
+	pub fn warn_long_strings(&self) {
+		for (field, value) in [("name", &self.name), ("motd", &self.motd)] {
+			let length = value.chars().count();
+			if length > STRING_LENGTH {
+				eprintln!(
+					"warning: the server {field} is {length} characters long and will be cut to {STRING_LENGTH} characters: {value}"
+				);
+			}
+		}
+	}
+
+	/// checks that the configured level size is usable
+	pub fn validate_level_size(&self) -> Result<(), GeneralError> {
+		let ConfigCoordinates { x, y, z } = self.level_size;
+		if x == 0 || y == 0 || z == 0 {
+			return Err(GeneralError::Custom(format!(
+				"level size {x}x{y}x{z} must not have any zero dimensions"
+			)));
+		}
+		if !self.level_size_allowed(x, y, z) {
+			return Err(GeneralError::Custom(format!(
+				"level size {x}x{y}x{z} is over the max level volume of {} blocks or {} blocks on a side",
+				self.max_level_volume,
+				i16::MAX
+			)));
+		}
+		Ok(())
+	}
+
+	/// checks whether a level of the given size may be created, with every side fitting in a packet and the volume under the max
+	pub fn level_size_allowed(&self, x: usize, y: usize, z: usize) -> bool {
+		let side_allowed = |side: usize| (1..=i16::MAX as usize).contains(&side);
+		side_allowed(x)
+			&& side_allowed(y)
+			&& side_allowed(z)
+			&& x.checked_mul(y)
+				.and_then(|v| v.checked_mul(z))
+				.is_some_and(|volume| volume <= self.max_level_volume)
+	}
 }
 
 impl OptionalServerConfig {
 	/// builds the server config filling with default options
 	pub fn build_default(self) -> ServerConfig {
-		self.build(Default::default())
+		let mut config = self.build(Default::default());
+		for username in &config.operators {
+			config
+				.player_perms
+				.entry(username.clone())
+				.or_insert(PlayerType::Operator);
+		}
+		config
 	}
 }
 
@@ -42,17 +213,173 @@ impl Default for ServerConfig {
 		Self {
 			name: "classic server wowie".to_string(),
 			motd: "here's the default server motd".to_string(),
+			rules: Vec::new(),
+			show_rules_on_join: false,
+			bind_address: "0.0.0.0".to_string(),
+			port: 25565,
+			max_players: 32,
 			protection_mode: ServerProtectionMode::None,
 			player_perms: Default::default(),
+			operators: Vec::new(),
+			banned_users: Default::default(),
+			banned_ips: Default::default(),
 			level_name: "default".to_string(),
 			level_size: ConfigCoordinates {
 				x: 256,
 				y: 64,
 				z: 256,
 			},
+			max_level_volume: 512 * 512 * 256,
 			spawn: None,
 			generation: LevelGeneration::Flat(crate::level::generation::FlatPreset::StoneAndGrass),
+			generation_features: Default::default(),
+			generation_seed: None,
+			block_definitions: Vec::new(),
 			auto_save_minutes: 1,
+			default_locale: crate::locale::DEFAULT_LOCALE.to_string(),
+			rank_defaults: [
+				(PlayerType::Normal, RankDefaults::restrictive()),
+				(PlayerType::Moderator, RankDefaults::default()),
+				(PlayerType::Operator, RankDefaults::default()),
+			]
+			.into(),
+			max_login_attempts: 5,
+			login_cooldown_seconds: 60,
+			show_staff_in_kick_reasons: true,
+			safe_teleport: false,
+			moderators_shown_as_op: true,
+			kick_old_session_on_reconnect: false,
+			client_timeout_seconds: 60,
+			read_timeout_seconds: 10,
+			max_selection_volume: 100_000,
+			level_compression: 6,
+			undo_history_length: 256,
+			max_blocks_per_second: 20,
+			block_spam_kick_threshold: 200,
+			strip_chat_colors: false,
+			day_colors: Default::default(),
+			night_colors: Default::default(),
+			aliases: [
+				("teleport", "tp"),
+				("w", "tell"),
+				("msg", "tell"),
+				("pl", "players"),
+			]
+			.into_iter()
+			.map(|(alias, command)| (alias.to_string(), command.to_string()))
+			.collect(),
+			command_permissions: Default::default(),
+			audit_log: Default::default(),
+			heartbeat: Default::default(),
+		}
+	}
+}
+
+/// a ban on a username
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanEntry {
+	/// the reason the user was banned, empty if none was given
+	#[serde(default)]
+	pub reason: String,
+	/// when the ban expires, if it's temporary
+	#[serde(default)]
+	pub expires: Option<SystemTime>,
+}
+
+impl BanEntry {
+	/// gets whether the ban has expired
+	pub fn is_expired(&self) -> bool {
+		self.expires
+			.is_some_and(|expires| expires <= SystemTime::now())
+	}
+}
+
+/// settings for the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditLogConfig {
+	/// the file the audit log is written to, none to disable it
+	pub path: Option<PathBuf>,
+	/// the size the log can grow to before it's rotated, 0 to never rotate
+	pub max_bytes: u64,
+	/// the number of rotated logs to keep
+	pub max_files: usize,
+}
+
+impl Default for AuditLogConfig {
+	fn default() -> Self {
+		Self {
+			path: Some(PathBuf::from("logs/audit.log")),
+			max_bytes: 1024 * 1024,
+			max_files: 5,
+		}
+	}
+}
+
+/// settings for announcing the server to a server list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+	/// whether heartbeats are sent at all
+	pub enabled: bool,
+	/// the url heartbeats are sent to
+	pub url: String,
+	/// whether the server is shown publicly on the server list
+	pub public: bool,
+}
+
+impl Default for HeartbeatConfig {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			url: "https://www.classicube.net/server/heartbeat".to_string(),
+			public: false,
+		}
+	}
+}
+
+/// hack permissions and reach applied to players of a rank
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankDefaults {
+	/// whether flying is allowed
+	pub flying: bool,
+	/// whether noclip is allowed
+	pub no_clip: bool,
+	/// whether speeding is allowed
+	pub speeding: bool,
+	/// whether the player can respawn or set their own spawn point
+	pub spawn_control: bool,
+	/// whether third person view is allowed
+	pub third_person_view: bool,
+	/// the player's jump height, or -1 for the client's default
+	pub jump_height: i16,
+	/// how far away the player can reach blocks, in blocks
+	pub click_distance: f32,
+}
+
+impl RankDefaults {
+	/// defaults which disallow all movement hacks
+	pub fn restrictive() -> Self {
+		Self {
+			flying: false,
+			no_clip: false,
+			speeding: false,
+			..Default::default()
+		}
+	}
+}
+
+impl Default for RankDefaults {
+	fn default() -> Self {
+		Self {
+			flying: true,
+			no_clip: true,
+			speeding: true,
+			spawn_control: true,
+			third_person_view: true,
+			jump_height: -1,
+			click_distance: 5.0,
 		}
 	}
 }
@@ -83,6 +410,31 @@ pub struct ConfigCoordinatesWithOrientation {
 	pub pitch: u8,
 }
 
+/// the main level's spawn, either a single point or a list of points picked from at random
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConfigSpawn {
+	Single(ConfigCoordinatesWithOrientation),
+	Multiple(Vec<ConfigCoordinatesWithOrientation>),
+}
+
+impl ConfigSpawn {
+	/// gets all of the spawn points
+	pub fn points(&self) -> &[ConfigCoordinatesWithOrientation] {
+		match self {
+			Self::Single(point) => std::slice::from_ref(point),
+			Self::Multiple(points) => points,
+		}
+	}
+
+	/// adds another spawn point
+	pub fn push(&mut self, point: ConfigCoordinatesWithOrientation) {
+		let mut points = self.points().to_vec();
+		points.push(point);
+		*self = Self::Multiple(points);
+	}
+}
+
 /// enum for the different kinds of server protection
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -93,4 +445,54 @@ pub enum ServerProtectionMode {
 	Password(String),
 	/// the server requires a password to join and the password is checked against each username
 	PasswordsByUser(BTreeMap<String, String>),
+	/// usernames are verified by the server list using the heartbeat salt, set as `{"verify_names": true}`
+	VerifyNames { verify_names: bool },
+}
+
+impl ServerProtectionMode {
+	/// whether logins are checked against stored passwords
+	pub fn uses_passwords(&self) -> bool {
+		matches!(self, Self::Password(_) | Self::PasswordsByUser(_))
+	}
+
+	/// gets the password the given user logs in with, if the server is protected by passwords
+	pub fn stored_password(&self, username: &str) -> Option<&String> {
+		match self {
+			Self::Password(password) => Some(password),
+			Self::PasswordsByUser(passwords) => passwords.get(username),
+			_ => None,
+		}
+	}
+
+	/// gets the password the given user logs in with, if the server is protected by passwords
+	pub fn stored_password_mut(&mut self, username: &str) -> Option<&mut String> {
+		match self {
+			Self::Password(password) => Some(password),
+			Self::PasswordsByUser(passwords) => passwords.get_mut(username),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::player::PlayerType;
+
+	use super::ServerConfig;
+
+	#[test]
+	fn moderators_can_be_shown_as_op() {
+		let mut config = ServerConfig {
+			moderators_shown_as_op: true,
+			..Default::default()
+		};
+		assert_eq!(config.user_type_for(PlayerType::Normal), 0);
+		assert_eq!(config.user_type_for(PlayerType::Moderator), 0x64);
+		assert_eq!(config.user_type_for(PlayerType::Operator), 0x64);
+
+		config.moderators_shown_as_op = false;
+		assert_eq!(config.user_type_for(PlayerType::Normal), 0);
+		assert_eq!(config.user_type_for(PlayerType::Moderator), 0);
+		assert_eq!(config.user_type_for(PlayerType::Operator), 0x64);
+	}
 }
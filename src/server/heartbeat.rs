@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::sync::RwLock;
+
+use super::ServerData;
+
+/// how often heartbeats are sent
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(45);
+/// the length of generated salts
+const SALT_LENGTH: usize = 16;
+
+/// generates a new random salt
+pub fn generate_salt() -> String {
+	rand::thread_rng()
+		.sample_iter(&Alphanumeric)
+		.take(SALT_LENGTH)
+		.map(char::from)
+		.collect()
+}
+
+/// periodically sends heartbeats to the configured server list
+pub async fn handle_heartbeats(data: Arc<RwLock<ServerData>>) {
+	let client = reqwest::Client::new();
+	let mut last_response = None;
+	loop {
+		let request = {
+			let data = data.read().await;
+			let heartbeat = &data.config.heartbeat;
+			heartbeat.enabled.then(|| {
+				client.post(&heartbeat.url).form(&[
+					("name", data.config.name.clone()),
+					("port", data.config.port.to_string()),
+					("users", data.player_count().to_string()),
+					("max", data.config.max_players.to_string()),
+					("public", heartbeat.public.to_string()),
+					("salt", data.salt.clone()),
+					("software", "classics".to_string()),
+				])
+			})
+		};
+
+		if let Some(request) = request {
+			match request.send().await.and_then(|r| r.error_for_status()) {
+				Ok(response) => match response.text().await {
+					Ok(text) => {
+						let text = text.trim().to_string();
+						if last_response.as_ref() != Some(&text) {
+							println!("heartbeat response: {text}");
+							last_response = Some(text);
+						}
+					}
+					Err(e) => eprintln!("failed to read heartbeat response: {e}"),
+				},
+				Err(e) => eprintln!("failed to send heartbeat: {e}"),
+			}
+		}
+
+		tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+	}
+}
@@ -0,0 +1,47 @@
+use std::fmt::Debug;
+
+use super::ServerData;
+
+/// hooks for running custom logic when things happen on the server
+///
+/// hooks are called while the server's data is locked, so they're given it directly to read or change
+///
+/// methods which return a bool return whether the action should be cancelled
+pub trait ServerHook: Debug + Send + Sync {
+	/// called when a player joins, before anyone else is told about them
+	///
+	/// cancelling disconnects the player
+	fn on_join(&mut self, _data: &mut ServerData, _player_id: i8) -> bool {
+		false
+	}
+
+	/// called when a player leaves, before they're removed from the server
+	fn on_leave(&mut self, _data: &mut ServerData, _player_id: i8) {}
+
+	/// called when a player places or breaks a block, after their permissions are checked
+	///
+	/// cancelling reverts the change for the player
+	fn on_block_change(
+		&mut self,
+		_data: &mut ServerData,
+		_player_id: i8,
+		_position: (usize, usize, usize),
+		_block: u16,
+	) -> bool {
+		false
+	}
+
+	/// called when a player sends a chat message
+	///
+	/// cancelling stops the message from being sent to anyone
+	fn on_chat(&mut self, _data: &mut ServerData, _player_id: i8, _message: &str) -> bool {
+		false
+	}
+
+	/// called when a player runs a command, given without the leading slash
+	///
+	/// cancelling stops the command from running
+	fn on_command(&mut self, _data: &mut ServerData, _player_id: i8, _command: &str) -> bool {
+		false
+	}
+}
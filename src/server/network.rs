@@ -1,12 +1,24 @@
 mod extensions;
+#[cfg(feature = "websocket")]
+mod websocket;
 
-use std::{io::Write, net::SocketAddr, sync::Arc};
+use std::{
+	collections::BTreeMap,
+	io::Write,
+	net::SocketAddr,
+	sync::Arc,
+	time::{Duration, Instant, SystemTime},
+};
 
 use bytes::BytesMut;
-use flate2::{write::GzEncoder, Compression};
-use half::f16;
+use flate2::{
+	write::{DeflateEncoder, GzEncoder},
+	Compression,
+};
+use internment::Intern;
+use strum::IntoEnumIterator;
 use tokio::{
-	io::{AsyncReadExt, AsyncWriteExt},
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
 	net::TcpStream,
 	sync::RwLock,
 };
@@ -14,70 +26,292 @@ use tokio::{
 use crate::{
 	command::Command,
 	error::GeneralError,
-	level::{block::BLOCK_INFO, BlockUpdate, Level},
+	level::{
+		block::{
+			definition::block_definitions, BlockType, BLOCK_INFO, BLOCK_STRING_ID_MAP,
+			MAX_BYTE_BLOCK_ID, MAX_CLASSIC_BLOCK_ID, MAX_CUSTOM_BLOCK_ID, TNT_FUSE_TICKS,
+		},
+		rules::LevelRules,
+		EnvColorType, Level,
+	},
+	locale::tr,
 	packet::{
-		client::ClientPacket, server::ServerPacket, ExtBitmask, PacketWriter, ARRAY_LENGTH,
-		EXTENSION_MAGIC_NUMBER, STRING_LENGTH,
+		client::ClientPacket,
+		client_extended::ExtendedClientPacket,
+		server::{MessageType, ServerPacket},
+		ExtBitmask, PacketWriter, ARRAY_LENGTH, BULK_BLOCK_UPDATE_COUNT, EXTENSION_MAGIC_NUMBER,
+		F16_UNITS,
+	},
+	player::{BlockEdit, Player, PlayerType, DEFAULT_MODEL},
+	selection::{Cuboid, SELECTION_PREVIEW_ID},
+	server::{
+		config::{ServerConfig, ServerProtectionMode},
+		AuditEvent,
+	},
+	util::{
+		format_duration, sanitize_color_codes, split_message, strip_color_codes,
+		truncate_to_string_length,
 	},
-	player::{Player, PlayerType},
-	server::config::ServerProtectionMode,
 };
 
-use super::ServerData;
+use super::{password, ServerData};
 
-async fn next_packet(stream: &mut TcpStream) -> Result<Option<ClientPacket>, GeneralError> {
-	let id = stream.read_u8().await?;
+/// the longest a connection waits for a packet before checking whether it has anything to send
+const PACKET_WAIT_TIMEOUT: Duration = Duration::from_millis(250);
 
-	if let Some(size) = ClientPacket::get_size_from_id(id) {
+/// a connection to a client, which carries classic packets over TCP or another transport
+pub(crate) trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T> ClientStream for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+
+async fn next_packet(
+	stream: &mut impl ClientStream,
+	extended_blocks: bool,
+) -> Result<Option<ClientPacket>, GeneralError> {
+	// waiting is capped so queued packets still get sent and kicks still happen while the client is quiet,
+	// which is safe since no bytes have been read if the wait runs out
+	let Ok(id) = tokio::time::timeout(PACKET_WAIT_TIMEOUT, stream.read_u8()).await else {
+		return Ok(None);
+	};
+	let id = id?;
+
+	if let Some(size) = ClientPacket::get_size_from_id(id, extended_blocks) {
 		let mut buf = BytesMut::zeroed(size);
 		stream.read_exact(&mut buf).await?;
-		Ok(ClientPacket::read(id, &mut buf))
+		Ok(ClientPacket::read(id, &mut buf, extended_blocks))
 	} else {
 		println!("unknown packet id: {id:0x}");
 		Ok(None)
 	}
 }
 
-async fn write_packets<I>(stream: &mut TcpStream, packets: I) -> Result<(), GeneralError>
+async fn write_packets<I>(
+	stream: &mut impl ClientStream,
+	extended_blocks: bool,
+	full_cp437: bool,
+	packets: I,
+) -> Result<(), GeneralError>
 where
 	I: Iterator<Item = ServerPacket>,
 {
 	for packet in packets {
-		let writer = PacketWriter::default().write_u8(packet.get_id());
+		let writer = PacketWriter::default()
+			.extended_blocks(extended_blocks)
+			.full_cp437(full_cp437)
+			.write_u8(packet.get_id());
 		let msg = packet.write(writer).into_raw_packet();
 		stream.write_all(&msg).await?;
 	}
 	Ok(())
 }
 
+/// gets whether a client with the given extensions knows about the given block
+pub(crate) fn client_supports_block(
+	id: u16,
+	extensions: ExtBitmask,
+	custom_blocks_support_level: u8,
+) -> bool {
+	if id <= MAX_CLASSIC_BLOCK_ID {
+		true
+	} else if id <= MAX_CUSTOM_BLOCK_ID {
+		extensions.contains(ExtBitmask::CustomBlocks) && custom_blocks_support_level >= 1
+	} else if id <= MAX_BYTE_BLOCK_ID {
+		extensions.contains(ExtBitmask::BlockDefinitions)
+	} else {
+		extensions.contains(ExtBitmask::BlockDefinitions | ExtBitmask::ExtendedBlocks)
+	}
+}
+
+/// gets the block a client with the given extensions is shown in place of the given block
+pub(crate) fn client_block(
+	id: u16,
+	extensions: ExtBitmask,
+	custom_blocks_support_level: u8,
+) -> u16 {
+	if client_supports_block(id, extensions, custom_blocks_support_level) {
+		id
+	} else {
+		BLOCK_INFO
+			.get(&id)
+			.expect("missing block")
+			.fallback
+			.unwrap_or_default()
+	}
+}
+
 /// gets the packets needed to update a player's inventory
 pub(crate) fn set_player_inventory(
 	perms: PlayerType,
 	extensions: ExtBitmask,
 	custom_blocks_support_level: u8,
+	inventory_order: Option<&[String]>,
 	packets_queue: &mut Vec<ServerPacket>,
 ) {
-	let custom_blocks =
-		extensions.contains(ExtBitmask::CustomBlocks) && custom_blocks_support_level == 1;
 	assert!(
 		custom_blocks_support_level <= 1,
 		"support not implemented for additional custom block levels"
 	);
+	// clients without custom blocks are offered a custom block's fallback in its place
+	let offered = |id: u16| {
+		if client_supports_block(id, extensions, custom_blocks_support_level) {
+			Some(id)
+		} else {
+			BLOCK_INFO
+				.get(&id)
+				.and_then(|info| info.fallback)
+				.filter(|f| *f != 0)
+		}
+	};
+	let allowed = |id: &u16| BLOCK_INFO.get(id).expect("missing block").place_permissions <= perms;
+
+	// every offered block starts hidden, then gets a position from the level's order or its id
+	let mut positions: BTreeMap<u16, u16> = BLOCK_INFO
+		.keys()
+		.filter_map(|id| offered(*id))
+		.map(|id| (id, 0))
+		.collect();
+	if let Some(inventory_order) = inventory_order {
+		let mut next_position = 1;
+		for id in inventory_order
+			.iter()
+			.filter_map(|name| BLOCK_STRING_ID_MAP.get(&Intern::new(name.clone())))
+			.filter_map(|id| offered(*id))
+			.filter(allowed)
+		{
+			if let Some(position @ 0) = positions.get_mut(&id) {
+				*position = next_position;
+				next_position += 1;
+			}
+		}
+	} else {
+		for (id, position) in &mut positions {
+			if allowed(id) {
+				*position = *id;
+			}
+		}
+	}
+
+	for (block, order) in positions {
+		packets_queue.push(ServerPacket::SetInventoryOrder { block, order });
+	}
+}
+
+/// gets the identification packet for a level, falling back to the configured name and motd
+pub(crate) fn server_identification_packet(
+	config: &ServerConfig,
+	level: &Level,
+	player_type: PlayerType,
+) -> ServerPacket {
+	ServerPacket::ServerIdentification {
+		protocol_version: 0x07,
+		server_name: level.name.clone().unwrap_or_else(|| config.name.clone()),
+		server_motd: level.motd.clone().unwrap_or_else(|| config.motd.clone()),
+		user_type: config.user_type_for(player_type),
+	}
+}
+
+/// gets the packets needed to show a level's weather and colors to a player
+pub(crate) fn level_env_packets(level: &Level, extensions: ExtBitmask) -> Vec<ServerPacket> {
+	let mut packets = Vec::new();
+	if extensions.contains(ExtBitmask::EnvWeatherType) {
+		packets.push(ServerPacket::EnvWeatherType {
+			weather_type: level.weather,
+		});
+	}
+	if extensions.contains(ExtBitmask::EnvColors) {
+		// every color is sent so colors from a previous level don't carry over
+		packets.extend(
+			EnvColorType::iter().map(|variable| ServerPacket::EnvSetColor {
+				variable,
+				color: level.env_color(variable),
+			}),
+		);
+	}
+	packets
+}
+
+/// gets the packets needed to tell a player which blocks they may place and break
+pub(crate) fn set_player_block_permissions(
+	perms: PlayerType,
+	extensions: ExtBitmask,
+	custom_blocks_support_level: u8,
+	packets_queue: &mut Vec<ServerPacket>,
+) {
+	if !extensions.contains(ExtBitmask::BlockPermissions) {
+		return;
+	}
 	for (id, info) in &*BLOCK_INFO {
-		if !custom_blocks && *id > 49 {
-			break;
+		if *id == 0 || !client_supports_block(*id, extensions, custom_blocks_support_level) {
+			continue;
 		}
-		let block = if info.place_permissions <= perms {
-			*id
-		} else {
-			0
-		};
-		packets_queue.push(ServerPacket::SetInventoryOrder { order: *id, block });
+		packets_queue.push(ServerPacket::SetBlockPermission {
+			block: *id,
+			allow_placement: info.place_permissions <= perms,
+			allow_deletion: info.break_permissions <= perms,
+		});
 	}
 }
 
-pub(super) async fn handle_stream(
-	mut stream: TcpStream,
+/// gets the packets needed to apply a player's rank defaults for hacks and reach, as limited by the level's rules
+pub(crate) fn set_player_rank_defaults(
+	config: &ServerConfig,
+	rules: &LevelRules,
+	perms: PlayerType,
+	extensions: ExtBitmask,
+	packets_queue: &mut Vec<ServerPacket>,
+) {
+	let defaults = config.rank_defaults_for(&perms);
+	if extensions.contains(ExtBitmask::HackControl) {
+		packets_queue.push(ServerPacket::HackControl {
+			flying: defaults.flying && rules.allow_flying,
+			no_clip: defaults.no_clip && rules.allow_noclip,
+			speeding: defaults.speeding && rules.allow_speeding,
+			spawn_control: defaults.spawn_control && rules.allow_respawn,
+			third_person_view: defaults.third_person_view && rules.allow_third_person,
+			jump_height: if rules.jump_height == -1 {
+				defaults.jump_height
+			} else {
+				rules.jump_height
+			},
+		});
+	}
+	if extensions.contains(ExtBitmask::ClickDistance) {
+		packets_queue.push(ServerPacket::SetClickDistance {
+			distance: (defaults.click_distance.min(rules.click_distance) * F16_UNITS) as i16,
+		});
+	}
+}
+
+/// handles a new connection, which may be a websocket connection if they're enabled
+pub(super) async fn handle_connection(
+	stream: TcpStream,
+	addr: SocketAddr,
+	data: Arc<RwLock<ServerData>>,
+) {
+	#[cfg(feature = "websocket")]
+	{
+		let read_timeout = Duration::from_secs(data.read().await.config.read_timeout_seconds);
+		// connections which stay quiet are left for the regular login timeout to deal with
+		let is_websocket = tokio::time::timeout(read_timeout, websocket::is_websocket(&stream))
+			.await
+			.unwrap_or(false);
+		if is_websocket {
+			match tokio::time::timeout(read_timeout, websocket::accept(stream)).await {
+				Ok(Ok(stream)) => handle_stream(stream, addr, data).await,
+				Ok(Err(e)) => eprintln!("failed websocket handshake with <{addr}>: {e}"),
+				Err(_) => eprintln!("websocket handshake with <{addr}> timed out"),
+			}
+			return;
+		}
+	}
+	handle_stream(stream, addr, data).await;
+}
+
+/// handles a client's connection until they disconnect
+///
+/// the stream can be anything carrying classic packets, such as an in-memory pipe from `tokio::io::duplex`
+pub(crate) async fn handle_stream(
+	mut stream: impl ClientStream,
 	addr: SocketAddr,
 	data: Arc<RwLock<ServerData>>,
 ) {
@@ -108,34 +342,72 @@ pub(super) async fn handle_stream(
 		eprintln!("Failed to properly shut down stream for <{addr}>: {e}");
 	}
 
-	let mut data = data.write().await;
-	if let Some(index) = data.players.iter().position(|p| p.id == own_id) {
-		let player = data.players.remove(index);
-		data.free_player_ids.push(player.id);
+	// kicked players have already been removed by now
+	remove_player(&mut *data.write().await, own_id);
+}
 
-		let despawn_packet = ServerPacket::DespawnPlayer { player_id: own_id };
-		let message_packet = ServerPacket::Message {
-			player_id: own_id,
-			message: format!("&e{} has left the server.", player.username),
-		};
-		for player in &mut data.players {
-			player.packets_to_send.push(despawn_packet.clone());
-			player.packets_to_send.push(message_packet.clone());
+/// removes a player from the server, telling everyone else they've left
+fn remove_player(data: &mut ServerData, own_id: i8) {
+	if data.player(own_id).is_none() {
+		return;
+	}
+	data.run_hook(|hook, data| {
+		hook.on_leave(data, own_id);
+		false
+	});
+	let Some(index) = data.players.iter().position(|p| p.id == own_id) else {
+		return;
+	};
+	let player = data.players.remove(index);
+	data.free_player_ids.push(player.id);
+	if let Some(reason) = &player.should_be_kicked {
+		data.audit.log(AuditEvent::Kick {
+			username: &player.username,
+			reason,
+		});
+	}
+	data.audit.log(AuditEvent::Leave {
+		username: &player.username,
+	});
+
+	let despawn_packet = ServerPacket::DespawnPlayer { player_id: own_id };
+	for other in &mut data.players {
+		if other.current_level == player.current_level {
+			other.packets_to_send.push(despawn_packet.clone());
 		}
-		data.level
+		other.packets_to_send.push(ServerPacket::Message {
+			player_id: own_id,
+			message: tr!(&other.locale, "player.left", player.username),
+		});
+	}
+	if let Some(level) = data.levels.get_mut(&player.current_level) {
+		level
 			.player_data
 			.insert(player.username, player.savable_data);
 	}
+	data.send_online_status();
 }
 
 async fn handle_stream_inner(
-	stream: &mut TcpStream,
+	stream: &mut impl ClientStream,
 	addr: SocketAddr,
 	data: Arc<RwLock<ServerData>>,
 	own_id: &mut i8,
 ) -> Result<(), GeneralError> {
 	let mut reply_queue: Vec<ServerPacket> = Vec::new();
 	let mut incoming_message: Vec<String> = Vec::new();
+	// whether block ids are sent as two bytes, which is only known after extensions are negotiated
+	let mut extended_blocks = false;
+	let mut full_cp437 = false;
+	let connected_at = Instant::now();
+	let (read_timeout, timed_out) = {
+		let data = data.read().await;
+		(
+			Duration::from_secs(data.config.read_timeout_seconds),
+			tr!(&data.config.default_locale, "disconnect.timed_out"),
+		)
+	};
+	let timed_out = || GeneralError::Custom(timed_out.clone());
 
 	macro_rules! msg {
 		($message:expr) => {
@@ -147,13 +419,51 @@ async fn handle_stream_inner(
 	}
 
 	loop {
-		if let Some(player) = data.read().await.players.iter().find(|p| p.id == *own_id) {
-			if let Some(msg) = &player.should_be_kicked {
-				return Err(GeneralError::Custom(msg.clone()));
+		let kick_message = {
+			let data = data.read().await;
+			match data.player(*own_id) {
+				_ if *own_id == -1 => None,
+				Some(player) if player.addr == addr => player.should_be_kicked.clone(),
+				// a new connection with the same username has taken over this player
+				_ => {
+					*own_id = -1;
+					return Err(GeneralError::Custom(tr!(
+						&data.config.default_locale,
+						"disconnect.replaced"
+					)));
+				}
 			}
+		};
+		if let Some(msg) = kick_message {
+			// others shouldn't have to wait for the connection to close to see the player leave
+			remove_player(&mut *data.write().await, *own_id);
+			// the id may be given to someone else now, so it can't be removed again once the connection closes
+			*own_id = -1;
+			return Err(GeneralError::Custom(msg));
 		}
+		// connections which never finish logging in would otherwise be kept around forever
+		if *own_id == -1 && connected_at.elapsed() >= read_timeout {
+			return Err(timed_out());
+		}
+
+		let packet = tokio::time::timeout(read_timeout, next_packet(stream, extended_blocks))
+			.await
+			.map_err(|_| timed_out())??;
+		if let Some(packet) = packet {
+			// a new connection with the same username may have taken over this player while waiting for the packet
+			if *own_id != -1 {
+				let mut data = data.write().await;
+				let default_locale = data.config.default_locale.clone();
+				let Some(player) = data.player_mut(*own_id).filter(|p| p.addr == addr) else {
+					*own_id = -1;
+					return Err(GeneralError::Custom(tr!(
+						&default_locale,
+						"disconnect.replaced"
+					)));
+				};
+				player.last_activity = Instant::now();
+			}
 
-		if let Some(packet) = next_packet(stream).await? {
 			match packet {
 				ClientPacket::PlayerIdentification {
 					protocol_version,
@@ -161,46 +471,153 @@ async fn handle_stream_inner(
 					verification_key,
 					magic_number,
 				} => {
+					let data_lock = data.clone();
+					let mut data = data.write().await;
+					let default_locale = data.config.default_locale.clone();
+
 					if protocol_version != 0x07 {
-						return Err(GeneralError::Custom("Unknown protocol version! Please connect with a classic 0.30-compatible client.".to_string()));
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.unknown_protocol"
+						)));
 					}
 
-					let mut data = data.write().await;
+					if data.config.banned_ips.contains(&addr.ip()) {
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.ip_banned"
+						)));
+					}
 
-					match &data.config.protection_mode {
-						ServerProtectionMode::None => {}
-						ServerProtectionMode::Password(password) => {
-							if verification_key != *password {
-								return Err(GeneralError::Custom(
-									"Incorrect password!".to_string(),
-								));
-							}
+					// usernames are shown in chat and above players, so they can't carry color codes
+					if strip_color_codes(&username) != username {
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.invalid_username"
+						)));
+					}
+
+					if data.login_throttled(addr.ip()) {
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.too_many_attempts"
+						)));
+					}
+
+					if let Some(ban) = data.config.banned_users.get(&username).cloned() {
+						if ban.is_expired() {
+							data.config.banned_users.remove(&username);
+							data.config_needs_saving = true;
+						} else {
+							let reason = if ban.reason.is_empty() {
+								tr!(&default_locale, "command.no_reason")
+							} else {
+								ban.reason
+							};
+							let message = if let Some(expires) = ban.expires {
+								let remaining = expires
+									.duration_since(SystemTime::now())
+									.unwrap_or_default();
+								tr!(
+									&default_locale,
+									"disconnect.banned_temporary",
+									format_duration(remaining),
+									reason
+								)
+							} else {
+								tr!(&default_locale, "command.ban.reason", reason)
+							};
+							return Err(GeneralError::Custom(truncate_to_string_length(&message)));
 						}
-						ServerProtectionMode::PasswordsByUser(passwords) => {
-							if !passwords
-								.get(&username)
-								.map(|password| verification_key == *password)
-								.unwrap_or_default()
-							{
-								return Err(GeneralError::Custom(
-									"Incorrect password!".to_string(),
-								));
+					}
+
+					let password_correct = if data.config.protection_mode.uses_passwords() {
+						match data
+							.config
+							.protection_mode
+							.stored_password(&username)
+							.cloned()
+						{
+							Some(stored_password) => {
+								// hashed passwords take a while to check, so the server isn't kept locked meanwhile
+								drop(data);
+								let (correct, upgraded) = password::verify_password_blocking(
+									stored_password,
+									verification_key.clone(),
+								)
+								.await?;
+								data = data_lock.write().await;
+								// legacy plaintext passwords are upgraded now that we know the password is correct,
+								// unless the password was changed while it was being checked
+								if let Some(upgraded) = upgraded {
+									if let Some(stored_password) = data
+										.config
+										.protection_mode
+										.stored_password_mut(&username)
+										.filter(|p| **p == verification_key)
+									{
+										*stored_password = upgraded;
+										data.config_needs_saving = true;
+									}
+								}
+								correct
 							}
+							None => false,
 						}
+					} else if let ServerProtectionMode::VerifyNames { verify_names } =
+						data.config.protection_mode
+					{
+						!verify_names
+							|| verification_key.eq_ignore_ascii_case(&format!(
+								"{:x}",
+								md5::compute(format!("{}{username}", data.salt))
+							))
+					} else {
+						true
+					};
+					if !password_correct {
+						data.record_failed_login(addr.ip());
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.incorrect_password"
+						)));
 					}
+					data.failed_logins.remove(&addr.ip());
 
-					for player in &data.players {
-						if player.username == username {
-							return Err(GeneralError::Custom(
-								"Player with username already connected!".to_string(),
-							));
+					if data.online_usernames().any(|u| u == username) {
+						if !data.config.kick_old_session_on_reconnect {
+							return Err(GeneralError::Custom(tr!(
+								&default_locale,
+								"disconnect.already_connected"
+							)));
 						}
+						// the old session is removed right away so its data is saved before this one loads it
+						let old_player = data
+							.players
+							.iter_mut()
+							.find(|p| p.username == username)
+							.expect("missing player");
+						old_player.should_be_kicked =
+							Some(tr!(&old_player.locale, "disconnect.replaced"));
+						let old_id = old_player.id;
+						remove_player(&mut data, old_id);
 					}
 
-					*own_id = data
-						.free_player_ids
-						.pop()
-						.unwrap_or_else(|| data.players.len() as i8);
+					// player ids can't go past 127, so no more players can fit once they run out
+					let next_id = if data.player_count() < data.config.max_players {
+						data.free_player_ids
+							.pop()
+							.or_else(|| i8::try_from(data.player_count()).ok())
+					} else {
+						None
+					};
+					let Some(next_id) = next_id else {
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.server_full"
+						)));
+					};
+					*own_id = next_id;
 
 					let player_type = data
 						.config
@@ -209,63 +626,95 @@ async fn handle_stream_inner(
 						.copied()
 						.unwrap_or_default();
 
-					let savable_data = data.level.player_data.get(&username).cloned();
-					let needs_spawn_coords = savable_data.is_none();
+					let current_level = data.config.level_name.clone();
+					let level = data
+						.levels
+						.get(&current_level)
+						.expect("main level should always be loaded");
+					let savable_data = level.player_data.get(&username).cloned();
+					// returning players spawn where they left, unless the level has since shrunk out from under them
+					let needs_spawn_coords = savable_data.as_ref().is_none_or(|saved| {
+						let (x, y, z) = (saved.x.to_f32(), saved.y.to_f32(), saved.z.to_f32());
+						x < 0.0
+							|| y < 0.0 || z < 0.0 || x >= level.x_size as f32
+							|| z >= level.z_size as f32
+					});
 					let savable_data = savable_data.unwrap_or_default();
 
 					let mut player = Player {
-						_addr: addr,
+						addr,
 						id: *own_id, // TODO: actually assign user ids
 						username,
 						savable_data,
 						permissions: player_type,
+						locale: default_locale.clone(),
+						current_level: current_level.clone(),
 						extensions: ExtBitmask::none(),
 						custom_blocks_support_level: 0,
 						packets_to_send: Vec::new(),
 						should_be_kicked: None,
+						pending_selection: None,
+						edit_history: Default::default(),
+						ping: Default::default(),
+						block_rate: Default::default(),
+						previous_position: None,
+						model: DEFAULT_MODEL.to_string(),
+						last_activity: Instant::now(),
 					};
 
 					if magic_number == EXTENSION_MAGIC_NUMBER {
-						(player.extensions, player.custom_blocks_support_level) =
-							extensions::get_supported_extensions(stream).await?;
+						let negotiated = tokio::time::timeout(
+							read_timeout,
+							extensions::get_supported_extensions(stream),
+						)
+						.await
+						.unwrap_or_else(|_| Err(timed_out()));
+						match negotiated {
+							Ok(negotiated) => {
+								(player.extensions, player.custom_blocks_support_level) = negotiated
+							}
+							Err(e) => {
+								// the player was never added, so their id has to be given back here
+								data.free_player_ids.push(*own_id);
+								*own_id = -1;
+								return Err(e);
+							}
+						}
 					}
 					let extensions = player.extensions;
 					let custom_blocks_support_level = player.custom_blocks_support_level;
+					extended_blocks = extensions.contains(ExtBitmask::ExtendedBlocks);
+					full_cp437 = extensions.contains(ExtBitmask::FullCP437);
 
-					reply_queue.push(ServerPacket::ServerIdentification {
-						protocol_version: 0x07,
-						server_name: data.config.name.clone(),
-						server_motd: data.config.motd.clone(),
-						user_type: player_type,
-					});
-
-					println!("generating level packets");
-					reply_queue.extend(
-						build_level_packets(&data.level, extensions, custom_blocks_support_level)?
-							.into_iter(),
-					);
+					let level = data
+						.levels
+						.get(&current_level)
+						.expect("main level should always be loaded");
+					reply_queue.push(server_identification_packet(
+						&data.config,
+						level,
+						player_type,
+					));
 
-					if extensions.contains(ExtBitmask::EnvWeatherType) {
-						reply_queue.push(ServerPacket::EnvWeatherType {
-							weather_type: data.level.weather,
-						});
+					if extensions.contains(ExtBitmask::BlockDefinitions) {
+						reply_queue.extend(block_definitions().iter().map(|definition| {
+							ServerPacket::DefineBlock {
+								definition: definition.clone(),
+							}
+						}));
 					}
 
+					// the level packets are built once the server is no longer locked, but must come before everything after them
+					let level_packets_index = reply_queue.len();
+					let level_snapshot = level.clone();
+
+					reply_queue.extend(level_env_packets(level, extensions));
+
 					let username = player.username.clone();
 
 					if needs_spawn_coords {
 						let (spawn_x, spawn_y, spawn_z, spawn_yaw, spawn_pitch) =
-							if let Some(spawn) = &data.config.spawn {
-								(spawn.x, spawn.y, spawn.z, spawn.yaw, spawn.pitch)
-							} else {
-								(16.5, (data.level.y_size / 2 + 2) as f32, 16.5, 0, 0)
-							};
-
-						let (spawn_x, spawn_y, spawn_z) = (
-							f16::from_f32(spawn_x),
-							f16::from_f32(spawn_y),
-							f16::from_f32(spawn_z),
-						);
+							data.level_spawn(&current_level);
 
 						player.x = spawn_x;
 						player.y = spawn_y;
@@ -285,29 +734,48 @@ async fn handle_stream_inner(
 					};
 
 					data.players.push(player);
+					let id = *own_id;
+					if data.run_hook(|hook, data| hook.on_join(data, id)) {
+						data.players.retain(|p| p.id != id);
+						data.free_player_ids.push(id);
+						*own_id = -1;
+						return Err(GeneralError::Custom(tr!(
+							&default_locale,
+							"disconnect.join_cancelled"
+						)));
+					}
+					data.audit.log(AuditEvent::Join {
+						username: &username,
+						ip: addr.ip(),
+					});
 
-					let message_packet = ServerPacket::Message {
-						player_id: *own_id,
-						message: format!("&e{} has joined the server.", username),
-					};
 					for player in &mut data.players {
-						player.packets_to_send.push(spawn_packet.clone());
+						let same_level = player.current_level == current_level;
+						if same_level {
+							player.packets_to_send.push(spawn_packet.clone());
+						}
 						if player.id != *own_id {
-							reply_queue.push(ServerPacket::SpawnPlayer {
-								player_id: player.id,
-								player_name: player.username.clone(),
-								x: player.x,
-								y: player.y,
-								z: player.z,
-								yaw: player.yaw,
-								pitch: player.pitch,
+							if same_level {
+								reply_queue.push(player_spawn_packet(player));
+								reply_queue.extend(player_model_packet(player, extensions));
+							}
+							player.packets_to_send.push(ServerPacket::Message {
+								player_id: *own_id,
+								message: tr!(&player.locale, "player.joined", username),
 							});
-							player.packets_to_send.push(message_packet.clone());
 						}
 					}
-					msg!("&dWelcome to the server! Enjoyyyyyy".to_string());
+					msg!(tr!(&default_locale, "server.welcome"));
+					if data.config.show_rules_on_join && !data.config.rules.is_empty() {
+						msg!(tr!(&default_locale, "command.rules.header"));
+						for line in data.config.rule_lines() {
+							msg!(line);
+						}
+					}
+					data.send_level_status(*own_id);
+					data.send_online_status();
 					reply_queue.push(ServerPacket::UpdateUserType {
-						user_type: player_type,
+						user_type: data.config.user_type_for(player_type),
 					});
 
 					if extensions.contains(ExtBitmask::InventoryOrder) {
@@ -315,9 +783,43 @@ async fn handle_stream_inner(
 							player_type,
 							extensions,
 							custom_blocks_support_level,
+							data.levels
+								.get(&current_level)
+								.expect("main level should always be loaded")
+								.inventory_order
+								.as_deref(),
 							&mut reply_queue,
 						);
 					}
+					set_player_block_permissions(
+						player_type,
+						extensions,
+						custom_blocks_support_level,
+						&mut reply_queue,
+					);
+					set_player_rank_defaults(
+						&data.config,
+						&data
+							.levels
+							.get(&current_level)
+							.expect("main level should always be loaded")
+							.rules,
+						player_type,
+						extensions,
+						&mut reply_queue,
+					);
+					let compression = data.config.compression();
+					drop(data);
+
+					println!("generating level packets");
+					let level_packets = build_level_packets_blocking(
+						level_snapshot,
+						extensions,
+						custom_blocks_support_level,
+						compression,
+					)
+					.await?;
+					reply_queue.splice(level_packets_index..level_packets_index, level_packets);
 				}
 				ClientPacket::SetBlock {
 					x,
@@ -325,64 +827,201 @@ async fn handle_stream_inner(
 					z,
 					mode,
 					block_type,
-				} => {
+				} => 'set_block: {
+					// changes which are refused break out rather than continuing the loop, so their corrections are sent
+					// straight away instead of waiting for a later packet which may be refused too
 					let block_type = if mode == 0x00 { 0 } else { block_type };
 					let mut data = data.write().await;
+					let data = &mut *data;
+					// blocks can't be changed before logging in, when there's no level to change them in
+					let Some((player_type, locale, level_name)) = data
+						.player(*own_id)
+						.map(|p| (p.permissions, p.locale.clone(), p.current_level.clone()))
+					else {
+						return Err(GeneralError::Custom(tr!(
+							&data.config.default_locale,
+							"disconnect.not_logged_in"
+						)));
+					};
+					let level = data
+						.levels
+						.get(&level_name)
+						.expect("player should always be in a loaded level");
 
 					// kick players if they attempt to place a block out of bounds
-					if x.clamp(0, data.level.x_size as i16 - 1) != x
-						|| y.clamp(0, data.level.y_size as i16 - 1) != y
-						|| z.clamp(0, data.level.z_size as i16 - 1) != z
+					if x.clamp(0, level.x_size as i16 - 1) != x
+						|| y.clamp(0, level.y_size as i16 - 1) != y
+						|| z.clamp(0, level.z_size as i16 - 1) != z
 					{
-						return Err(GeneralError::Custom(
-							"Attempt to place block out of bounds".to_string(),
-						));
+						return Err(GeneralError::Custom(tr!(
+							&locale,
+							"disconnect.out_of_bounds"
+						)));
 					}
 
+					let max_blocks_per_second = data.config.max_blocks_per_second;
+					let block_spam_kick_threshold = data.config.block_spam_kick_threshold;
+
+					// clicks while making a selection mark corners instead of changing blocks
+					let player = data.player_mut(*own_id).expect("missing player");
+					if let Some(mut selection) = player.pending_selection.take() {
+						let corner = (x as usize, y as usize, z as usize);
+						let previews = player.extensions.contains(ExtBitmask::SelectionCuboid);
+						if let Some(first_corner) = selection.first_corner {
+							if previews {
+								reply_queue.push(ServerPacket::RemoveSelection {
+									selection_id: SELECTION_PREVIEW_ID,
+								});
+							}
+							for message in selection.action.apply(
+								data,
+								*own_id,
+								Cuboid::from_corners(first_corner, corner),
+							) {
+								msg!(message);
+							}
+						} else {
+							selection.first_corner = Some(corner);
+							player.pending_selection = Some(selection);
+							if previews {
+								reply_queue
+									.push(Cuboid::from_corners(corner, corner).preview_packet());
+							}
+							msg!(tr!(&locale, "selection.first_corner"));
+						}
+						reply_queue.push(ServerPacket::SetBlock {
+							x,
+							y,
+							z,
+							block_type: data
+								.player_level(*own_id)
+								.expect("player should always be in a loaded level")
+								.get_block(corner.0, corner.1, corner.2),
+						});
+						break 'set_block;
+					}
+
+					// players changing blocks too quickly have their changes reverted, operators are trusted not to
+					let max_blocks_per_second = if player_type < PlayerType::Operator {
+						max_blocks_per_second
+					} else {
+						0
+					};
+					if !player.block_rate.allow(max_blocks_per_second) {
+						let rejected_in_a_row = player.block_rate.rejected_in_a_row;
+						if block_spam_kick_threshold != 0
+							&& rejected_in_a_row >= block_spam_kick_threshold
+						{
+							return Err(GeneralError::Custom(tr!(
+								&locale,
+								"disconnect.block_spam"
+							)));
+						}
+						if rejected_in_a_row == 1 {
+							msg!(tr!(&locale, "block.too_fast"));
+						}
+						reply_queue.push(ServerPacket::SetBlock {
+							x,
+							y,
+							z,
+							block_type: data
+								.player_level(*own_id)
+								.expect("player should always be in a loaded level")
+								.get_block(x as usize, y as usize, z as usize),
+						});
+						break 'set_block;
+					}
+					let level = data
+						.levels
+						.get_mut(&level_name)
+						.expect("player should always be in a loaded level");
+
 					let new_block_info = BLOCK_INFO.get(&block_type);
 					if new_block_info.is_none() {
-						msg!(format!("&cUnknown block ID: 0x{:0x}", block_type));
-						continue;
+						msg!(tr!(&locale, "block.unknown_id", format!("{block_type:0x}")));
+						break 'set_block;
 					}
 					let new_block_info = new_block_info.expect("will never fail");
 					let mut cancel = false;
-					let block = data.level.get_block(x as usize, y as usize, z as usize);
+					let block = level.get_block(x as usize, y as usize, z as usize);
 					let block_info = BLOCK_INFO
 						.get(&block)
 						.expect("missing block information for block!");
+					let max_build_height = level.rules.max_build_height;
 
 					// check if player has ability to place/break these blocks
-					let player_type = data
-						.players
-						.iter()
-						.find_map(|p| (p.id == *own_id).then_some(p.permissions))
-						.unwrap_or_default();
-					if player_type < new_block_info.place_permissions {
+					if player_type < level.edit_permissions(x as usize, y as usize, z as usize)
+						|| player_type < PlayerType::Moderator
+							&& data.is_spawn_protected(&level_name, x as usize, z as usize)
+					{
 						cancel = true;
-						msg!("&cNot allow to place this block.".to_string());
+						msg!(tr!(&locale, "block.protected"));
+					} else if block_type != 0
+						&& player_type < PlayerType::Operator
+						&& max_build_height >= 0
+						&& y > max_build_height
+					{
+						cancel = true;
+						msg!(tr!(&locale, "block.too_high", max_build_height));
+					} else if player_type < new_block_info.place_permissions {
+						cancel = true;
+						msg!(tr!(&locale, "block.cannot_place"));
 					} else if player_type < block_info.break_permissions {
 						cancel = true;
-						msg!("&cNot allowed to break this block.".to_string());
+						msg!(tr!(&locale, "block.cannot_break"));
 					}
 
-					if cancel {
+					let id = *own_id;
+					let position = (x as usize, y as usize, z as usize);
+					if cancel
+						|| data.run_hook(|hook, data| {
+							hook.on_block_change(data, id, position, block_type)
+						}) {
 						reply_queue.push(ServerPacket::SetBlock {
 							x,
 							y,
 							z,
 							block_type: block,
 						});
-						continue;
+						break 'set_block;
 					}
-					let (x, y, z) = (x as usize, y as usize, z as usize);
-					let index = data.level.index(x, y, z);
-					data.level.updates.push(BlockUpdate {
-						index,
-						block: block_type,
-					});
-					if new_block_info.block_type.needs_update_on_place() {
-						data.level.awaiting_update.insert(index);
+					let level = data
+						.levels
+						.get_mut(&level_name)
+						.expect("player should always be in a loaded level");
+					let (x, y, z) = position;
+					let index = level.index(x, y, z);
+
+					// breaking tnt lights it instead of removing it
+					if block_type == 0
+						&& matches!(block_info.block_type, BlockType::Explosive)
+						&& level.rules.tnt_enabled
+					{
+						level.light_fuse(index, TNT_FUSE_TICKS);
+						reply_queue.push(ServerPacket::SetBlock {
+							x: x as i16,
+							y: y as i16,
+							z: z as i16,
+							block_type: block,
+						});
+						break 'set_block;
 					}
+
+					level.queue_block(index, block_type);
+
+					let limit = data.config.undo_history_length;
+					data.player_mut(*own_id)
+						.expect("missing player")
+						.record_edit(
+							BlockEdit {
+								level: level_name,
+								x,
+								y,
+								z,
+								previous_block: block,
+							},
+							limit,
+						);
 				}
 				ClientPacket::PositionOrientation {
 					_player_id_or_held_block: _,
@@ -394,34 +1033,34 @@ async fn handle_stream_inner(
 				} => {
 					let mut data = data.write().await;
 
-					let player = data
-						.players
-						.iter_mut()
-						.find(|p| p.id == *own_id)
-						.expect("missing player");
+					let player = data.player_mut(*own_id).expect("missing player");
 					player.x = x;
 					player.y = y;
 					player.z = z;
 					player.yaw = yaw;
 					player.pitch = pitch;
+					let level_name = player.current_level.clone();
 
-					data.spread_packet(ServerPacket::SetPositionOrientation {
-						player_id: *own_id,
-						x,
-						y,
-						z,
-						yaw,
-						pitch,
-					});
+					data.spread_packet(
+						&level_name,
+						ServerPacket::SetPositionOrientation {
+							player_id: *own_id,
+							x,
+							y,
+							z,
+							yaw,
+							pitch,
+						},
+					);
 				}
 				ClientPacket::Message { player_id, message } => {
 					let mut data = data.write().await;
 
-					let player = data
-						.players
-						.iter()
-						.find(|p| p.id == *own_id)
-						.expect("missing player");
+					let player = data.player(*own_id).expect("missing player");
+					let locale = player.locale.clone();
+					let username = player.username.clone();
+					let level_name = player.current_level.clone();
+					let permissions = player.permissions;
 					let message = if player.extensions.contains(ExtBitmask::LongerMessages) {
 						incoming_message.push(message);
 						if player_id == 0 {
@@ -434,45 +1073,85 @@ async fn handle_stream_inner(
 					};
 
 					if let Some(message) = message.strip_prefix(Command::PREFIX) {
-						match Command::parse(message) {
+						data.audit.log(AuditEvent::Command {
+							username: &username,
+							command: message,
+						});
+						let id = *own_id;
+						if data.run_hook(|hook, data| hook.on_command(data, id, message)) {
+							continue;
+						}
+						match Command::parse(message, &data.config.aliases) {
 							Ok(cmd) => {
 								for message in cmd.process(&mut data, *own_id) {
 									msg!(message);
 								}
 							}
 							Err(msg) => {
-								msg!(format!("&c{msg}"));
+								msg!(format!("&c{}", msg.translate(&locale)));
 							}
 						}
 					} else {
-						println!("{message}");
-						let mut messages = Vec::new();
-						let mut message = format!(
-							"&f<{}> {message}",
-							data.players
-								.iter()
-								.find(|p| p.id == *own_id)
-								.expect("should never fail")
-								.username
-						);
-						while message.len() > STRING_LENGTH {
-							// TODO: split on whitespace if possible
-							let new_message = message.split_off(STRING_LENGTH);
-							// TODO: this will overwrite color codes and it shouldn't
-							messages.push(ServerPacket::Message { player_id, message });
-							message = format!("&f{new_message}");
+						let message = if data.config.strip_chat_colors
+							&& permissions < PlayerType::Operator
+						{
+							strip_color_codes(&message)
+						} else {
+							sanitize_color_codes(&message)
+						};
+						data.audit.log(AuditEvent::Chat {
+							username: &username,
+							message: &message,
+						});
+						let id = *own_id;
+						if data.run_hook(|hook, data| hook.on_chat(data, id, &message)) {
+							continue;
 						}
-						messages.push(ServerPacket::Message { player_id, message });
-						println!("{messages:#?}");
-						data.spread_packets(&messages);
+						println!("{message}");
+						let messages: Vec<_> = split_message(&format!("&f<{username}> {message}"))
+							.into_iter()
+							.map(|message| ServerPacket::Message { player_id, message })
+							.collect();
+						data.spread_packets(&level_name, &messages);
 					}
 				}
 
+				ClientPacket::Extended(ExtendedClientPacket::TwoWayPing {
+					direction,
+					data: ping_data,
+				}) => {
+					if direction == 0 {
+						reply_queue.push(ServerPacket::TwoWayPing {
+							direction,
+							data: ping_data,
+						});
+					} else {
+						let mut data = data.write().await;
+						let player = data.player_mut(*own_id).expect("missing player");
+						if player.ping.answer(ping_data)
+							&& player.extensions.contains(ExtBitmask::MessageTypes)
+						{
+							let rtt = player.ping.rtt_ms.unwrap_or_default();
+							let message = tr!(&player.locale, "status.ping", rtt);
+							player.packets_to_send.push(ServerPacket::TypedMessage {
+								message_type: MessageType::BottomRight1,
+								message,
+							});
+						}
+					}
+				}
 				ClientPacket::Extended(_packet) => {
 					// extended packets!
-					return Err(GeneralError::Custom(
-						"Unexpected extension packet in this phase!".to_string(),
-					));
+					let locale = data
+						.read()
+						.await
+						.player(*own_id)
+						.map(|p| p.locale.clone())
+						.unwrap_or_default();
+					return Err(GeneralError::Custom(tr!(
+						&locale,
+						"disconnect.unexpected_extension_packet"
+					)));
 					// match packet {
 					// 	packet => {
 					// 		println!("improper client packet for this phase!: {packet:#?}");
@@ -486,8 +1165,17 @@ async fn handle_stream_inner(
 		}
 
 		let mut data = data.write().await;
-		if let Some(player) = data.players.iter_mut().find(|p| p.id == *own_id) {
+		let data = &mut *data;
+		if let Some(player) = data
+			.players
+			.iter_mut()
+			.find(|p| p.id == *own_id && p.addr == addr)
+		{
 			for mut packet in player.packets_to_send.drain(..) {
+				// pings are timed from when they're actually sent rather than when they were queued
+				if let ServerPacket::TwoWayPing { direction: 1, data } = packet {
+					player.ping.sent(data);
+				}
 				if let Some(id) = packet.get_player_id() {
 					if id == *own_id {
 						if !packet.should_echo() {
@@ -498,56 +1186,158 @@ async fn handle_stream_inner(
 				}
 				reply_queue.push(packet);
 			}
+
+			// blocks the client doesn't know about are shown as their fallbacks
+			for packet in &mut reply_queue {
+				if let ServerPacket::SetBlock { block_type, .. } = packet {
+					*block_type = client_block(
+						*block_type,
+						player.extensions,
+						player.custom_blocks_support_level,
+					);
+				}
+			}
+
+			if player.extensions.contains(ExtBitmask::BulkBlockUpdate) {
+				if let Some(level) = data.levels.get(&player.current_level) {
+					reply_queue = coalesce_block_updates(std::mem::take(&mut reply_queue), level);
+				}
+			}
 		}
 
-		write_packets(stream, reply_queue.drain(..)).await?;
+		write_packets(stream, extended_blocks, full_cp437, reply_queue.drain(..)).await?;
 	}
 }
 
+/// groups runs of consecutive block changes into bulk updates for clients supporting BulkBlockUpdate
+fn coalesce_block_updates(packets: Vec<ServerPacket>, level: &Level) -> Vec<ServerPacket> {
+	let mut coalesced = Vec::with_capacity(packets.len());
+	let mut run: Vec<(i32, u16)> = Vec::new();
+
+	let flush_run = |run: &mut Vec<(i32, u16)>, coalesced: &mut Vec<ServerPacket>| {
+		for chunk in run.chunks(BULK_BLOCK_UPDATE_COUNT) {
+			if let [(index, block)] = chunk {
+				let (x, y, z) = level.coordinates(*index as usize);
+				coalesced.push(ServerPacket::SetBlock {
+					x: x as i16,
+					y: y as i16,
+					z: z as i16,
+					block_type: *block,
+				});
+			} else {
+				coalesced.push(ServerPacket::BulkBlockUpdate {
+					indices: chunk.iter().map(|(index, _)| *index).collect(),
+					blocks: chunk.iter().map(|(_, block)| *block).collect(),
+				});
+			}
+		}
+		run.clear();
+	};
+
+	for packet in packets {
+		if let ServerPacket::SetBlock {
+			x,
+			y,
+			z,
+			block_type,
+		} = packet
+		{
+			let index = level.index(x as usize, y as usize, z as usize);
+			run.push((index as i32, block_type));
+		} else {
+			flush_run(&mut run, &mut coalesced);
+			coalesced.push(packet);
+		}
+	}
+	flush_run(&mut run, &mut coalesced);
+
+	coalesced
+}
+
+/// gets the packet which spawns the given player for others
+pub(crate) fn player_spawn_packet(player: &Player) -> ServerPacket {
+	ServerPacket::SpawnPlayer {
+		player_id: player.id,
+		player_name: player.username.clone(),
+		x: player.x,
+		y: player.y,
+		z: player.z,
+		yaw: player.yaw,
+		pitch: player.pitch,
+	}
+}
+
+/// gets the packet which shows the given player's model to a viewer, if the viewer needs one
+pub(crate) fn player_model_packet(
+	player: &Player,
+	viewer_extensions: ExtBitmask,
+) -> Option<ServerPacket> {
+	(viewer_extensions.contains(ExtBitmask::ChangeModel) && player.model != DEFAULT_MODEL).then(
+		|| ServerPacket::ChangeModel {
+			entity_id: player.id,
+			model: player.model.clone(),
+		},
+	)
+}
+
+/// builds the level packets on a blocking thread, so compressing large levels doesn't stall the server
+pub(crate) async fn build_level_packets_blocking(
+	level: Level,
+	extensions: ExtBitmask,
+	custom_blocks_support_level: u8,
+	compression: Compression,
+) -> Result<Vec<ServerPacket>, GeneralError> {
+	tokio::task::spawn_blocking(move || {
+		build_level_packets(&level, extensions, custom_blocks_support_level, compression)
+	})
+	.await?
+}
+
 /// helper to put together packets that need to be sent to send full level data for the given level
-fn build_level_packets(
+///
+/// the compression only applies to clients without FastMap, which are always sent quickly compressed data
+pub(crate) fn build_level_packets(
 	level: &Level,
 	extensions: ExtBitmask,
 	custom_blocks_support_level: u8,
+	compression: Compression,
 ) -> Result<Vec<ServerPacket>, GeneralError> {
-	let mut packets: Vec<ServerPacket> = vec![ServerPacket::LevelInitialize {}];
-
-	let custom_blocks =
-		extensions.contains(ExtBitmask::CustomBlocks) && custom_blocks_support_level >= 1;
-
 	let volume = level.x_size * level.y_size * level.z_size;
-	let mut data = Vec::with_capacity(volume + 4);
-	data.extend_from_slice(&(volume as i32).to_be_bytes());
-	data.extend(level.blocks.iter().copied().map(|b| {
-		if custom_blocks || b <= 49 {
-			b
-		} else {
-			BLOCK_INFO
-				.get(&b)
-				.expect("missing block")
-				.fallback
-				.unwrap_or_default()
-		}
-	}));
-
-	let mut e = GzEncoder::new(Vec::new(), Compression::best());
-	e.write_all(&data)?;
-	let data = e.finish()?;
-	let data_len = data.len();
-	let mut total_bytes = 0;
-
-	for chunk in data.chunks(ARRAY_LENGTH) {
-		let chunk_len = chunk.len();
-		let percent_complete = (total_bytes * 100 / data_len) as u8;
-		packets.push(ServerPacket::LevelDataChunk {
-			chunk_length: chunk_len as i16,
-			chunk_data: chunk.to_vec(),
-			percent_complete,
-		});
+	let fast_map = extensions.contains(ExtBitmask::FastMap);
+	let blocks: Vec<u16> = level
+		.blocks
+		.iter()
+		.map(|b| client_block(*b, extensions, custom_blocks_support_level))
+		.collect();
 
-		total_bytes += chunk_len;
+	let mut planes = vec![blocks.iter().map(|b| *b as u8).collect::<Vec<_>>()];
+	// the high bytes of blocks are sent directly after the low bytes, only if they're needed
+	if blocks.iter().any(|b| *b > MAX_BYTE_BLOCK_ID) {
+		planes.push(blocks.iter().map(|b| (*b >> 8) as u8).collect());
 	}
 
+	// FastMap clients get the volume up front and raw deflate data instead of a length-prefixed gzip stream
+	let mut packets = if fast_map {
+		let mut e = DeflateEncoder::new(LevelChunkWriter::default(), Compression::fast());
+		for plane in &planes {
+			e.write_all(plane)?;
+		}
+		e.finish()?.finish()
+	} else {
+		let mut e = GzEncoder::new(LevelChunkWriter::default(), compression);
+		e.write_all(&(volume as i32).to_be_bytes())?;
+		for plane in &planes {
+			e.write_all(plane)?;
+		}
+		e.finish()?.finish()
+	};
+	packets.insert(
+		0,
+		ServerPacket::LevelInitialize {
+			volume: fast_map.then_some(volume as i32),
+		},
+	);
+
 	packets.push(ServerPacket::LevelFinalize {
 		x_size: level.x_size as i16,
 		y_size: level.y_size as i16,
@@ -556,3 +1346,419 @@ fn build_level_packets(
 
 	Ok(packets)
 }
+
+/// writer which splits compressed level data into level data chunks as the encoder produces it
+#[derive(Debug, Default)]
+struct LevelChunkWriter {
+	packets: Vec<ServerPacket>,
+	buffer: Vec<u8>,
+	/// the total length of the compressed data
+	data_len: usize,
+}
+
+impl LevelChunkWriter {
+	/// queues a level data chunk with the given data, its progress is filled in once all data is known
+	fn push_chunk(&mut self, chunk: Vec<u8>) {
+		self.packets.push(ServerPacket::LevelDataChunk {
+			chunk_length: chunk.len() as i16,
+			chunk_data: chunk,
+			percent_complete: 0,
+		});
+	}
+
+	/// gets the level data chunks, including any remaining partial chunk
+	fn finish(mut self) -> Vec<ServerPacket> {
+		if !self.buffer.is_empty() {
+			let chunk = std::mem::take(&mut self.buffer);
+			self.push_chunk(chunk);
+		}
+
+		for (i, packet) in self.packets.iter_mut().enumerate() {
+			if let ServerPacket::LevelDataChunk {
+				percent_complete, ..
+			} = packet
+			{
+				*percent_complete = (i * ARRAY_LENGTH * 100 / self.data_len) as u8;
+			}
+		}
+		self.packets
+	}
+}
+
+impl Write for LevelChunkWriter {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		self.buffer.extend_from_slice(buf);
+		self.data_len += buf.len();
+		while self.buffer.len() >= ARRAY_LENGTH {
+			let chunk = self.buffer.drain(..ARRAY_LENGTH).collect();
+			self.push_chunk(chunk);
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+	use flate2::Compression;
+	use tokio::sync::RwLock;
+
+	use crate::{
+		error::GeneralError,
+		level::{
+			block::{BLOCK_INFO, MAX_CLASSIC_BLOCK_ID},
+			generation::{FlatPreset, LevelGeneration},
+			rules::LevelRules,
+			Level,
+		},
+		packet::{server::ServerPacket, ExtBitmask, F16_UNITS},
+		player::PlayerType,
+		server::{
+			config::{ServerConfig, ServerProtectionMode},
+			password,
+			testing::{test_addr, test_config, test_data, test_data_with_config, TestClient},
+			tick,
+		},
+	};
+
+	use super::{
+		build_level_packets, handle_stream_inner, remove_player, set_player_inventory,
+		set_player_rank_defaults, PACKET_WAIT_TIMEOUT,
+	};
+
+	#[tokio::test]
+	async fn join_place_block_and_chat() {
+		let data = Arc::new(RwLock::new(test_data()));
+
+		let mut alice = TestClient::connect(&data, test_addr(1));
+		alice.identify("alice", "").await;
+		let ids: Vec<_> = alice.packets().await.into_iter().map(|p| p.id).collect();
+		assert_eq!(ids.first(), Some(&0x00));
+		let init = ids
+			.iter()
+			.position(|id| *id == 0x02)
+			.expect("level should be initialized");
+		let finalize = ids
+			.iter()
+			.position(|id| *id == 0x04)
+			.expect("level should be finalized");
+		assert!(ids[init + 1..finalize].iter().all(|id| *id == 0x03));
+		assert!(finalize > init + 1);
+
+		let mut bob = TestClient::join(&data, test_addr(2), "bob").await;
+		bob.packets().await;
+		let packets = alice.packets().await;
+		assert!(packets
+			.iter()
+			.any(|p| p.id == 0x07 && p.string_at(1) == "bob"));
+		assert!(packets
+			.iter()
+			.any(|p| p.id == 0x0d && p.string_at(1).contains("bob has joined")));
+
+		alice.set_block(1, 15, 1, true, 1).await;
+		assert!(bob.packets().await.is_empty());
+		tick(&mut *data.write().await, 1);
+		for client in [&mut alice, &mut bob] {
+			let packets = client.packets().await;
+			let set_block = packets
+				.iter()
+				.find(|p| p.id == 0x06)
+				.expect("block change should be sent");
+			assert_eq!(set_block.body, [0, 1, 0, 15, 0, 1, 1]);
+		}
+
+		bob.chat("hello").await;
+		assert_eq!(alice.messages().await, ["&f<bob> hello"]);
+		assert_eq!(bob.messages().await, ["&f<bob> hello"]);
+	}
+
+	#[test]
+	fn rank_defaults_restrict_normal_players() {
+		let config = ServerConfig::default();
+		let rules = LevelRules::default();
+		let extensions = ExtBitmask::HackControl | ExtBitmask::ClickDistance;
+		let hacks = |perms| {
+			let mut packets = Vec::new();
+			set_player_rank_defaults(&config, &rules, perms, extensions, &mut packets);
+			let [ServerPacket::HackControl {
+				flying,
+				no_clip,
+				speeding,
+				spawn_control,
+				third_person_view,
+				..
+			}, ServerPacket::SetClickDistance { distance }] = packets[..]
+			else {
+				panic!("expected hack control and click distance, got {packets:?}");
+			};
+			(
+				[flying, no_clip, speeding, spawn_control, third_person_view],
+				distance,
+			)
+		};
+		let reach = (5.0 * F16_UNITS) as i16;
+
+		assert_eq!(
+			hacks(PlayerType::Normal),
+			([false, false, false, true, true], reach)
+		);
+		assert_eq!(hacks(PlayerType::Operator), ([true; 5], reach));
+	}
+
+	#[test]
+	fn inventory_offers_fallbacks_without_custom_blocks() {
+		let inventory = |extensions, custom_blocks_support_level, order: Option<&[String]>| {
+			let mut packets = Vec::new();
+			set_player_inventory(
+				PlayerType::Normal,
+				extensions,
+				custom_blocks_support_level,
+				order,
+				&mut packets,
+			);
+			let mut positions = BTreeMap::new();
+			for packet in packets {
+				let ServerPacket::SetInventoryOrder { block, order } = packet else {
+					panic!("expected inventory order, got {packet:?}");
+				};
+				assert!(
+					positions.insert(block, order).is_none(),
+					"{block} offered twice"
+				);
+			}
+			positions
+		};
+		let (custom_block, fallback) = BLOCK_INFO
+			.iter()
+			.find_map(|(id, info)| {
+				(*id > MAX_CLASSIC_BLOCK_ID)
+					.then_some(info.fallback)
+					.flatten()
+					.filter(|f| *f != 0)
+					.map(|f| (*id, f))
+			})
+			.expect("a custom block should have a fallback");
+
+		let positions = inventory(ExtBitmask::none(), 0, None);
+		assert!(positions.keys().all(|id| *id <= MAX_CLASSIC_BLOCK_ID));
+		assert_ne!(positions[&fallback], 0);
+		assert_eq!(
+			inventory(ExtBitmask::CustomBlocks, 1, None)[&custom_block],
+			custom_block
+		);
+
+		// a custom block listed in the level's order puts its fallback there instead
+		let order = [BLOCK_INFO[&custom_block].str_id.to_string()];
+		let positions = inventory(ExtBitmask::none(), 0, Some(&order));
+		assert_eq!(positions[&fallback], 1);
+		assert!(!positions.contains_key(&custom_block));
+	}
+
+	#[tokio::test]
+	async fn failed_logins_are_throttled_by_address() {
+		let mut config = test_config();
+		config.protection_mode = ServerProtectionMode::Password("secret".to_string());
+		config.max_login_attempts = 3;
+		let data = Arc::new(RwLock::new(test_data_with_config(config)));
+		let attempt = async |n, key| {
+			let mut client = TestClient::connect(&data, test_addr(n));
+			client.identify("alice", key).await;
+			client
+		};
+		let disconnect_reason = async |mut client: TestClient| {
+			client
+				.packets()
+				.await
+				.iter()
+				.find(|p| p.id == 0x0e)
+				.map(|p| p.string_at(0))
+		};
+
+		for _ in 0..3 {
+			assert_eq!(
+				disconnect_reason(attempt(1, "wrong").await)
+					.await
+					.as_deref(),
+				Some("Incorrect password!")
+			);
+		}
+		assert_eq!(
+			disconnect_reason(attempt(1, "secret").await)
+				.await
+				.as_deref(),
+			Some("Too many failed login attempts! Try again later.")
+		);
+
+		// other addresses can still log in, though the password is hashed once it's been used, which takes a while
+		let mut client = attempt(2, "secret").await;
+		let joined = tokio::time::timeout(Duration::from_secs(60), async {
+			loop {
+				match client.next_packet().await.map(|p| p.id) {
+					Some(0x04) => break true,
+					Some(0x0e) => break false,
+					_ => {}
+				}
+			}
+		})
+		.await;
+		assert_eq!(joined, Ok(true));
+	}
+	#[tokio::test]
+	async fn plaintext_passwords_are_hashed_after_logging_in() {
+		let mut config = test_config();
+		config.protection_mode = ServerProtectionMode::PasswordsByUser(BTreeMap::from([(
+			"alice".to_string(),
+			"secret".to_string(),
+		)]));
+		let data = Arc::new(RwLock::new(test_data_with_config(config)));
+		data.write().await.config_needs_saving = false;
+
+		let mut alice = TestClient::connect(&data, test_addr(1));
+		alice.identify("alice", "secret").await;
+		// hashing is slow without optimizations
+		let joined = tokio::time::timeout(Duration::from_secs(60), async {
+			loop {
+				match alice.next_packet().await.map(|p| p.id) {
+					Some(0x04) => break true,
+					Some(0x0e) => break false,
+					_ => {}
+				}
+			}
+		})
+		.await;
+		assert_eq!(joined, Ok(true));
+
+		let data = data.read().await;
+		let stored = data
+			.config
+			.protection_mode
+			.stored_password("alice")
+			.unwrap();
+		assert!(!password::is_plaintext(stored));
+		assert!(password::verify_password(stored, "secret"));
+		assert!(data.config_needs_saving);
+	}
+
+	#[tokio::test]
+	async fn kicks_despawn_once() {
+		let data = Arc::new(RwLock::new(test_data()));
+		let mut bob = TestClient::join(&data, test_addr(2), "bob").await;
+
+		let (mut alice, mut stream) = TestClient::pair();
+		let connection = tokio::spawn({
+			let data = data.clone();
+			async move {
+				let mut own_id = -1;
+				let result =
+					handle_stream_inner(&mut stream, test_addr(1), data, &mut own_id).await;
+				(result, own_id)
+			}
+		});
+		alice.identify("alice", "").await;
+		alice.packets().await;
+		bob.packets().await;
+		let alice_id = data
+			.read()
+			.await
+			.players
+			.iter()
+			.find(|p| p.username == "alice")
+			.unwrap()
+			.id;
+
+		data.write()
+			.await
+			.player_mut(alice_id)
+			.unwrap()
+			.should_be_kicked = Some("bye".to_string());
+		// kicks are noticed once the connection's next packet is handled
+		alice.chat("hi").await;
+		let (result, own_id) = connection.await.unwrap();
+		assert!(matches!(result, Err(GeneralError::Custom(reason)) if reason == "bye"));
+		assert_eq!(own_id, -1);
+
+		// someone joining before the connection is cleaned up is given the kicked player's id
+		let _carol = TestClient::join(&data, test_addr(3), "carol").await;
+		remove_player(&mut *data.write().await, own_id);
+		assert_eq!(
+			data.read()
+				.await
+				.player(alice_id)
+				.map(|p| p.username.as_str()),
+			Some("carol")
+		);
+
+		bob.chat("hello").await;
+		let despawns = bob
+			.packets()
+			.await
+			.into_iter()
+			.filter(|p| p.id == 0x0c && p.body == [alice_id as u8])
+			.count();
+		assert_eq!(despawns, 1);
+	}
+
+	#[tokio::test]
+	async fn refused_changes_are_corrected_immediately() {
+		let data = Arc::new(RwLock::new(test_data()));
+		for level in data.write().await.levels.values_mut() {
+			level.rules.max_build_height = 4;
+		}
+		let mut alice = TestClient::join(&data, test_addr(1), "alice").await;
+		alice.packets().await;
+
+		alice.set_block(1, 15, 1, true, 1).await;
+		// the correction shouldn't wait for the connection to go idle
+		let correction = tokio::time::timeout(PACKET_WAIT_TIMEOUT / 2, async {
+			loop {
+				let packet = alice
+					.next_packet()
+					.await
+					.expect("correction should be sent");
+				if packet.id == 0x06 {
+					break packet;
+				}
+			}
+		})
+		.await
+		.expect("correction should be sent straight away");
+		assert_eq!(correction.body, [0, 1, 0, 15, 0, 1, 0]);
+		assert!(alice.packets().await.iter().all(|p| p.id != 0x06));
+	}
+
+	#[test]
+	fn level_compression_trades_time_for_size() {
+		let mut level = Level::new(256, 64, 256);
+		LevelGeneration::Flat(FlatPreset::StoneAndGrass).generate_seeded(
+			&mut level,
+			&Default::default(),
+			1,
+		);
+		let sent_size = |compression: u32| {
+			let started = std::time::Instant::now();
+			let packets =
+				build_level_packets(&level, ExtBitmask::none(), 0, Compression::new(compression))
+					.unwrap();
+			let size: usize = packets
+				.iter()
+				.map(|packet| match packet {
+					ServerPacket::LevelDataChunk { chunk_length, .. } => *chunk_length as usize,
+					_ => 0,
+				})
+				.sum();
+			println!(
+				"compression {compression}: {size} bytes in {:?}",
+				started.elapsed()
+			);
+			size
+		};
+		let fastest = sent_size(1);
+		let smallest = sent_size(9);
+		assert!(smallest < fastest);
+	}
+}
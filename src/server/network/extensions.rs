@@ -1,5 +1,3 @@
-use tokio::net::TcpStream;
-
 use crate::{
 	error::GeneralError,
 	level::block::CUSTOM_BLOCKS_SUPPORT_LEVEL,
@@ -9,15 +7,17 @@ use crate::{
 	},
 };
 
-use super::{next_packet, write_packets};
+use super::{next_packet, write_packets, ClientStream};
 
 pub async fn get_supported_extensions(
-	stream: &mut TcpStream,
+	stream: &mut impl ClientStream,
 ) -> Result<(ExtBitmask, u8), GeneralError> {
 	let extensions = ExtBitmask::all().all_contained_info();
 
 	write_packets(
 		stream,
+		false,
+		false,
 		Some(ServerPacket::ExtInfo {})
 			.into_iter()
 			.chain(extensions.iter().map(|info| ServerPacket::ExtEntry {
@@ -30,7 +30,7 @@ pub async fn get_supported_extensions(
 	let client_extensions = if let Some(ClientPacket::Extended(ExtendedClientPacket::ExtInfo {
 		app_name,
 		extension_count,
-	})) = next_packet(stream).await?
+	})) = next_packet(stream, false).await?
 	{
 		println!("client name: {app_name}");
 		let mut client_extensions = Vec::with_capacity(extension_count as usize);
@@ -38,7 +38,7 @@ pub async fn get_supported_extensions(
 			if let Some(ClientPacket::Extended(ExtendedClientPacket::ExtEntry {
 				ext_name,
 				version,
-			})) = next_packet(stream).await?
+			})) = next_packet(stream, false).await?
 			{
 				client_extensions.push(ExtInfo::new(ext_name, version, ExtBitmask::none()));
 			} else {
@@ -72,12 +72,14 @@ pub async fn get_supported_extensions(
 	let custom_blocks_support_level = if final_bitmask.contains(ExtBitmask::CustomBlocks) {
 		write_packets(
 			stream,
+			false,
+			false,
 			Some(ServerPacket::CustomBlockSupportLevel).into_iter(),
 		)
 		.await?;
 		if let Some(ClientPacket::Extended(ExtendedClientPacket::CustomBlockSupportLevel {
 			support_level,
-		})) = next_packet(stream).await?
+		})) = next_packet(stream, false).await?
 		{
 			support_level.min(CUSTOM_BLOCKS_SUPPORT_LEVEL)
 		} else {
@@ -0,0 +1,94 @@
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+	net::TcpStream,
+};
+use tokio_tungstenite::tungstenite::{
+	handshake::server::{ErrorResponse, Request, Response},
+	http::HeaderValue,
+	Message,
+};
+
+use crate::error::GeneralError;
+
+/// the subprotocol requested by ClassiCube's web client, which browsers require the server to agree to
+const CLASSICUBE_PROTOCOL: &str = "ClassiCube";
+/// the size of the buffer between a websocket and the connection reading from it
+const BRIDGE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// checks whether a new connection is starting a websocket handshake instead of sending classic packets
+///
+/// classic clients start with packet id 0, while websocket handshakes start with an http `GET`
+pub async fn is_websocket(stream: &TcpStream) -> bool {
+	let mut first = [0];
+	matches!(stream.peek(&mut first).await, Ok(1) if first[0] == b'G')
+}
+
+/// accepts a websocket connection, returning a stream carrying the classic packets inside its binary messages
+pub async fn accept(stream: TcpStream) -> Result<DuplexStream, GeneralError> {
+	let websocket = tokio_tungstenite::accept_hdr_async(stream, agree_to_protocol)
+		.await
+		.map_err(std::io::Error::other)?;
+
+	let (connection, bridge) = tokio::io::duplex(BRIDGE_BUFFER_SIZE);
+	tokio::spawn(async move {
+		let (mut ws_sink, mut ws_stream) = websocket.split();
+		let (mut bridge_read, mut bridge_write) = tokio::io::split(bridge);
+		let incoming = async {
+			while let Some(message) = ws_stream.next().await {
+				match message.map_err(std::io::Error::other)? {
+					Message::Binary(data) => bridge_write.write_all(&data).await?,
+					Message::Close(_) => break,
+					_ => {}
+				}
+			}
+			Ok::<_, GeneralError>(())
+		};
+		let outgoing = async {
+			let mut buf = vec![0; BRIDGE_BUFFER_SIZE];
+			loop {
+				let n = bridge_read.read(&mut buf).await?;
+				if n == 0 {
+					break;
+				}
+				ws_sink
+					.send(Message::Binary(buf[..n].to_vec()))
+					.await
+					.map_err(std::io::Error::other)?;
+			}
+			ws_sink.close().await.map_err(std::io::Error::other)?;
+			Ok::<_, GeneralError>(())
+		};
+		// either side finishing means the connection is over, which drops the other
+		let result = tokio::select! {
+			r = incoming => r,
+			r = outgoing => r,
+		};
+		if let Err(e) = result {
+			eprintln!("websocket connection closed with an error: {e}");
+		}
+	});
+	Ok(connection)
+}
+
+/// agrees to the ClassiCube subprotocol during the handshake if the client asks for it
+// the error type is decided by tungstenite
+#[allow(clippy::result_large_err)]
+fn agree_to_protocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+	let requested = request
+		.headers()
+		.get("Sec-WebSocket-Protocol")
+		.and_then(|protocols| protocols.to_str().ok())
+		.is_some_and(|protocols| {
+			protocols
+				.split(',')
+				.any(|protocol| protocol.trim() == CLASSICUBE_PROTOCOL)
+		});
+	if requested {
+		response.headers_mut().insert(
+			"Sec-WebSocket-Protocol",
+			HeaderValue::from_static(CLASSICUBE_PROTOCOL),
+		);
+	}
+	Ok(response)
+}
@@ -0,0 +1,45 @@
+use argon2::{
+	password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+	Argon2,
+};
+
+use crate::error::GeneralError;
+
+/// hashes a password for storage
+pub fn hash_password(password: &str) -> String {
+	let salt = SaltString::generate(&mut OsRng);
+	Argon2::default()
+		.hash_password(password.as_bytes(), &salt)
+		.expect("hashing with default parameters should never fail")
+		.to_string()
+}
+
+/// gets whether a stored password is a legacy plaintext password which should be upgraded
+pub fn is_plaintext(stored: &str) -> bool {
+	PasswordHash::new(stored).is_err()
+}
+
+/// checks an attempted password against a stored password, which may be a legacy plaintext password
+pub fn verify_password(stored: &str, attempt: &str) -> bool {
+	match PasswordHash::new(stored) {
+		Ok(hash) => Argon2::default()
+			.verify_password(attempt.as_bytes(), &hash)
+			.is_ok(),
+		Err(_) => stored == attempt,
+	}
+}
+
+/// checks an attempted password on a blocking thread, since hashed passwords take a while to check
+///
+/// if the stored password is a correct legacy plaintext password, its hashed replacement is returned too
+pub async fn verify_password_blocking(
+	stored: String,
+	attempt: String,
+) -> Result<(bool, Option<String>), GeneralError> {
+	Ok(tokio::task::spawn_blocking(move || {
+		let correct = verify_password(&stored, &attempt);
+		let upgraded = (correct && is_plaintext(&stored)).then(|| hash_password(&attempt));
+		(correct, upgraded)
+	})
+	.await?)
+}
@@ -0,0 +1,265 @@
+//! helpers for tests which need server data or clients connected over in-memory streams
+
+use std::{
+	collections::BTreeMap,
+	net::{Ipv4Addr, SocketAddr},
+	path::PathBuf,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+	sync::RwLock,
+};
+
+use crate::{
+	level::{
+		generation::{FlatPreset, GenerationFeatures, LevelGeneration},
+		Level,
+	},
+	packet::ExtBitmask,
+	player::{Player, PlayerType, DEFAULT_MODEL},
+	server::{
+		config::{AuditLogConfig, ConfigCoordinates, ServerConfig},
+		network::handle_stream,
+		ServerData,
+	},
+};
+
+/// the size of each side of the level tests are given
+pub const TEST_LEVEL_SIZE: usize = 16;
+/// how long a test client waits for a packet before deciding none are coming
+const PACKET_WAIT: Duration = Duration::from_millis(600);
+
+/// gets a config for tests, with a small level and nothing written to disk
+pub fn test_config() -> ServerConfig {
+	ServerConfig {
+		level_size: ConfigCoordinates {
+			x: TEST_LEVEL_SIZE,
+			y: TEST_LEVEL_SIZE,
+			z: TEST_LEVEL_SIZE,
+		},
+		audit_log: AuditLogConfig {
+			path: None,
+			..Default::default()
+		},
+		..Default::default()
+	}
+}
+
+/// creates server data with the given config and a flat main level
+pub fn test_data_with_config(config: ServerConfig) -> ServerData {
+	let mut level = Level::new(TEST_LEVEL_SIZE, TEST_LEVEL_SIZE, TEST_LEVEL_SIZE);
+	LevelGeneration::Flat(FlatPreset::StoneAndGrass).generate_seeded(
+		&mut level,
+		&GenerationFeatures {
+			ores: Vec::new(),
+			tree_count: 0,
+		},
+		0,
+	);
+	let levels = BTreeMap::from([(config.level_name.clone(), level)]);
+	ServerData::new(config, levels, None)
+}
+
+/// creates server data with the test config and a flat main level
+pub fn test_data() -> ServerData {
+	test_data_with_config(test_config())
+}
+
+/// adds a player to the main level without a connection, returning their id
+pub fn add_player(data: &mut ServerData, username: &str, permissions: PlayerType) -> i8 {
+	let id = data
+		.free_player_ids
+		.pop()
+		.unwrap_or(data.player_count() as i8);
+	data.players.push(Player {
+		id,
+		username: username.to_string(),
+		savable_data: Default::default(),
+		permissions,
+		locale: data.config.default_locale.clone(),
+		current_level: data.config.level_name.clone(),
+		addr: test_addr(id as u8),
+		extensions: ExtBitmask::none(),
+		custom_blocks_support_level: 0,
+		packets_to_send: Vec::new(),
+		should_be_kicked: None,
+		pending_selection: None,
+		edit_history: Default::default(),
+		ping: Default::default(),
+		model: DEFAULT_MODEL.to_string(),
+		block_rate: Default::default(),
+		previous_position: None,
+		last_activity: Instant::now(),
+	});
+	id
+}
+
+/// waits until commands have started work on blocking threads, then until all of it is finished
+pub async fn finish_pending_commands(data: &RwLock<ServerData>) {
+	while data.read().await.pending_commands.is_empty() {
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+	loop {
+		let mut data = data.write().await;
+		data.finish_pending_commands().await;
+		if data.pending_commands.is_empty() {
+			break;
+		}
+		drop(data);
+		tokio::time::sleep(Duration::from_millis(10)).await;
+	}
+}
+
+/// creates an empty directory for a test to write files in
+pub fn test_dir(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("classics-{name}-{}", std::process::id()));
+	// directories left over from an earlier run are replaced
+	let _ = std::fs::remove_dir_all(&path);
+	std::fs::create_dir_all(&path).expect("test directory should be created");
+	path
+}
+
+/// gets a distinct address for a test client
+pub fn test_addr(n: u8) -> SocketAddr {
+	SocketAddr::from((Ipv4Addr::new(10, 0, 0, n), 25565))
+}
+
+/// pads a string to the length strings are sent with
+fn padded(s: &str) -> [u8; 64] {
+	let mut bytes = [b' '; 64];
+	bytes[..s.len()].copy_from_slice(s.as_bytes());
+	bytes
+}
+
+/// a packet sent by the server, split into its id and the bytes after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawPacket {
+	pub id: u8,
+	pub body: Vec<u8>,
+}
+
+impl RawPacket {
+	/// gets the string at the given offset in the packet
+	pub fn string_at(&self, offset: usize) -> String {
+		String::from_utf8_lossy(&self.body[offset..offset + 64])
+			.trim_end()
+			.to_string()
+	}
+}
+
+/// a client connected to the server over an in-memory stream, which doesn't support any extensions
+pub struct TestClient {
+	stream: DuplexStream,
+}
+
+impl TestClient {
+	/// connects a new client from the given address, without identifying
+	pub fn connect(data: &Arc<RwLock<ServerData>>, addr: SocketAddr) -> Self {
+		let (client, server_stream) = Self::pair();
+		tokio::spawn(handle_stream(server_stream, addr, data.clone()));
+		client
+	}
+
+	/// creates a client along with the server's end of its stream, for tests which handle the connection themselves
+	pub fn pair() -> (Self, DuplexStream) {
+		let (stream, server_stream) = tokio::io::duplex(1024 * 1024);
+		(Self { stream }, server_stream)
+	}
+
+	/// connects a new client and logs in with the given username, reading every packet up to and including the level
+	pub async fn join(data: &Arc<RwLock<ServerData>>, addr: SocketAddr, username: &str) -> Self {
+		let mut client = Self::connect(data, addr);
+		client.identify(username, "").await;
+		while client
+			.next_packet()
+			.await
+			.expect("client should be sent the level")
+			.id != 0x04
+		{}
+		client
+	}
+
+	/// sends the identification packet
+	pub async fn identify(&mut self, username: &str, key: &str) {
+		let mut packet = vec![0x00, 0x07];
+		packet.extend(padded(username));
+		packet.extend(padded(key));
+		packet.push(0);
+		self.send(&packet).await;
+	}
+
+	/// tries to change a block, placing it if `place` is true and breaking it otherwise
+	pub async fn set_block(&mut self, x: i16, y: i16, z: i16, place: bool, block: u8) {
+		let mut packet = vec![0x05];
+		for n in [x, y, z] {
+			packet.extend(n.to_be_bytes());
+		}
+		packet.extend([place as u8, block]);
+		self.send(&packet).await;
+	}
+
+	/// sends a chat message or command
+	pub async fn chat(&mut self, message: &str) {
+		let mut packet = vec![0x0d, 0xff];
+		packet.extend(padded(message));
+		self.send(&packet).await;
+	}
+
+	/// sends raw bytes to the server
+	pub async fn send(&mut self, bytes: &[u8]) {
+		self.stream
+			.write_all(bytes)
+			.await
+			.expect("server should be reading");
+	}
+
+	/// reads the next packet from the server, or none if nothing arrives for a while or the connection has closed
+	pub async fn next_packet(&mut self) -> Option<RawPacket> {
+		let id = tokio::time::timeout(PACKET_WAIT, self.stream.read_u8())
+			.await
+			.ok()?
+			.ok()?;
+		let size = match id {
+			0x00 => 130,
+			0x01 | 0x02 => 0,
+			0x03 => 1027,
+			0x04 => 6,
+			0x06 => 7,
+			0x07 => 73,
+			0x08 => 9,
+			0x09 => 6,
+			0x0a => 4,
+			0x0b => 3,
+			0x0c => 1,
+			0x0d => 65,
+			0x0e => 64,
+			0x0f => 1,
+			_ => panic!("unexpected packet id {id:#x}"),
+		};
+		let mut body = vec![0; size];
+		self.stream.read_exact(&mut body).await.ok()?;
+		Some(RawPacket { id, body })
+	}
+
+	/// reads packets until none arrive for a while
+	pub async fn packets(&mut self) -> Vec<RawPacket> {
+		let mut packets = Vec::new();
+		while let Some(packet) = self.next_packet().await {
+			packets.push(packet);
+		}
+		packets
+	}
+
+	/// reads packets until none arrive for a while, returning the text of every chat message
+	pub async fn messages(&mut self) -> Vec<String> {
+		self.packets()
+			.await
+			.into_iter()
+			.filter(|p| p.id == 0x0d)
+			.map(|p| p.string_at(1))
+			.collect()
+	}
+}
@@ -1,4 +1,6 @@
-use crate::level::Level;
+use std::time::{Duration, SystemTime};
+
+use crate::{level::Level, packet::STRING_LENGTH};
 
 const NEIGHBORS: &[(isize, isize, isize)] = &[
 	(0, 1, 0),
@@ -9,6 +11,202 @@ const NEIGHBORS: &[(isize, isize, isize)] = &[
 	(0, 0, 1),
 ];
 
+/// gets the byte index where a classic string of the given text ends, since each character is sent as a single CP437 byte
+fn string_length_end(s: &str) -> usize {
+	s.char_indices()
+		.nth(STRING_LENGTH)
+		.map_or(s.len(), |(i, _)| i)
+}
+
+/// truncates a string to fit in a single classic string, without leaving a dangling color code marker at the end
+pub fn truncate_to_string_length(s: &str) -> String {
+	let end = string_length_end(s);
+	let mut truncated = &s[..end];
+	if end < s.len() {
+		truncated = truncated.trim_end_matches('&');
+	}
+	truncated.to_string()
+}
+
+/// splits a message into lines which each fit in a classic string, breaking on whitespace where possible
+///
+/// the color active at the end of each line is carried onto the next one
+pub fn split_message(message: &str) -> Vec<String> {
+	let mut lines = Vec::new();
+	let mut color = None;
+	let mut rest = message;
+	loop {
+		let prefix = color.map(|c| format!("&{c}")).unwrap_or_default();
+		let line = format!("{prefix}{rest}");
+		let end = string_length_end(&line);
+		if end == line.len() {
+			lines.push(line);
+			break;
+		}
+
+		let split = match line[..end].rfind(char::is_whitespace) {
+			Some(i) if i > prefix.len() => i,
+			// words too long for a line have to be cut, but not in the middle of a color code
+			_ if line[..end].ends_with('&') => end - 1,
+			_ => end,
+		};
+		let head = &line[..split];
+		color = last_color_code(head).or(color);
+		lines.push(head.to_string());
+
+		// the rest of the message comes from the original message, which is offset by the prefix
+		rest = rest[split - prefix.len()..].trim_start();
+		if rest.is_empty() {
+			break;
+		}
+	}
+	lines
+}
+
+/// gets the number of single character insertions, deletions, or substitutions needed to turn one string into another
+pub fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, ca) in a.chars().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(ca != *cb);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+	previous[b.len()]
+}
+
+/// gets the last color code used in the given text
+fn last_color_code(text: &str) -> Option<char> {
+	let chars: Vec<char> = text.chars().collect();
+	chars
+		.windows(2)
+		.rev()
+		.find(|w| w[0] == '&' && w[1].is_ascii_hexdigit())
+		.map(|w| w[1])
+}
+
+/// removes any `&` which doesn't start a valid color code, so it can't combine with text after it
+pub fn sanitize_color_codes(text: &str) -> String {
+	let mut chars = text.chars().peekable();
+	let mut sanitized = String::with_capacity(text.len());
+	while let Some(c) = chars.next() {
+		if c != '&' || chars.peek().is_some_and(char::is_ascii_hexdigit) {
+			sanitized.push(c);
+		}
+	}
+	sanitized
+}
+
+/// removes all color codes and stray `&` from the given text
+pub fn strip_color_codes(text: &str) -> String {
+	let mut chars = text.chars().peekable();
+	let mut stripped = String::with_capacity(text.len());
+	while let Some(c) = chars.next() {
+		if c == '&' {
+			chars.next_if(char::is_ascii_hexdigit);
+		} else {
+			stripped.push(c);
+		}
+	}
+	stripped
+}
+
+/// parses a duration such as `30m`, `2h` or `7d`
+pub fn parse_duration(s: &str) -> Option<Duration> {
+	let unit = s.chars().last()?;
+	let amount: u64 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+	let seconds = match unit {
+		'm' => 60,
+		'h' => 60 * 60,
+		'd' => 60 * 60 * 24,
+		_ => return None,
+	};
+	Some(Duration::from_secs(amount.checked_mul(seconds)?))
+}
+
+/// formats a duration in days, hours and minutes, rounding up to the next minute
+pub fn format_duration(duration: Duration) -> String {
+	let minutes = duration.as_secs().div_ceil(60);
+	let (days, hours, minutes) = (minutes / (60 * 24), minutes / 60 % 24, minutes % 60);
+	let mut parts = Vec::new();
+	if days > 0 {
+		parts.push(format!("{days}d"));
+	}
+	if hours > 0 {
+		parts.push(format!("{hours}h"));
+	}
+	if minutes > 0 || parts.is_empty() {
+		parts.push(format!("{minutes}m"));
+	}
+	parts.join(" ")
+}
+
+/// formats a point in time as a UTC date and time, i.e. `2024-05-01 13:45:00`
+pub fn format_timestamp(time: SystemTime) -> String {
+	let seconds = time
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+	// converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let days = days as i64 + 719468;
+	let era = days / 146097;
+	let day_of_era = days - era * 146097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 {
+		shifted_month + 3
+	} else {
+		shifted_month - 9
+	};
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+	format!(
+		"{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+		seconds / 3600,
+		seconds / 60 % 60,
+		seconds % 60
+	)
+}
+
+/// patterns of neighboring blocks which a block's physics updates can spread to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborPattern {
+	/// the block doesn't affect any neighbors
+	None,
+	/// the block's direct neighbors
+	Direct,
+	/// the block's direct neighbors, excluding above the block
+	DirectMinusUp,
+	/// only the block directly below
+	Below,
+}
+
+impl NeighborPattern {
+	/// gets the neighbors matching this pattern which are in the bounds of the level
+	pub fn neighbors(
+		self,
+		level: &Level,
+		x: usize,
+		y: usize,
+		z: usize,
+	) -> Vec<(usize, usize, usize)> {
+		match self {
+			Self::None => Vec::new(),
+			Self::Direct => neighbors(level, x, y, z),
+			Self::DirectMinusUp => neighbors_minus_up(level, x, y, z),
+			Self::Below => get_many_relative_coords(level, x, y, z, [(0, -1, 0)]),
+		}
+	}
+}
+
 /// gets a block's direct neighbors which are in the bounds of the level
 pub fn neighbors(level: &Level, x: usize, y: usize, z: usize) -> Vec<(usize, usize, usize)> {
 	get_many_relative_coords(level, x, y, z, NEIGHBORS.iter().copied())
@@ -24,6 +222,26 @@ pub fn neighbors_minus_up(
 	get_many_relative_coords(level, x, y, z, NEIGHBORS.iter().skip(1).copied())
 }
 
+/// gets all coordinates within the given distance of a block on each axis which are in the bounds of the level
+pub fn cube_around(
+	level: &Level,
+	x: usize,
+	y: usize,
+	z: usize,
+	radius: usize,
+) -> Vec<(usize, usize, usize)> {
+	let radius = radius as isize;
+	get_many_relative_coords(
+		level,
+		x,
+		y,
+		z,
+		(-radius..=radius).flat_map(|ry| {
+			(-radius..=radius).flat_map(move |rz| (-radius..=radius).map(move |rx| (rx, ry, rz)))
+		}),
+	)
+}
+
 /// adds relative coordinates to the given ones, returning `None` if the coordinates would be out of bounds for hte level
 pub fn get_relative_coords(
 	level: &Level,
@@ -60,3 +278,70 @@ where
 		.filter_map(|(rx, ry, rz)| get_relative_coords(level, x, y, z, rx, ry, rz))
 		.collect()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_duration_units() {
+		assert_eq!(parse_duration("10m"), Some(Duration::from_secs(10 * 60)));
+		assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+		assert_eq!(
+			parse_duration("7d"),
+			Some(Duration::from_secs(7 * 60 * 60 * 24))
+		);
+	}
+
+	#[test]
+	fn parse_duration_invalid() {
+		assert_eq!(parse_duration("m"), None);
+		assert_eq!(parse_duration(""), None);
+		assert_eq!(parse_duration("10"), None);
+		assert_eq!(parse_duration("3é"), None);
+		assert_eq!(parse_duration("é"), None);
+	}
+
+	#[test]
+	fn split_message_keeps_words_and_colors() {
+		let message = "&aThe quick brown fox jumps over the lazy dog while &bthe slow gray cat watches it from a window across the street";
+		let lines = split_message(message);
+		assert!(lines.len() > 1);
+		for line in &lines {
+			assert!(
+				line.chars().count() <= STRING_LENGTH,
+				"{line:?} is too long"
+			);
+		}
+		// continuation lines start with the color the previous line ended on
+		for (previous, line) in lines.iter().zip(&lines[1..]) {
+			let color = last_color_code(previous).expect("every line should be colored");
+			assert!(line.starts_with(&format!("&{color}")), "{line:?}");
+		}
+		// the lines hold every word of the message, without splitting any of them
+		let words: Vec<&str> = lines
+			.iter()
+			.enumerate()
+			.flat_map(|(i, line)| {
+				let line = if i == 0 { line.as_str() } else { &line[2..] };
+				line.split_whitespace()
+			})
+			.collect();
+		assert_eq!(words, message.split_whitespace().collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn truncation_drops_dangling_color_markers() {
+		// cutting between the marker and its code would leave the marker dangling
+		let motd = format!("{}&c", "a".repeat(STRING_LENGTH - 1));
+		assert_eq!(motd.len(), STRING_LENGTH + 1);
+		assert_eq!(
+			truncate_to_string_length(&motd),
+			"a".repeat(STRING_LENGTH - 1)
+		);
+
+		// a code which fits is kept whole
+		let motd = format!("{}&c", "a".repeat(STRING_LENGTH - 2));
+		assert_eq!(truncate_to_string_length(&motd), motd);
+	}
+}